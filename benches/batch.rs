@@ -0,0 +1,57 @@
+//! compare running many tiny MOPAC jobs with one `mopac` process per job to
+//! batching them with [Local::batch_size]. set `MOPAC` to the path of the
+//! `mopac` binary if it is not in `/opt/mopac/mopac`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use psqs::{
+    geom::Geom,
+    program::{mopac::Mopac, Procedure, Program, Template},
+    queue::{local::Local, Queue, Submit},
+};
+use symm::molecule;
+
+const JOBS: usize = 64;
+
+pub fn batch(c: &mut Criterion) {
+    let dir = "/tmp/batch_bench";
+    std::fs::create_dir_all(dir).unwrap();
+    let mol = molecule![
+        H 0.0 0.0 0.0,
+        H 0.0 0.0 0.74,
+    ];
+    let names: Vec<_> = (0..JOBS).map(|i| format!("{dir}/job{i:03}")).collect();
+    for name in &names {
+        let mut mop = Mopac::new(
+            name.clone(),
+            Template::from("scfcrt=1.D-21 aux(precision=14) PM6 THREADS=1"),
+            0,
+            Geom::Xyz(mol.atoms.clone()),
+        );
+        mop.write_input(Procedure::SinglePt).unwrap();
+    }
+    let mopac = std::env::var("MOPAC")
+        .unwrap_or_else(|_| "/opt/mopac/mopac".to_owned());
+
+    let mut group = c.benchmark_group("batch");
+    group.sample_size(10);
+    for batch_size in [None, Some(8), Some(JOBS)] {
+        let local = Local {
+            mopac: mopac.clone(),
+            direct: true,
+            batch_size,
+            ..Local::default()
+        };
+        let script = format!("{dir}/main.slurm");
+        <Local as Queue<Mopac>>::write_submit_script(&local, &names, &script)
+            .unwrap();
+        let id = batch_size.map_or("none".to_owned(), |b| b.to_string());
+        group.bench_function(BenchmarkId::from_parameter(id), |b| {
+            b.iter(|| <Local as Submit<Mopac>>::submit(&local, &script))
+        });
+    }
+    group.finish();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, batch);
+criterion_main!(benches);
@@ -41,7 +41,7 @@ fn main() {
     tm.param_dir = Some("/tmp".to_string());
     let mut res = Vec::new();
     for _ in 0..1000 {
-        tm.write_input(Procedure::SinglePt);
+        tm.write_input(Procedure::SinglePt).unwrap();
         res.push(());
     }
 }
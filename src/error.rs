@@ -0,0 +1,86 @@
+use std::{
+    fmt::Display,
+    num::{ParseFloatError, ParseIntError},
+};
+
+use crate::program::ProgramError;
+
+/// The error type for the fallible operations across the crate, like writing
+/// input files and submit scripts or querying the queue status
+#[derive(Debug)]
+pub enum Error {
+    /// an error reading or writing a file
+    Io(std::io::Error),
+
+    /// an error reading the output of a [crate::program::Program]
+    Program(ProgramError),
+
+    /// an error parsing a number or other value
+    Parse(String),
+
+    /// the queue rejected a submission, or the submit command could not be run
+    Submit(String),
+
+    /// the queue status command failed, or its output could not be understood
+    Scheduler(String),
+
+    /// an invalid option, like a malformed directive or a keyword that
+    /// conflicts with the template, prevented writing an input file or submit
+    /// script
+    Input(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "i/o error: {e}"),
+            Error::Program(e) => write!(f, "program error: {e}"),
+            Error::Parse(e) => write!(f, "parse error: {e}"),
+            Error::Submit(e) => write!(f, "submit error: {e}"),
+            Error::Scheduler(e) => write!(f, "scheduler error: {e}"),
+            Error::Input(e) => write!(f, "input error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Program(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ProgramError> for Error {
+    fn from(e: ProgramError) -> Self {
+        Self::Program(e)
+    }
+}
+
+impl From<ParseFloatError> for Error {
+    fn from(e: ParseFloatError) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(e: ParseIntError) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
+
+impl Error {
+    /// wrap `e`, from an operation on `path`, in an [Error::Io] including
+    /// `path` in its message
+    pub(crate) fn io(path: &str, e: std::io::Error) -> Self {
+        Self::Io(std::io::Error::new(e.kind(), format!("{path}: {e}")))
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    process::Command,
+    sync::{Arc, LazyLock, Mutex, RwLock},
+    time::SystemTime,
+};
+
+#[cfg(feature = "ssh")]
+pub mod ssh;
+
+/// The file operations used for writing input files, reading output files, and
+/// cleaning up after jobs, along with running the queue status commands.
+/// [StdFs] is used by default, but another implementation like [MemFs] can be
+/// installed on the current thread with [set] or on every thread with
+/// [set_global]
+pub trait FileSystem: Send + Sync {
+    /// read the entire contents of `path`
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// create `path`, replacing any existing file, and write `contents` to it
+    fn write(&self, path: &str, contents: &[u8]) -> Result<()>;
+
+    /// remove the file at `path`
+    fn remove(&self, path: &str) -> Result<()>;
+
+    /// read the entire contents of `path` as a `String`, returning an
+    /// [ErrorKind::InvalidData] error if it is not valid UTF-8
+    fn read_to_string(&self, path: &str) -> Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    /// move the file at `from` to `to`, replacing any existing file. the
+    /// default implementation copies the contents and removes `from`
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.write(to, &self.read(from)?)?;
+        self.remove(from)
+    }
+
+    /// return whether a file exists at `path`. the default implementation
+    /// tries to read it
+    fn exists(&self, path: &str) -> bool {
+        self.read(path).is_ok()
+    }
+
+    /// return the time `path` was last modified. the default implementation
+    /// returns an [ErrorKind::Unsupported] error
+    fn modified(&self, path: &str) -> Result<SystemTime> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("modification time of {path} is not available"),
+        ))
+    }
+
+    /// create the directory `path` along with any missing parents. the default
+    /// implementation does nothing, for file systems without real directories
+    /// like [MemFs]
+    fn create_dir_all(&self, _path: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// gzip the file at `path` to `path.gz` and remove the original. the
+    /// default implementation compresses the whole contents in memory
+    fn gzip(&self, path: &str) -> Result<()> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.read(path)?)?;
+        self.write(&format!("{path}.gz"), &encoder.finish()?)?;
+        self.remove(path)
+    }
+
+    /// run `program` with `args` where the files live and return its standard
+    /// output, regardless of its exit status. the default implementation runs
+    /// it on the local machine
+    fn run(&self, program: &str, args: &[&str]) -> Result<Vec<u8>> {
+        Ok(Command::new(program).args(args).output()?.stdout)
+    }
+}
+
+/// The default [FileSystem], backed by [std::fs]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdFs;
+
+impl FileSystem for StdFs {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> Result<()> {
+        std::fs::write(path, contents)
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        std::path::Path::new(path).exists()
+    }
+
+    fn modified(&self, path: &str) -> Result<SystemTime> {
+        std::fs::metadata(path)?.modified()
+    }
+
+    fn create_dir_all(&self, path: &str) -> Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    /// stream the contents of `path` through the encoder so that large output
+    /// files are never held in memory
+    fn gzip(&self, path: &str) -> Result<()> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::{BufReader, BufWriter, Write};
+        let mut input = BufReader::new(std::fs::File::open(path)?);
+        let output = std::fs::File::create(format!("{path}.gz"))?;
+        let mut encoder =
+            GzEncoder::new(BufWriter::new(output), Compression::default());
+        std::io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.flush()?;
+        std::fs::remove_file(path)
+    }
+}
+
+/// An in-memory [FileSystem] keyed by path, for fast, deterministic tests
+#[derive(Debug, Default)]
+pub struct MemFs {
+    /// the contents of each file and the time it was last written
+    files: Mutex<HashMap<String, (Vec<u8>, SystemTime)>>,
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// return the paths of all of the files currently stored, sorted for
+    /// comparison in tests
+    pub fn paths(&self) -> Vec<String> {
+        let mut ret: Vec<_> =
+            self.files.lock().unwrap().keys().cloned().collect();
+        ret.sort();
+        ret
+    }
+}
+
+fn not_found(path: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("{path} not found"))
+}
+
+impl FileSystem for MemFs {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(path)
+            .map(|(contents, _)| contents.clone())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (contents.to_vec(), SystemTime::now()));
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let contents = files.remove(from).ok_or_else(|| not_found(from))?;
+        files.insert(to.to_owned(), contents);
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn modified(&self, path: &str) -> Result<SystemTime> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(path)
+            .map(|(_, modified)| *modified)
+            .ok_or_else(|| not_found(path))
+    }
+}
+
+static GLOBAL: LazyLock<RwLock<Arc<dyn FileSystem>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(StdFs)));
+
+thread_local! {
+    static FS: RefCell<Option<Arc<dyn FileSystem>>> =
+        const { RefCell::new(None) };
+}
+
+/// return the [FileSystem] in use on the current thread, which is the one
+/// installed with [set] if there is one and otherwise the one installed with
+/// [set_global]
+pub fn current() -> Arc<dyn FileSystem> {
+    FS.with(|fs| fs.borrow().clone())
+        .unwrap_or_else(|| GLOBAL.read().unwrap().clone())
+}
+
+/// install `fs` as the [FileSystem] for the current thread and return the
+/// previous one. this only affects the calling thread, so it is safe to use in
+/// tests running in parallel, but jobs run on other threads, such as by
+/// rayon, still use their own
+pub fn set(fs: Arc<dyn FileSystem>) -> Arc<dyn FileSystem> {
+    let prev = current();
+    FS.with(|cur| cur.replace(Some(fs)));
+    prev
+}
+
+/// install `fs` as the [FileSystem] for every thread without one installed by
+/// [set], including those running jobs in parallel, and return the previous
+/// one. this is how a remote file system like `ssh::SshFs` should be used for
+/// a whole run
+pub fn set_global(fs: Arc<dyn FileSystem>) -> Arc<dyn FileSystem> {
+    std::mem::replace(&mut *GLOBAL.write().unwrap(), fs)
+}
@@ -0,0 +1,213 @@
+use std::{
+    io::{Error, ErrorKind, Result, Write},
+    process::{Command, Output, Stdio},
+    time::{Duration, SystemTime},
+};
+
+use super::FileSystem;
+
+#[cfg(test)]
+mod tests;
+
+/// the exit status `ssh` uses for its own errors, like a dropped connection,
+/// as opposed to those of the remote command
+const SSH_ERROR: i32 = 255;
+
+/// A [FileSystem] on a remote host, accessed by running commands with `ssh`,
+/// for monitoring jobs on a cluster from a driver running elsewhere. This
+/// relies on non-interactive authentication, like an SSH key or a control
+/// master, being set up already. Installing it with [super::set_global] makes
+/// `read_output` read from, and the queue status commands run on, `host`
+#[derive(Clone, Debug)]
+pub struct SshFs {
+    /// the host to connect to, in any form accepted by `ssh`, like
+    /// `user@cluster`
+    pub host: String,
+
+    /// the `ssh` binary to run. defaults to `ssh`
+    pub ssh: String,
+
+    /// the number of times to retry a command after the connection fails.
+    /// defaults to 5
+    pub retries: usize,
+
+    /// the delay before the first retry, doubled after each failure up to
+    /// `max_backoff`. defaults to 1 second
+    pub backoff: Duration,
+
+    /// the longest delay between retries. defaults to 1 minute
+    pub max_backoff: Duration,
+}
+
+impl SshFs {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_owned(),
+            ssh: "ssh".to_owned(),
+            retries: 5,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    /// run `command` on `self.host` with `input` on its standard input,
+    /// retrying with backoff if the connection fails. a failure of `command`
+    /// itself is returned without retrying
+    fn ssh(&self, command: &str, input: Option<&[u8]>) -> Result<Output> {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let mut child = Command::new(&self.ssh)
+                .args(["-o", "BatchMode=yes", &self.host, command])
+                .stdin(if input.is_some() {
+                    Stdio::piped()
+                } else {
+                    Stdio::null()
+                })
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            if let Some(input) = input {
+                child.stdin.take().unwrap().write_all(input)?;
+            }
+            let output = child.wait_with_output()?;
+            if output.status.code() != Some(SSH_ERROR) {
+                return Ok(output);
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if attempt >= self.retries {
+                return Err(Error::new(
+                    ErrorKind::ConnectionAborted,
+                    format!(
+                        "ssh to {} failed after {} attempts: {}",
+                        self.host,
+                        attempt + 1,
+                        stderr.trim()
+                    ),
+                ));
+            }
+            log::warn!(
+                "ssh to {} failed with `{}`, retrying in {:.1} s",
+                self.host,
+                stderr.trim(),
+                delay.as_secs_f64()
+            );
+            std::thread::sleep(delay);
+            delay = (delay * 2).min(self.max_backoff);
+            attempt += 1;
+        }
+    }
+
+    /// run `command` with [Self::ssh] and return its standard output,
+    /// converting a failure of `command` into an error of `kind`
+    fn checked(
+        &self,
+        command: &str,
+        input: Option<&[u8]>,
+        kind: ErrorKind,
+    ) -> Result<Vec<u8>> {
+        let output = self.ssh(command, input)?;
+        if !output.status.success() {
+            return Err(Error::new(
+                kind,
+                format!(
+                    "`{command}` failed on {}: {}",
+                    self.host,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// quote `s` for the remote shell, which receives the whole command as a
+/// single string
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+impl FileSystem for SshFs {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.checked(
+            &format!("cat -- {}", quote(path)),
+            None,
+            ErrorKind::NotFound,
+        )
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> Result<()> {
+        self.checked(
+            &format!("cat > {}", quote(path)),
+            Some(contents),
+            ErrorKind::Other,
+        )
+        .map(|_| ())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        self.checked(
+            &format!("rm -- {}", quote(path)),
+            None,
+            ErrorKind::NotFound,
+        )
+        .map(|_| ())
+    }
+
+    fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.checked(
+            &format!("mv -- {} {}", quote(from), quote(to)),
+            None,
+            ErrorKind::NotFound,
+        )
+        .map(|_| ())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.ssh(&format!("test -e {}", quote(path)), None)
+            .is_ok_and(|output| output.status.success())
+    }
+
+    /// read the modification time in seconds since the epoch with `stat`
+    fn modified(&self, path: &str) -> Result<SystemTime> {
+        let output = self.checked(
+            &format!("stat -c %Y -- {}", quote(path)),
+            None,
+            ErrorKind::NotFound,
+        )?;
+        let secs = String::from_utf8_lossy(&output)
+            .trim()
+            .parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    fn create_dir_all(&self, path: &str) -> Result<()> {
+        self.checked(
+            &format!("mkdir -p -- {}", quote(path)),
+            None,
+            ErrorKind::Other,
+        )
+        .map(|_| ())
+    }
+
+    /// compress `path` on the remote host with `gzip` instead of copying it
+    /// back and forth
+    fn gzip(&self, path: &str) -> Result<()> {
+        self.checked(
+            &format!("gzip -f -- {}", quote(path)),
+            None,
+            ErrorKind::NotFound,
+        )
+        .map(|_| ())
+    }
+
+    fn run(&self, program: &str, args: &[&str]) -> Result<Vec<u8>> {
+        let command = std::iter::once(program)
+            .chain(args.iter().copied())
+            .map(quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(self.ssh(&command, None)?.stdout)
+    }
+}
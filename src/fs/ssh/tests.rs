@@ -0,0 +1,58 @@
+use std::{io::ErrorKind, os::unix::fs::PermissionsExt, time::Duration};
+
+use super::{quote, SshFs};
+use crate::fs::FileSystem;
+
+/// write an executable script standing in for `ssh` to `path`
+fn fake_ssh(path: &str, body: &str) -> SshFs {
+    std::fs::write(path, format!("#!/bin/sh\n{body}\n")).unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .unwrap();
+    SshFs {
+        ssh: path.to_owned(),
+        backoff: Duration::ZERO,
+        ..SshFs::new("cluster")
+    }
+}
+
+#[test]
+fn quoting() {
+    assert_eq!(quote("a b"), "'a b'");
+    assert_eq!(quote("it's"), r"'it'\''s'");
+}
+
+#[test]
+fn local_commands() {
+    // skip the -o BatchMode=yes and host arguments and run the command here
+    let ssh = fake_ssh("/tmp/fake_ssh_local", r#"shift 3; exec sh -c "$1""#);
+    let path = "/tmp/ssh fs test";
+    ssh.write(path, b"contents").unwrap();
+    assert_eq!(ssh.read_to_string(path).unwrap(), "contents");
+    assert_eq!(ssh.run("echo", &["a", "b c"]).unwrap(), b"a b c\n");
+    // stat only reports whole seconds
+    let want = std::fs::metadata(path).unwrap().modified().unwrap();
+    let got = ssh.modified(path).unwrap();
+    assert!(want.duration_since(got).unwrap() < Duration::from_secs(1));
+    ssh.remove(path).unwrap();
+    let err = ssh.read(path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    std::fs::remove_file("/tmp/fake_ssh_local").unwrap();
+}
+
+#[test]
+fn retry() {
+    let ssh = SshFs {
+        retries: 2,
+        ..fake_ssh(
+            "/tmp/fake_ssh_retry",
+            "echo x >> /tmp/fake_ssh_retry.log; exit 255",
+        )
+    };
+    let _ = std::fs::remove_file("/tmp/fake_ssh_retry.log");
+    let err = ssh.read("/tmp/anything").unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionAborted);
+    let log = std::fs::read_to_string("/tmp/fake_ssh_retry.log").unwrap();
+    assert_eq!(log.lines().count(), 3);
+    std::fs::remove_file("/tmp/fake_ssh_retry").unwrap();
+    std::fs::remove_file("/tmp/fake_ssh_retry.log").unwrap();
+}
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::{fmt::Display, str::FromStr};
-use symm::atom::Atom;
+use symm::atom::{Atom, NUMBER_TO_SYMBOL};
+
+use crate::{fs, Error};
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Geom {
@@ -42,32 +44,10 @@ impl From<symm::Molecule> for Geom {
 }
 
 impl FromStr for Geom {
-    type Err = std::string::ParseError;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut atoms = Vec::new();
-        let mut skip = 0;
-        for line in s.lines() {
-            let fields = line.split_whitespace().collect::<Vec<_>>();
-            if skip > 0 {
-                skip -= 1;
-                continue;
-            } else if fields.is_empty() {
-                continue;
-            } else if fields.len() == 1 {
-                // one field, all letters => zmat
-                if fields[0].chars().all(char::is_alphabetic) {
-                    return Ok(Geom::Zmat(String::from(s)));
-                } else {
-                    // else, start of XYZ with comment line
-                    skip = 1;
-                    continue;
-                }
-            } else {
-                atoms.push(line.parse().unwrap());
-            }
-        }
-        Ok(Geom::Xyz(atoms))
+        Self::parse(s)
     }
 }
 
@@ -91,17 +71,482 @@ impl Geom {
     pub fn is_zmat(&self) -> bool {
         matches!(self, Geom::Zmat(_))
     }
+
+    /// rotate the Cartesian coordinates of `self` in place by `matrix`,
+    /// replacing each position `r` with `matrix * r`. a Z-matrix is already
+    /// invariant to rotations, so it is left unchanged. panics if `matrix` is
+    /// not orthonormal to within 1e-8
+    pub fn rotate(&mut self, matrix: [[f64; 3]; 3]) {
+        for (i, a) in matrix.iter().enumerate() {
+            for (j, b) in matrix.iter().enumerate() {
+                let dot: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+                let want = if i == j { 1.0 } else { 0.0 };
+                assert!(
+                    (dot - want).abs() < 1e-8,
+                    "rotation matrix {matrix:?} is not orthonormal"
+                );
+            }
+        }
+        let Geom::Xyz(atoms) = self else {
+            return;
+        };
+        for atom in atoms {
+            let r = [atom.x, atom.y, atom.z];
+            let [x, y, z] = matrix
+                .map(|row| row.iter().zip(r).map(|(m, r)| m * r).sum::<f64>());
+            atom.x = x;
+            atom.y = y;
+            atom.z = z;
+        }
+    }
+
+    /// check that every atom in `self` is an element known to [symm], after
+    /// normalizing the case of Z-matrix symbols to the canonical form like
+    /// `Cl`. the dummy atoms `X` and `Q` are also allowed in a Z-matrix, and
+    /// numeric suffixes like `H1` are kept. returns an error naming the first
+    /// invalid atom and its 0-based index
+    pub fn normalize_elements(&mut self) -> Result<(), String> {
+        match self {
+            Geom::Xyz(atoms) => {
+                for (i, atom) in atoms.iter().enumerate() {
+                    let n = atom.atomic_number;
+                    if !(1..NUMBER_TO_SYMBOL.len()).contains(&n) {
+                        return Err(format!(
+                            "invalid atomic number {n} for atom {i}"
+                        ));
+                    }
+                }
+            }
+            Geom::Zmat(zmat) => {
+                let mut ret = String::with_capacity(zmat.len());
+                let mut atoms = true;
+                let mut i = 0;
+                for line in zmat.split_inclusive('\n') {
+                    atoms &= !line.contains('=');
+                    let start = line.len() - line.trim_start().len();
+                    let len = line[start..]
+                        .find(|c: char| !c.is_ascii_alphabetic())
+                        .unwrap_or(line.len() - start);
+                    if !atoms || len == 0 {
+                        ret.push_str(line);
+                        continue;
+                    }
+                    let label = &line[start..start + len];
+                    let Some(elem) = canonical_element(label) else {
+                        return Err(format!(
+                            "invalid element `{label}` for atom {i}"
+                        ));
+                    };
+                    ret.push_str(&line[..start]);
+                    ret.push_str(elem);
+                    ret.push_str(&line[start + len..]);
+                    i += 1;
+                }
+                *zmat = ret;
+            }
+        }
+        Ok(())
+    }
+
+    /// parse a single geometry in the standard XYZ format: a line with the
+    /// number of atoms, a comment line, and then one line for each atom with
+    /// its element symbol, or atomic number, and its coordinates. any columns
+    /// after the coordinates are ignored, as are blank lines. returns an
+    /// [Error::Parse] describing the first problem, such as a number of atoms
+    /// that doesn't match the header or a non-numeric coordinate
+    pub fn parse_xyz(s: &str) -> Result<Self, Error> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .skip_while(|(_, line)| line.trim().is_empty());
+        let Some((n, header)) = lines.next() else {
+            return Err(Error::Parse("empty XYZ geometry".to_owned()));
+        };
+        let natoms: usize = header.trim().parse().map_err(|_| {
+            Error::Parse(format!(
+                "invalid atom count `{}` on line {n} of XYZ geometry",
+                header.trim()
+            ))
+        })?;
+        if lines.next().is_none() {
+            return Err(Error::Parse(
+                "missing comment line in XYZ geometry".to_owned(),
+            ));
+        }
+        let mut atoms = Vec::with_capacity(natoms);
+        for (n, line) in lines {
+            if !line.trim().is_empty() {
+                atoms.push(parse_atom(line, n)?);
+            }
+        }
+        if atoms.len() != natoms {
+            return Err(Error::Parse(format!(
+                "expected {natoms} atoms in XYZ geometry, found {}",
+                atoms.len()
+            )));
+        }
+        Ok(Geom::Xyz(atoms))
+    }
+
+    /// parse a geometry in any of the forms written by other tools, detecting
+    /// which variant to return: an XYZ file with its atom count and comment
+    /// lines, as for [Geom::parse_xyz], bare `Sym x y z` lines like those
+    /// written by [geom_string], or otherwise a Z-matrix, which must be
+    /// accepted by [Geom::to_cartesian]. returns an [Error::Parse] if `s` is
+    /// none of these
+    pub fn parse(s: &str) -> Result<Self, Error> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty())
+            .peekable();
+        let Some((_, first)) = lines.peek() else {
+            return Err(Error::Parse("empty geometry".to_owned()));
+        };
+        if first.trim().parse::<usize>().is_ok() {
+            return Self::parse_xyz(s);
+        }
+        let cartesian: Result<Vec<_>, _> =
+            lines.map(|(n, line)| parse_atom(line, n)).collect();
+        if let Ok(atoms) = cartesian {
+            return Ok(Geom::Xyz(atoms));
+        }
+        let zmat = Geom::Zmat(s.to_owned());
+        zmat.to_cartesian()?;
+        Ok(zmat)
+    }
+
+    /// read the XYZ file at `path` with [Geom::parse_xyz], through the current
+    /// [crate::fs::FileSystem]
+    pub fn from_xyz_file(path: &str) -> Result<Self, Error> {
+        let contents = fs::current()
+            .read_to_string(path)
+            .map_err(|e| Error::io(path, e))?;
+        Self::parse_xyz(&contents).map_err(|e| match e {
+            Error::Parse(msg) => Error::Parse(format!("{path}: {msg}")),
+            e => e,
+        })
+    }
+
+    /// evaluate the Z-matrix in `self` into Cartesian coordinates, in the same
+    /// units as its bond lengths, or return a copy of `self` if it is already
+    /// Cartesian. the angles and dihedrals are in degrees, and each atom line
+    /// refers to earlier atoms by their 1-based index or label. the first atom
+    /// is placed at the origin, the second along the z axis, and the third in
+    /// the xz plane. dummy atoms (`X` or `Q`) are used for placing the other
+    /// atoms but are left out of the result. returns an [Error::Parse] if a
+    /// line or variable can't be understood, or if a dihedral is undefined
+    /// because its first three atoms are collinear
+    pub fn to_cartesian(&self) -> Result<Geom, Error> {
+        let zmat = match self {
+            Geom::Xyz(_) => return Ok(self.clone()),
+            Geom::Zmat(zmat) => zmat,
+        };
+        let mut vars = std::collections::HashMap::new();
+        for line in zmat.lines().filter(|l| l.contains('=')) {
+            for def in line.split(';').filter(|d| !d.trim().is_empty()) {
+                let Some((name, value)) = def.split_once('=') else {
+                    return Err(Error::Parse(format!(
+                        "invalid Z-matrix variable definition `{def}`"
+                    )));
+                };
+                let value: f64 = value.trim().parse().map_err(|_| {
+                    Error::Parse(format!(
+                        "invalid value `{}` for Z-matrix variable {}",
+                        value.trim(),
+                        name.trim()
+                    ))
+                })?;
+                vars.insert(name.trim().to_owned(), value);
+            }
+        }
+        let value = |field: &str| -> Result<f64, Error> {
+            if let Ok(v) = field.parse() {
+                return Ok(v);
+            }
+            let (sign, name) = match field.strip_prefix('-') {
+                Some(name) => (-1.0, name),
+                None => (1.0, field),
+            };
+            vars.get(name).map(|v| sign * v).ok_or_else(|| {
+                Error::Parse(format!("undefined Z-matrix variable `{name}`"))
+            })
+        };
+
+        let mut labels: Vec<&str> = Vec::new();
+        let mut coords: Vec<[f64; 3]> = Vec::new();
+        let mut atoms = Vec::new();
+        for line in zmat
+            .lines()
+            .take_while(|l| !l.contains('='))
+            .filter(|l| !l.trim().is_empty())
+        {
+            let fields: Vec<_> = line
+                .split([' ', '\t', ','])
+                .filter(|f| !f.is_empty())
+                .collect();
+            let i = labels.len();
+            if fields.len() != 1 + 2 * i.min(3) {
+                return Err(Error::Parse(format!(
+                    "expected {} fields for Z-matrix atom {}, found `{line}`",
+                    1 + 2 * i.min(3),
+                    i + 1
+                )));
+            }
+            let label = fields[0];
+            let len = label
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(label.len());
+            let number = match canonical_element(&label[..len]) {
+                Some("X" | "Q") => None,
+                Some(_) => atomic_number(&label[..len]),
+                None => {
+                    return Err(Error::Parse(format!(
+                        "invalid element `{label}` for Z-matrix atom {}",
+                        i + 1
+                    )))
+                }
+            };
+            // resolve the references to earlier atoms and their values
+            let mut refs = Vec::new();
+            for pair in fields[1..].chunks(2) {
+                let reference = match pair[0].parse::<usize>() {
+                    Ok(n) => (1..=i).contains(&n).then(|| n - 1),
+                    Err(_) => labels
+                        .iter()
+                        .position(|l| l.eq_ignore_ascii_case(pair[0])),
+                };
+                let Some(reference) = reference else {
+                    return Err(Error::Parse(format!(
+                        "invalid reference `{}` for Z-matrix atom {}",
+                        pair[0],
+                        i + 1
+                    )));
+                };
+                refs.push((reference, value(pair[1])?));
+            }
+            let pos = match refs[..] {
+                [] => [0.0; 3],
+                [(a, r)] => add(coords[a], [0.0, 0.0, r]),
+                [(a, r), (b, theta)] => {
+                    // put the third atom in the xz plane by using a point off
+                    // of the a-b axis in that plane as the dihedral reference
+                    let ab = sub(coords[a], coords[b]);
+                    let off = if cross(ab, [1.0, 0.0, 0.0]) == [0.0; 3] {
+                        [0.0, 0.0, 1.0]
+                    } else {
+                        [1.0, 0.0, 0.0]
+                    };
+                    let c = add(coords[b], off);
+                    place(coords[a], coords[b], c, r, theta, 0.0)
+                        .expect("the reference point is off of the a-b axis")
+                }
+                [(a, r), (b, theta), (c, phi)] => {
+                    place(coords[a], coords[b], coords[c], r, theta, phi)
+                        .ok_or_else(|| {
+                            Error::Parse(format!(
+                                "dihedral for Z-matrix atom {} is undefined \
+                                 because atoms {}, {}, and {} are collinear",
+                                i + 1,
+                                a + 1,
+                                b + 1,
+                                c + 1
+                            ))
+                        })?
+                }
+                _ => unreachable!(),
+            };
+            labels.push(label);
+            coords.push(pos);
+            if let Some(number) = number {
+                let [x, y, z] = pos;
+                atoms.push(Atom::new(number, x, y, z));
+            }
+        }
+        Ok(Geom::Xyz(atoms))
+    }
+
+    /// return the number of atoms in `self`. for a Z-matrix, this is the
+    /// number of non-empty lines before the variable definitions, so any dummy
+    /// atoms are included in the count
+    pub fn natoms(&self) -> usize {
+        match self {
+            Geom::Xyz(atoms) => atoms.len(),
+            Geom::Zmat(zmat) => zmat
+                .lines()
+                .map(str::trim)
+                .take_while(|l| !l.contains('='))
+                .filter(|l| !l.is_empty())
+                .count(),
+        }
+    }
+}
+
+/// parse the atom on line `n` of an XYZ geometry, with its element symbol or
+/// atomic number followed by its coordinates and any ignored columns
+fn parse_atom(line: &str, n: usize) -> Result<Atom, Error> {
+    let fields: Vec<_> = line.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(Error::Parse(format!(
+            "expected an element and 3 coordinates on line {n} of XYZ \
+             geometry, found `{line}`"
+        )));
+    }
+    let number = atomic_number(fields[0]).ok_or_else(|| {
+        Error::Parse(format!(
+            "invalid element `{}` on line {n} of XYZ geometry",
+            fields[0]
+        ))
+    })?;
+    let mut coords = [0.0; 3];
+    for (c, field) in coords.iter_mut().zip(&fields[1..4]) {
+        *c = field.parse().map_err(|_| {
+            Error::Parse(format!(
+                "invalid coordinate `{field}` on line {n} of XYZ geometry"
+            ))
+        })?;
+    }
+    let [x, y, z] = coords;
+    Ok(Atom::new(number, x, y, z))
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    a.map(|x| x * s)
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn unit(a: [f64; 3]) -> [f64; 3] {
+    scale(a, 1.0 / a.iter().map(|x| x * x).sum::<f64>().sqrt())
+}
+
+/// return the position of an atom a distance `r` from `a`, making an angle of
+/// `theta` degrees with `a` and `b` and a dihedral of `phi` degrees with `a`,
+/// `b`, and `c`. returns `None` if `a`, `b`, and `c` are collinear, so that
+/// the dihedral is undefined, unless the angle is linear and the dihedral
+/// doesn't matter
+fn place(
+    a: [f64; 3],
+    b: [f64; 3],
+    c: [f64; 3],
+    r: f64,
+    theta: f64,
+    phi: f64,
+) -> Option<[f64; 3]> {
+    let (theta, phi) = (theta.to_radians(), phi.to_radians());
+    let ba = unit(sub(a, b));
+    let normal = cross(sub(b, c), ba);
+    let len = normal.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if len < 1e-8 {
+        return (theta.sin().abs() < 1e-8)
+            .then(|| add(a, scale(ba, -r * theta.cos())));
+    }
+    let n = scale(normal, 1.0 / len);
+    let m = cross(n, ba);
+    let d = add(
+        scale(ba, -r * theta.cos()),
+        add(
+            scale(m, r * theta.sin() * phi.cos()),
+            scale(n, r * theta.sin() * phi.sin()),
+        ),
+    );
+    Some(add(a, d))
+}
+
+/// return the atomic number of the element `label`, given as a symbol in any
+/// case or as the atomic number itself, or `None` if it is not an element.
+/// [NUMBER_TO_SYMBOL] is indexed by atomic number, so its first entry is a
+/// placeholder rather than an element
+fn atomic_number(label: &str) -> Option<usize> {
+    if let Ok(n) = label.parse::<usize>() {
+        return (1..NUMBER_TO_SYMBOL.len()).contains(&n).then_some(n);
+    }
+    NUMBER_TO_SYMBOL
+        .iter()
+        .skip(1)
+        .position(|e| e.eq_ignore_ascii_case(label))
+        .map(|i| i + 1)
+}
+
+/// return the canonical form of the element or Z-matrix dummy atom `label`,
+/// ignoring case, or `None` if it is not one
+fn canonical_element(label: &str) -> Option<&'static str> {
+    NUMBER_TO_SYMBOL
+        .iter()
+        .skip(1)
+        .chain(&["X", "Q"])
+        .find(|e| e.eq_ignore_ascii_case(label))
+        .copied()
+}
+
+/// the geometry conventions of the programs supported by the crate
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum GeomFormat {
+    Molpro,
+    Mopac,
+    Gaussian,
+}
+
+/// render `geom` for the program described by `format`. Cartesian geometries
+/// are written as `Sym x y z` lines for every program. Z-matrices are passed
+/// through unchanged for Molpro and MOPAC, while for Gaussian the atom lines
+/// are written space-separated, followed by a blank line and a variable block
+/// of `name value` lines
+pub fn geom_string_for(geom: &Geom, format: GeomFormat) -> String {
+    match (geom, format) {
+        (Geom::Zmat(zmat), GeomFormat::Gaussian) => gaussian_zmat(zmat),
+        _ => geom_string(geom),
+    }
+}
+
+fn gaussian_zmat(zmat: &str) -> String {
+    use std::fmt::Write;
+    let mut atoms = String::new();
+    let mut vars = String::new();
+    for line in zmat.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if let Some((name, value)) = line.split_once('=') {
+            let value = value.trim().trim_end_matches(';');
+            writeln!(vars, "{} {}", name.trim(), value).unwrap();
+        } else {
+            let fields: Vec<_> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect();
+            writeln!(atoms, "{}", fields.join(" ")).unwrap();
+        }
+    }
+    format!("{atoms}\n{vars}")
 }
 
+/// render `geom` for an input file. Z-matrices are returned unchanged, while
+/// Cartesian geometries are written as `Sym x y z` lines with the symbol padded
+/// to two characters and each coordinate right-aligned with 12 decimal places,
+/// so that the columns line up for coordinates of magnitude below 100
 pub fn geom_string(geom: &Geom) -> String {
     use std::fmt::Write;
     match geom {
         Geom::Xyz(geom) => {
-            let mut ret = String::with_capacity(50 * geom.len());
+            let mut ret = String::with_capacity(54 * geom.len());
             for g in geom {
                 writeln!(
                     ret,
-                    "{} {:.12} {:.12} {:.12}",
+                    "{:<2} {:>16.12} {:>16.12} {:>16.12}",
                     g.label(),
                     g.x,
                     g.y,
@@ -1,8 +1,15 @@
 #![feature(test, iter_collect_into, lazy_cell)]
 
+pub mod error;
+pub mod fs;
 pub mod geom;
 pub mod program;
 pub mod queue;
+#[cfg(feature = "record")]
+pub mod record;
+pub mod util;
+
+pub use error::Error;
 
 #[cfg(test)]
 mod tests;
@@ -1,11 +1,16 @@
 use std::{
-    error::Error, fmt::Display, path::Path, str::FromStr, time::SystemTime,
+    collections::HashMap,
+    fmt::Display,
+    path::Path,
+    process::Command,
+    str::FromStr,
+    time::{Duration, SystemTime},
 };
 
 use serde::{Deserialize, Serialize};
 use symm::Atom;
 
-use crate::geom::Geom;
+use crate::{geom::Geom, Error};
 
 pub mod molpro;
 pub mod mopac;
@@ -15,6 +20,378 @@ pub struct ProgramResult {
     pub energy: f64,
     pub cart_geom: Option<Vec<Atom>>,
     pub time: f64,
+
+    /// the Cartesian gradient at the final geometry, with the x, y, and z
+    /// components of each atom in turn, or `None` if the program didn't print
+    /// one, as for energy-only single points
+    pub gradient: Option<Gradient>,
+
+    /// the total CPU time reported by the program, in seconds
+    pub cpu_time: Option<f64>,
+
+    /// the elapsed wall time reported by the program, in seconds
+    pub wall_time: Option<f64>,
+
+    /// the `(energy, max gradient)` at each step of a geometry optimization,
+    /// or empty for other jobs
+    #[serde(default)]
+    pub opt_steps: Vec<(f64, f64)>,
+
+    /// the last SCF dipole moment printed by the program as `[x, y, z]`, in
+    /// atomic units
+    pub dipole: Option<[f64; 3]>,
+
+    /// the last SCF quadrupole moment printed by the program as `[xx, yy, zz,
+    /// xy, xz, yz]`, in atomic units like `dipole`. this is only printed when
+    /// requested, such as by Molpro's `expec,qm` directive
+    pub quadrupole: Option<[f64; 6]>,
+
+    /// the convergence criteria achieved at the last step of a geometry
+    /// optimization, or `None` for other jobs
+    pub convergence: Option<OptConvergence>,
+
+    /// the number of contracted basis functions, from the first basis set
+    /// printed by the program
+    pub nbasis: Option<usize>,
+
+    /// the number of primitive basis functions, from the same basis set as
+    /// `nbasis`
+    pub nprimitive: Option<usize>,
+
+    /// the harmonic vibrational frequencies in cm⁻¹, with imaginary
+    /// frequencies stored as negative values, or `None` for jobs without a
+    /// frequency calculation
+    pub frequencies: Option<Vec<f64>>,
+
+    /// the CPU time spent in each part of the calculation, or an empty
+    /// breakdown if the program does not print one
+    #[serde(default)]
+    pub timings: Timings,
+
+    /// the spin contamination of an open-shell wave function, or `None` for
+    /// closed-shell jobs and programs that do not print it
+    pub spin_contamination: Option<SpinContamination>,
+
+    /// the thermochemical quantities at each temperature requested, as by
+    /// MOPAC's `THERMO` keyword, or empty for jobs without them
+    #[serde(default)]
+    pub thermo: Vec<Thermo>,
+}
+
+/// The optional quantities to extract in [Program::read_output_with]. The
+/// default selects none of them, leaving only the energy, the times, and the
+/// error checks, which is all most single-point jobs need
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct ParseSelectors {
+    /// the final Cartesian geometry, [ProgramResult::cart_geom]
+    pub geometry: bool,
+
+    /// [ProgramResult::gradient]
+    pub gradient: bool,
+
+    /// [ProgramResult::dipole] and [ProgramResult::quadrupole]
+    pub multipoles: bool,
+
+    /// the optimization progress, [ProgramResult::opt_steps] and
+    /// [ProgramResult::convergence]
+    pub optimization: bool,
+
+    /// the basis set sizes, [ProgramResult::nbasis] and
+    /// [ProgramResult::nprimitive]
+    pub basis: bool,
+
+    /// [ProgramResult::timings]
+    pub timings: bool,
+
+    /// [ProgramResult::frequencies] and [ProgramResult::thermo]
+    pub frequencies: bool,
+
+    /// [ProgramResult::spin_contamination]
+    pub spin: bool,
+}
+
+impl ParseSelectors {
+    /// select every quantity, matching [Program::read_output]
+    pub fn all() -> Self {
+        Self {
+            geometry: true,
+            gradient: true,
+            multipoles: true,
+            optimization: true,
+            basis: true,
+            timings: true,
+            frequencies: true,
+            spin: true,
+        }
+    }
+
+    /// clear the quantities in `res` that are not selected
+    pub fn apply(&self, mut res: ProgramResult) -> ProgramResult {
+        if !self.geometry {
+            res.cart_geom = None;
+        }
+        if !self.gradient {
+            res.gradient = None;
+        }
+        if !self.multipoles {
+            res.dipole = None;
+            res.quadrupole = None;
+        }
+        if !self.optimization {
+            res.opt_steps.clear();
+            res.convergence = None;
+        }
+        if !self.basis {
+            res.nbasis = None;
+            res.nprimitive = None;
+        }
+        if !self.timings {
+            res.timings = Timings::default();
+        }
+        if !self.frequencies {
+            res.frequencies = None;
+            res.thermo.clear();
+        }
+        if !self.spin {
+            res.spin_contamination = None;
+        }
+        res
+    }
+}
+
+/// The thermochemical quantities from a frequency calculation at a single
+/// temperature
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Thermo {
+    /// the temperature in K
+    pub temperature: f64,
+
+    /// the zero-point vibrational energy in kcal/mol, if it was printed
+    pub zpe: Option<f64>,
+
+    /// the heat of formation at `temperature` in kcal/mol
+    pub heat_of_formation: f64,
+
+    /// the total thermal contribution to the enthalpy in cal/mol
+    pub enthalpy: f64,
+
+    /// the total entropy in cal/(K mol)
+    pub entropy: f64,
+}
+
+impl Thermo {
+    /// the Gibbs free energy of formation at `self.temperature`, `H - TS`, in
+    /// kcal/mol
+    pub fn gibbs(&self) -> f64 {
+        self.heat_of_formation - self.temperature * self.entropy / 1000.0
+    }
+}
+
+/// The expectation value of the total spin operator, `<S²>`, for an open-shell
+/// wave function, along with the value for a pure spin state. A large
+/// [SpinContamination::deviation] means that the wave function is
+/// contaminated by higher spin states and may not be reliable
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpinContamination {
+    /// the computed value of `<S²>`
+    pub s2: f64,
+
+    /// the exact value, `S(S+1)`, for the requested spin state
+    pub expected: f64,
+}
+
+impl SpinContamination {
+    /// the spin contamination of a state with `two_s` unpaired electrons,
+    /// like Molpro's `spin` setting, from the `excess` of `<S²>` over
+    /// `S(S+1)`, which is how Molpro prints it
+    pub fn from_excess(two_s: f64, excess: f64) -> Self {
+        let s = two_s / 2.0;
+        let expected = s * (s + 1.0);
+        Self {
+            s2: expected + excess,
+            expected,
+        }
+    }
+
+    /// the deviation of `<S²>` from its expected value
+    pub fn deviation(&self) -> f64 {
+        self.s2 - self.expected
+    }
+
+    /// whether the magnitude of [Self::deviation] exceeds `threshold`. a
+    /// common threshold is 10% of `S(S+1)`
+    pub fn is_excessive(&self, threshold: f64) -> bool {
+        self.deviation().abs() > threshold
+    }
+}
+
+/// The CPU time in seconds spent in each part of a calculation, in the order
+/// the parts first ran, for estimating the cost of similar jobs more
+/// accurately than from the total time alone
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timings {
+    /// the time in each program, like `HF-SCF` or `CCSD(T)`, summed over
+    /// repeated runs of the same program
+    pub programs: Vec<(String, f64)>,
+
+    /// the time in each step of the coupled-cluster programs, like `CCSD
+    /// iterations` and `Triples`, summed over all of the programs
+    pub steps: Vec<(String, f64)>,
+}
+
+impl Timings {
+    /// return the time spent in the program or step called `name`, if it ran
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.programs
+            .iter()
+            .chain(&self.steps)
+            .find(|(n, _)| n == name)
+            .map(|&(_, t)| t)
+    }
+
+    /// add `time` to the total for `name` in `times`. the times are printed
+    /// to hundredths of a second, so the sum is rounded to match
+    pub(crate) fn add(times: &mut Vec<(String, f64)>, name: &str, time: f64) {
+        match times.iter_mut().find(|(n, _)| n == name) {
+            Some((_, t)) => *t = ((*t + time) * 100.0).round() / 100.0,
+            None => times.push((name.to_owned(), time)),
+        }
+    }
+}
+
+/// The kind of stationary point described by a set of frequencies, as
+/// determined by [ProgramResult::stationary_point]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StationaryPoint {
+    /// no imaginary frequencies
+    Minimum,
+    /// exactly one imaginary frequency
+    TransitionState,
+    /// the number of imaginary frequencies, which is more than one
+    Saddle(usize),
+}
+
+/// The gradient and step sizes at the last step of a geometry optimization,
+/// along with the requested thresholds on their maximum values, if these were
+/// printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OptConvergence {
+    pub grad_max: f64,
+    pub grad_rms: f64,
+    pub step_max: f64,
+    pub step_rms: f64,
+    pub optgrad: Option<f64>,
+    pub optstep: Option<f64>,
+}
+
+impl ProgramResult {
+    /// the imaginary frequencies in `self.frequencies`, as negative values,
+    /// ignoring those smaller in magnitude than `threshold` cm⁻¹ as
+    /// numerical noise. returns an empty vector if there are no frequencies
+    pub fn imaginary_frequencies(&self, threshold: f64) -> Vec<f64> {
+        self.frequencies
+            .iter()
+            .flatten()
+            .filter(|&&f| f < -threshold.abs())
+            .copied()
+            .collect()
+    }
+
+    /// classify the stationary point described by `self.frequencies` from its
+    /// number of imaginary frequencies, ignoring those smaller in magnitude
+    /// than `threshold` cm⁻¹, or return `None` if there are no frequencies
+    pub fn stationary_point(&self, threshold: f64) -> Option<StationaryPoint> {
+        self.frequencies.as_ref()?;
+        Some(match self.imaginary_frequencies(threshold).len() {
+            0 => StationaryPoint::Minimum,
+            1 => StationaryPoint::TransitionState,
+            n => StationaryPoint::Saddle(n),
+        })
+    }
+
+    /// the parallel efficiency of the job, `cpu_time / (wall_time * ncpus)`,
+    /// if both times were parsed. a value near 1 means all `ncpus` were kept
+    /// busy for the whole run
+    pub fn efficiency(&self, ncpus: usize) -> Option<f64> {
+        match (self.cpu_time, self.wall_time) {
+            (Some(cpu), Some(wall)) if wall > 0.0 && ncpus > 0 => {
+                Some(cpu / (wall * ncpus as f64))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// return the energies in `results` relative to that of
+/// `results[reference_idx]`, in Hartrees or, if `kcal` is true, in kcal/mol.
+/// panics if `reference_idx` is out of bounds
+pub fn relative_energies(
+    results: &[ProgramResult],
+    reference_idx: usize,
+    kcal: bool,
+) -> Vec<f64> {
+    let reference = results[reference_idx].energy;
+    let scale = if kcal { mopac::KCALHT } else { 1.0 };
+    results
+        .iter()
+        .map(|r| (r.energy - reference) * scale)
+        .collect()
+}
+
+/// The combined results of several runs over the same jobs, as returned by
+/// [merge_results]
+#[derive(Debug, Default, PartialEq)]
+pub struct MergedResults {
+    pub results: HashMap<String, Result<ProgramResult, ProgramError>>,
+
+    /// the jobs with more than one successful result whose energies disagree,
+    /// as `(key, kept energy, conflicting energy)`
+    pub conflicts: Vec<(String, f64, f64)>,
+}
+
+/// merge the `(key, result)` pairs from each of `runs`, such as the results
+/// of a run and its resumed continuations, into a single set with one result
+/// per key. the key should identify the job across runs, like its filename. a
+/// successful result replaces an error, but the first successful result for a
+/// key is kept, and any later ones with energies differing by more than `tol`
+/// are reported in [MergedResults::conflicts]
+pub fn merge_results<I>(runs: I, tol: f64) -> MergedResults
+where
+    I: IntoIterator<Item = Vec<(String, Result<ProgramResult, ProgramError>)>>,
+{
+    let mut ret = MergedResults::default();
+    for (key, res) in runs.into_iter().flatten() {
+        match (ret.results.get(&key), &res) {
+            (Some(Ok(old)), Ok(new)) => {
+                if (old.energy - new.energy).abs() > tol {
+                    ret.conflicts.push((key, old.energy, new.energy));
+                }
+            }
+            (Some(Ok(_)), Err(_)) => {}
+            _ => {
+                ret.results.insert(key, res);
+            }
+        }
+    }
+    ret
+}
+
+/// the units a [Gradient] was reported in by the program that produced it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GradientUnit {
+    /// kcal/mol/Å, as printed by MOPAC
+    KcalMolAng,
+    /// Eh/bohr, as printed by most ab initio programs
+    HartreeBohr,
+}
+
+/// a gradient vector along with its units
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    pub values: Vec<f64>,
+    pub unit: GradientUnit,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -24,6 +401,31 @@ pub enum ProgramError {
     EnergyNotFound(String),
     EnergyParseError(String),
     GeomNotFound(String),
+    GradientParseError(String),
+    /// a geometry optimization reached its maximum number of steps without
+    /// converging
+    OptNotConverged(String),
+    /// the SCF failed to converge, so the job may succeed with a better guess
+    /// or different convergence settings
+    ScfNotConverged(String),
+    /// the output file exists but ends before the program finished, either
+    /// because it is still running or because it was killed
+    Truncated(String),
+    /// more jobs failed or were resubmitted than allowed by
+    /// [crate::queue::SubQueue::retry_budget]
+    RetryBudgetExhausted(usize),
+    /// the fraction of failed jobs in a chunk exceeded the threshold in
+    /// [crate::queue::ChunkFailure]
+    ChunkFailure(String),
+    /// the output file is older than the job's submission time, as
+    /// determined by [Job::is_stale]
+    StaleOutput(String),
+    /// the contained job needed to be resubmitted, but resubmission was
+    /// disabled by the `NO_RESUB` or `SEMP_RESUB` environment variable
+    ResubmitDisabled(String),
+    /// writing, submitting, or checkpointing jobs failed with the contained
+    /// [crate::Error] message
+    Queue(String),
 }
 
 impl ProgramError {
@@ -34,6 +436,40 @@ impl ProgramError {
     pub fn is_error_in_output(&self) -> bool {
         matches!(self, Self::ErrorInOutput(..))
     }
+
+    /// Returns `true` if the program error is [`OptNotConverged`].
+    ///
+    /// [`OptNotConverged`]: ProgramError::OptNotConverged
+    #[must_use]
+    pub fn is_opt_not_converged(&self) -> bool {
+        matches!(self, Self::OptNotConverged(..))
+    }
+
+    /// Returns `true` if the job ran to completion but failed in a way that
+    /// resubmitting the same input won't fix, so it should be counted as a
+    /// failed job rather than resubmitted
+    #[must_use]
+    pub fn is_failure(&self) -> bool {
+        self.is_error_in_output()
+            || self.is_opt_not_converged()
+            || self.is_scf_not_converged()
+    }
+
+    /// Returns `true` if the program error is [`ScfNotConverged`].
+    ///
+    /// [`ScfNotConverged`]: ProgramError::ScfNotConverged
+    #[must_use]
+    pub fn is_scf_not_converged(&self) -> bool {
+        matches!(self, Self::ScfNotConverged(..))
+    }
+
+    /// Returns `true` if the program error is [`Truncated`].
+    ///
+    /// [`Truncated`]: ProgramError::Truncated
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, Self::Truncated(..))
+    }
 }
 
 impl Display for ProgramError {
@@ -42,7 +478,16 @@ impl Display for ProgramError {
     }
 }
 
-impl Error for ProgramError {}
+impl std::error::Error for ProgramError {}
+
+impl From<Error> for ProgramError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Program(e) => e,
+            e => Self::Queue(e.to_string()),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Procedure {
@@ -64,6 +509,76 @@ impl Template {
     }
 }
 
+impl Template {
+    /// Write a tiny H2 single-point input from `self` for the program `P` in
+    /// `dir`, run it with `binary`, and try to parse the result. This is much
+    /// heavier than checking the template by hand since it actually runs the
+    /// program, so it is only ever done when called explicitly. On failure,
+    /// the returned error includes the program's stderr
+    pub fn smoke_test<P: Program>(
+        &self,
+        binary: &str,
+        dir: impl AsRef<Path>,
+    ) -> Result<ProgramResult, String> {
+        let dir = dir.as_ref();
+        let filename = dir.join("smoke").to_str().unwrap().to_owned();
+        let mut prog = P::new(filename.clone(), self.clone(), 0, h2());
+        prog.write_input(Procedure::SinglePt)
+            .map_err(|e| format!("failed to write input with {e}"))?;
+        let infile = Path::new(&prog.infile()).file_name().unwrap().to_owned();
+        let output = Command::new(binary)
+            .arg(infile)
+            .current_dir(dir)
+            .output()
+            .map_err(|e| format!("failed to run {binary} with {e}"))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            return Err(format!(
+                "{binary} exited with {}, stderr:\n{stderr}",
+                output.status
+            ));
+        }
+        P::read_output(&filename).map_err(|e| {
+            format!("failed to read output with {e}, stderr:\n{stderr}")
+        })
+    }
+}
+
+/// run the program `binary` with `args`, in `dir` if provided, and return its
+/// output if it exits successfully. otherwise return an [Error::Io] naming
+/// `binary`, with its stderr if it ran at all
+pub(crate) fn run_binary(
+    binary: &str,
+    args: &[&str],
+    dir: Option<&Path>,
+) -> Result<std::process::Output, Error> {
+    let mut cmd = Command::new(binary);
+    cmd.args(args);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let output = cmd.output().map_err(|e| Error::io(binary, e))?;
+    if !output.status.success() {
+        return Err(Error::io(
+            binary,
+            std::io::Error::other(format!(
+                "exited with {}, stderr:\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )),
+        ));
+    }
+    Ok(output)
+}
+
+/// a tiny H2 geometry for checking that a program runs at all
+pub(crate) fn h2() -> Geom {
+    Geom::Xyz(vec![
+        Atom::new(1, 0.0, 0.0, 0.0),
+        Atom::new(1, 0.0, 0.0, 0.74),
+    ])
+}
+
 impl From<String> for Template {
     fn from(header: String) -> Self {
         Self { header }
@@ -94,6 +609,15 @@ pub trait Program {
     /// return the input file associated with `self`
     fn infile(&self) -> String;
 
+    /// return all of the input files written by `write_input`. defaults to
+    /// just `infile`, but this can be overridden for programs reading several
+    /// input files, like TURBOMOLE's `coord` and `control`, or ones with names
+    /// that don't follow `filename`, like CFOUR's `ZMAT`, which would be given
+    /// as `{dir}/ZMAT` for a job in its own directory
+    fn input_files(&self) -> Vec<String> {
+        vec![self.infile()]
+    }
+
     /// set `filename`
     fn set_filename(&mut self, filename: &str);
 
@@ -106,16 +630,90 @@ pub trait Program {
     /// molecular charge
     fn charge(&self) -> isize;
 
-    /// write the input file to the name returned by `filename`
-    fn write_input(&mut self, proc: Procedure);
+    /// the number of unpaired electrons, `2S`, like Molpro's `spin` setting.
+    /// defaults to 0, a closed-shell singlet
+    fn spin(&self) -> usize {
+        0
+    }
+
+    /// a short, human-readable description of the calculation for logging.
+    /// defaults to the filename
+    fn summary(&self) -> String {
+        self.filename()
+    }
+
+    /// return the last `n` lines of the output file, as found among
+    /// `associated_files`, for monitoring a running job. This only reads the
+    /// file, and an empty vector is returned if it has not been created yet
+    fn tail_output(&self, n: usize) -> Vec<String> {
+        let Some(outfile) = self
+            .associated_files()
+            .into_iter()
+            .find(|f| f.ends_with(".out"))
+        else {
+            return Vec::new();
+        };
+        tail_file(outfile, n)
+    }
+
+    /// write the input file to the name returned by `filename`, returning an
+    /// [Error::Io] if it cannot be written
+    fn write_input(&mut self, proc: Procedure) -> Result<(), Error>;
+
+    /// write the input files for all of `programs`. the default implementation
+    /// simply calls `write_input` on each of them, but programs with expensive
+    /// template processing can override this to share that work between
+    /// inputs with the same template
+    fn write_inputs<'a>(
+        programs: impl Iterator<Item = &'a mut Self>,
+        proc: Procedure,
+    ) -> Result<(), Error>
+    where
+        Self: 'a + Sized,
+    {
+        for p in programs {
+            p.write_input(proc)?;
+        }
+        Ok(())
+    }
 
     /// read the output file `filename`
     fn read_output(filename: &str) -> Result<ProgramResult, ProgramError>;
 
+    /// read the output file `filename` like [Program::read_output], but only
+    /// extract the quantities chosen by `select`. the energy, times, and errors
+    /// are always read. the default implementation reads everything and then
+    /// discards the quantities that were not selected
+    fn read_output_with(
+        filename: &str,
+        select: ParseSelectors,
+    ) -> Result<ProgramResult, ProgramError> {
+        Self::read_output(filename).map(|res| select.apply(res))
+    }
+
     /// Return all the filenames associated with the Program for deletion when
     /// it finishes
     fn associated_files(&self) -> Vec<String>;
 
+    /// check that the program binary at `path` exists and runs, and return
+    /// the version it reports, for catching a wrong or missing path before
+    /// submitting any jobs. the default implementation runs `path --version`
+    /// and returns the first non-empty line it prints
+    fn check_binary(path: &str) -> Result<String, Error> {
+        let output = run_binary(path, &["--version"], None)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        stdout
+            .lines()
+            .chain(stderr.lines())
+            .map(str::trim)
+            .find(|l| !l.is_empty())
+            .map(str::to_owned)
+            .ok_or_else(|| {
+                Error::io(path, std::io::Error::other("no version printed"))
+            })
+    }
+
     fn new(
         filename: String,
         template: Template,
@@ -171,6 +769,14 @@ pub struct Job<P: Program> {
 
     /// the last modified time of `program`'s output file
     pub(crate) modtime: SystemTime,
+
+    /// the time the job was last submitted, if it has been
+    pub(crate) submitted: Option<SystemTime>,
+
+    /// the quantities to read from the job's output, overriding the default
+    /// for the kind of run, which is only the energy for single points and
+    /// the geometry for optimizations
+    pub parse: Option<ParseSelectors>,
 }
 
 impl<P: Program> Job<P> {
@@ -182,17 +788,78 @@ impl<P: Program> Job<P> {
             index,
             coeff: 1.0,
             modtime: SystemTime::UNIX_EPOCH,
+            submitted: None,
+            parse: None,
         }
     }
 
+    /// return the last `n` lines of the job's output file. See
+    /// [Program::tail_output]
+    pub fn tail(&self, n: usize) -> Vec<String> {
+        self.program.tail_output(n)
+    }
+
     /// return the current modtime of `self.program`'s output file, or
     /// `self.modtime` if there is an error accessing the metadata
     pub fn modtime(&self) -> SystemTime {
-        let p = self.program.outfile();
-        if let Ok(meta) = std::fs::metadata(p) {
-            meta.modified().unwrap()
-        } else {
-            self.modtime
-        }
+        crate::fs::current()
+            .modified(&self.program.outfile())
+            .unwrap_or(self.modtime)
+    }
+
+    /// return `true` if `self.program`'s output file was last modified more
+    /// than `tolerance` before the job was submitted, meaning that it was left
+    /// over from a previous run reusing the same filename. returns `false` if
+    /// the job has not been submitted or the output file cannot be accessed.
+    /// `tolerance` allows for clock skew between the submitting machine and
+    /// the one writing the output, as on networked filesystems
+    pub fn is_stale(&self, tolerance: Duration) -> bool {
+        let Some(submitted) = self.submitted else {
+            return false;
+        };
+        crate::fs::current()
+            .modified(&self.program.outfile())
+            .is_ok_and(|modified| modified + tolerance < submitted)
     }
 }
+
+/// return the sorted, deduplicated indices of the jobs in `jobs` without an
+/// output file, either plain or gzipped, along with the submit scripts that
+/// should have run them, for validating an array of jobs run outside of
+/// [crate::queue::Queue::drain] or with `no_del` set. Sub-jobs that never ran,
+/// as after a node failure or preemption, leave no error in any output, so
+/// this is the only way to find the gaps for resubmission. An index is
+/// reported if any of the jobs storing a result there is missing its output.
+/// The script identifies the element of an array job, whose sub-jobs all share
+/// a job id
+pub fn missing_outputs<P: Program>(jobs: &[Job<P>]) -> Vec<(usize, String)> {
+    let fs = crate::fs::current();
+    let mut ret: Vec<_> = jobs
+        .iter()
+        .filter(|job| {
+            let outfile = job.program.outfile();
+            !fs.exists(&outfile) && !fs.exists(&format!("{outfile}.gz"))
+        })
+        .map(|job| (job.index, job.pbs_file.clone()))
+        .collect();
+    ret.sort_unstable();
+    ret.dedup();
+    ret
+}
+
+/// return the last `n` lines of `path`, read through the current
+/// [crate::fs::FileSystem], or an empty vector if it cannot be read. Invalid
+/// UTF-8, as may occur at the end of a partially-written file, is replaced
+/// rather than treated as an error
+pub fn tail_file(path: impl AsRef<Path>, n: usize) -> Vec<String> {
+    let path = path.as_ref().to_string_lossy();
+    let Ok(bytes) = crate::fs::current().read(&path) else {
+        return Vec::new();
+    };
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines: Vec<_> = contents.lines().collect();
+    lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
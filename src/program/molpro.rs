@@ -1,14 +1,19 @@
 use std::{
-    fs::{read_to_string, File},
-    sync::OnceLock,
+    cell::RefCell,
+    str::FromStr,
+    sync::{LazyLock, OnceLock, RwLock},
 };
 
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::geom::{geom_string, Geom};
+use crate::{fs, Error};
 
-use super::{Procedure, Program, ProgramError, ProgramResult, Template};
+use super::{
+    Gradient, GradientUnit, OptConvergence, ParseSelectors, Procedure, Program,
+    ProgramError, ProgramResult, SpinContamination, Template, Timings,
+};
 
 #[cfg(test)]
 mod tests;
@@ -19,10 +24,472 @@ pub struct Molpro {
     template: Template,
     charge: isize,
     geom: Geom,
+
+    /// the number of unpaired electrons, substituted for `{{.spin}}` in the
+    /// template by [Program::write_input]. defaults to 0
+    pub spin: usize,
+
+    /// whether to emit the `nocompress` directive. `None` leaves the template
+    /// unchanged, while `Some(true)` adds the directive if it is missing and
+    /// `Some(false)` removes it
+    pub nocompress: Option<bool>,
+
+    /// isotopic mass overrides as `(atom index, mass in amu)` pairs, with the
+    /// atom indices starting from 0. the atoms are relabeled as their symbol
+    /// followed by their 1-based index in the geometry, and a `mass` directive
+    /// assigning their masses is emitted after it. other atoms keep Molpro's
+    /// default masses
+    pub masses: Vec<(usize, f64)>,
+
+    /// overrides for the `gthresh` thresholds in the template
+    pub thresholds: Thresholds,
+
+    /// a structured basis set specification to render in place of the
+    /// template's `basis` section. `None` uses the template's section as-is
+    pub basis: Option<BasisSpec>,
+
+    /// the names of Z-matrix variables to hold fixed during geometry
+    /// optimizations, emitted as an `inactive` directive in the `optg` block.
+    /// these must be defined in the Z-matrix, so freezing coordinates is not
+    /// supported for Cartesian geometries. defaults to empty, optimizing all
+    /// of the variables
+    pub frozen: Vec<String>,
+
+    /// auxiliary basis sets for density fitting and F12 methods
+    pub aux_basis: AuxBasis,
+
+    /// the initial orbital guess for the first Hartree-Fock calculation in the
+    /// template. defaults to `None`, using Molpro's default guess
+    pub guess: Option<Guess>,
+
+    /// the maximum number of SCF iterations for the first Hartree-Fock
+    /// calculation in the template, via `maxit`. must be positive if set.
+    /// defaults to `None`, using Molpro's default
+    pub maxit: Option<usize>,
+
+    /// whether to use DIIS in the first Hartree-Fock calculation in the
+    /// template. `Some(false)` emits `nodiis`, while `None` and `Some(true)`
+    /// leave Molpro's default of using DIIS
+    pub diis: Option<bool>,
+
+    /// the path to an external file of point charges to embed the molecule
+    /// in, as written by an MM engine, emitted as a `lattice,infile=`
+    /// directive after the geometry instead of inlining the charges. the file
+    /// must exist when the input is written. it is not included in
+    /// [Program::associated_files] since it is usually shared by many jobs.
+    /// defaults to `None`
+    pub point_charges: Option<String>,
+}
+
+/// Initial orbital guesses for Hartree-Fock, rendered as a `start` directive
+/// on the `hf`, `rhf`, or `uhf` line of the template
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Guess {
+    /// eigenvectors of the core Hamiltonian, `start,h0`
+    Core,
+
+    /// a superposition of atomic densities, `start,atden`
+    AtomicDensity,
+
+    /// orbitals saved in a record of a previous run, like `2100.2`, which
+    /// requires the run's wave function file to be restored
+    Record(String),
+}
+
+impl Guess {
+    fn directive(&self) -> Result<String, Error> {
+        match self {
+            Guess::Core => Ok("start,h0".to_owned()),
+            Guess::AtomicDensity => Ok("start,atden".to_owned()),
+            Guess::Record(r) if r.parse::<f64>().is_ok() => {
+                Ok(format!("start,{r}"))
+            }
+            Guess::Record(r) => Err(Error::Input(format!(
+                "invalid orbital record `{r}` for guess"
+            ))),
+        }
+    }
 }
 
-static CELL: OnceLock<[Regex; 6]> = OnceLock::new();
-static INPUT_CELL: OnceLock<[Regex; 4]> = OnceLock::new();
+/// Auxiliary basis sets for explicitly-correlated methods. Each one that is
+/// set is added to the template's basis block as a named set, like
+///
+/// ```text
+/// set,jkfit
+/// default,cc-pVTZ-F12/jkfit
+/// ```
+///
+/// and passed to the F12 methods in the template by the `df_basis_exch`,
+/// `df_basis`, and `ri_basis` options, respectively. `None` leaves the choice
+/// to Molpro's automatic selection
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuxBasis {
+    /// the density fitting basis for the Fock and exchange matrices
+    pub jkfit: Option<String>,
+
+    /// the density fitting basis for the MP2 and F12 integrals
+    pub mp2fit: Option<String>,
+
+    /// the complementary auxiliary basis for the resolution of the identity
+    pub cabs: Option<String>,
+}
+
+impl AuxBasis {
+    /// the `(set name, method option, basis)` triples for the fields that are
+    /// set
+    fn sets(&self) -> Vec<(&str, &str, &str)> {
+        [
+            ("jkfit", "df_basis_exch", &self.jkfit),
+            ("mp2fit", "df_basis", &self.mp2fit),
+            ("ri", "ri_basis", &self.cabs),
+        ]
+        .into_iter()
+        .filter_map(|(set, opt, b)| b.as_deref().map(|b| (set, opt, b)))
+        .collect()
+    }
+}
+
+/// A structured description of a Molpro `basis={...}` block, rendered as
+///
+/// ```text
+/// basis={
+/// default,{default}
+/// {element}={basis}
+/// ecp,{element},{ecp}
+/// }
+/// ```
+///
+/// with one line for each entry in `atoms` and `ecp`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BasisSpec {
+    /// the basis set used for any element without an override
+    pub default: String,
+
+    /// per-element basis set overrides as `(element, basis)` pairs
+    pub atoms: Vec<(String, String)>,
+
+    /// effective core potentials as `(element, ecp)` pairs
+    pub ecp: Vec<(String, String)>,
+}
+
+impl BasisSpec {
+    pub fn new(default: &str) -> Self {
+        Self {
+            default: default.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// render `self` as a Molpro basis block, including a trailing newline
+    pub fn render(&self) -> String {
+        use std::fmt::Write;
+        let mut ret = format!("basis={{\ndefault,{}\n", self.default);
+        for (elem, basis) in &self.atoms {
+            writeln!(ret, "{elem}={basis}").unwrap();
+        }
+        for (elem, ecp) in &self.ecp {
+            writeln!(ret, "ecp,{elem},{ecp}").unwrap();
+        }
+        ret.push_str("}\n");
+        ret
+    }
+
+    /// the names of the basis sets in `self`, excluding the ECPs
+    fn names(&self) -> Vec<String> {
+        std::iter::once(&self.default)
+            .chain(self.atoms.iter().map(|(_, b)| b))
+            .cloned()
+            .collect()
+    }
+
+    /// return an [Error::Input] if any of the elements with an override or ECP
+    /// do not appear in `geom`
+    fn check(&self, geom: &Geom) -> Result<(), Error> {
+        let elements = elements(geom);
+        for (elem, _) in self.atoms.iter().chain(&self.ecp) {
+            if !elements.iter().any(|e| e.eq_ignore_ascii_case(elem)) {
+                return Err(Error::Input(format!(
+                    "basis set given for element {elem} not in the geometry"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// return the element symbols in `geom`, stripping any numeric suffixes from
+/// Z-matrix labels
+fn elements(geom: &Geom) -> Vec<String> {
+    match geom {
+        Geom::Xyz(atoms) => {
+            atoms.iter().map(|a| a.label().to_owned()).collect()
+        }
+        Geom::Zmat(zmat) => zmat
+            .lines()
+            .map(str::trim)
+            .take_while(|l| !l.contains('='))
+            .filter_map(|l| {
+                l.split(|c: char| c == ',' || c.is_whitespace()).next()
+            })
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                l.trim_end_matches(|c: char| c.is_ascii_digit()).to_owned()
+            })
+            .collect(),
+    }
+}
+
+/// replace the `basis` section of `body`, either a single `basis=name` line or
+/// a `basis={...}` block, with `block`. if there is no basis section, `block`
+/// is inserted before the first `set` line, or at the end if there are none
+fn set_basis(body: &str, block: &str) -> String {
+    let mut ret = String::with_capacity(body.len());
+    let mut in_block = false;
+    let mut found = false;
+    for line in body.lines() {
+        let lower = line.trim().to_lowercase();
+        if in_block {
+            in_block = !line.trim().starts_with('}');
+            continue;
+        } else if !found && lower.starts_with("basis=") {
+            found = true;
+            in_block = lower.starts_with("basis={") && !lower.ends_with('}');
+            ret.push_str(block);
+            continue;
+        } else if !found && lower.starts_with("set,") {
+            found = true;
+            ret.push_str(block);
+        }
+        ret.push_str(line);
+        ret.push('\n');
+    }
+    if !found {
+        ret.push_str(block);
+    }
+    ret
+}
+
+/// Overrides for the most commonly tuned `gthresh` thresholds. Each value must
+/// be a valid Fortran floating-point literal, such as `1.d-12`. `None` leaves
+/// the value in the template unchanged, otherwise the value is replaced or
+/// added to the template
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub energy: Option<String>,
+    pub optgrad: Option<String>,
+    pub optstep: Option<String>,
+}
+
+impl Thresholds {
+    fn iter(&self) -> impl Iterator<Item = (&str, &String)> {
+        [
+            ("energy", &self.energy),
+            ("optgrad", &self.optgrad),
+            ("optstep", &self.optstep),
+        ]
+        .into_iter()
+        .filter_map(|(k, v)| v.as_ref().map(|v| (k, v)))
+    }
+}
+
+/// A typed description of the [Template]s usually used with Molpro. The
+/// default value reproduces the example template documented on
+/// [Molpro::write_input], and `build` renders the fields into the header.
+/// Raw string templates remain fully supported for anything this does not
+/// cover.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TemplateBuilder {
+    /// the argument to the `memory` directive, such as `1,g`
+    pub memory: String,
+
+    /// `gthresh` settings as `(name, value)` pairs, like `("energy",
+    /// "1.d-12")`
+    pub gthresh: Vec<(String, String)>,
+
+    /// whether to emit the `nocompress` directive
+    pub nocompress: bool,
+
+    /// the default basis set, emitted as `default,{basis}` in the basis block
+    pub basis: String,
+
+    /// the method lines following the charge and spin settings
+    pub method: Vec<String>,
+}
+
+impl Default for TemplateBuilder {
+    fn default() -> Self {
+        let gthresh = [
+            ("energy", "1.d-12"),
+            ("zero", "1.d-22"),
+            ("oneint", "1.d-22"),
+            ("twoint", "1.d-22"),
+            ("optgrad", "1.d-8"),
+            ("optstep", "1.d-8"),
+        ];
+        Self {
+            memory: "1,g".to_owned(),
+            gthresh: gthresh
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            nocompress: true,
+            basis: "cc-pVTZ-f12".to_owned(),
+            method: vec![
+                "hf,accuracy=16,energy=1.0d-10".to_owned(),
+                "{CCSD(T)-F12,thrden=1.0d-8,thrvar=1.0d-10}".to_owned(),
+            ],
+        }
+    }
+}
+
+impl TemplateBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn memory(mut self, memory: &str) -> Self {
+        self.memory = memory.to_owned();
+        self
+    }
+
+    /// set the `gthresh` option `name` to `value`, replacing any existing
+    /// value. `value` is checked to be a valid Fortran float by
+    /// [TemplateBuilder::build]
+    pub fn gthresh(mut self, name: &str, value: &str) -> Self {
+        match self.gthresh.iter_mut().find(|(k, _)| k == name) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self.gthresh.push((name.to_owned(), value.to_owned())),
+        }
+        self
+    }
+
+    pub fn nocompress(mut self, nocompress: bool) -> Self {
+        self.nocompress = nocompress;
+        self
+    }
+
+    pub fn basis(mut self, basis: &str) -> Self {
+        self.basis = basis.to_owned();
+        self
+    }
+
+    /// replace the method lines with `method`
+    pub fn method(mut self, method: &[&str]) -> Self {
+        self.method = method.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// render the fields into a [Template] with `{{.geom}}`, `{{.charge}}`,
+    /// and `{{.spin}}` placeholders. returns an [Error::Input] if any
+    /// `gthresh` value is not a valid Fortran float
+    pub fn build(&self) -> Result<Template, Error> {
+        use std::fmt::Write;
+        if let Some((name, value)) =
+            self.gthresh.iter().find(|(_, v)| !is_fortran_float(v))
+        {
+            return Err(Error::Input(format!(
+                "invalid value `{value}` for gthresh option {name}"
+            )));
+        }
+        let mut header = String::new();
+        writeln!(header, "memory,{}", self.memory).unwrap();
+        if !self.gthresh.is_empty() {
+            let settings: Vec<_> = self
+                .gthresh
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect();
+            writeln!(header, "gthresh,{};", settings.join(",")).unwrap();
+        }
+        if self.nocompress {
+            writeln!(header, "nocompress;").unwrap();
+        }
+        writeln!(header, "\ngeometry={{\n{{{{.geom}}}}").unwrap();
+        writeln!(header, "basis={{\ndefault,{}\n}}", self.basis).unwrap();
+        writeln!(header, "set,charge={{{{.charge}}}}\nset,spin={{{{.spin}}}}")
+            .unwrap();
+        for line in &self.method {
+            writeln!(header, "{line}").unwrap();
+        }
+        Ok(Template { header })
+    }
+}
+
+/// Common basis sets from the Molpro basis library, used by
+/// [Molpro::check_basis] to catch typos before a job is submitted. Molpro
+/// itself treats basis names case-insensitively, so they are compared that way
+/// here too
+#[rustfmt::skip]
+pub static KNOWN_BASES: &[&str] = &[
+    "STO-3G", "3-21G", "6-31G", "6-31G*", "6-31G**", "6-31+G*", "6-31+G**",
+    "6-31++G**", "6-311G", "6-311G*", "6-311G**", "6-311+G*", "6-311+G**",
+    "6-311++G**", "cc-pVDZ", "cc-pVTZ", "cc-pVQZ", "cc-pV5Z", "cc-pV6Z",
+    "aug-cc-pVDZ", "aug-cc-pVTZ", "aug-cc-pVQZ", "aug-cc-pV5Z", "aug-cc-pV6Z",
+    "cc-pV(D+d)Z", "cc-pV(T+d)Z", "cc-pV(Q+d)Z", "cc-pV(5+d)Z",
+    "aug-cc-pV(D+d)Z", "aug-cc-pV(T+d)Z", "aug-cc-pV(Q+d)Z", "cc-pCVDZ",
+    "cc-pCVTZ", "cc-pCVQZ", "cc-pCV5Z", "aug-cc-pCVDZ", "aug-cc-pCVTZ",
+    "aug-cc-pCVQZ", "cc-pwCVDZ", "cc-pwCVTZ", "cc-pwCVQZ", "cc-pwCV5Z",
+    "aug-cc-pwCVDZ", "aug-cc-pwCVTZ", "aug-cc-pwCVQZ", "cc-pVDZ-F12",
+    "cc-pVTZ-F12", "cc-pVQZ-F12", "cc-pCVDZ-F12", "cc-pCVTZ-F12",
+    "cc-pCVQZ-F12", "cc-pVDZ-DK", "cc-pVTZ-DK", "cc-pVQZ-DK", "cc-pV5Z-DK",
+    "cc-pVDZ-PP", "cc-pVTZ-PP", "cc-pVQZ-PP", "aug-cc-pVDZ-PP",
+    "aug-cc-pVTZ-PP", "aug-cc-pVQZ-PP", "def2-SVP", "def2-SVPD", "def2-TZVP",
+    "def2-TZVPP", "def2-TZVPD", "def2-TZVPPD", "def2-QZVP", "def2-QZVPP",
+    "def2-QZVPD", "def2-QZVPPD", "VDZ", "VTZ", "VQZ", "V5Z", "AVDZ", "AVTZ",
+    "AVQZ", "AV5Z", "VDZ-F12", "VTZ-F12", "VQZ-F12", "CVDZ-F12", "CVTZ-F12",
+    "CVQZ-F12",
+];
+
+/// the number of Angstrom in a Bohr, for converting the `ATOMIC COORDINATES`
+/// block, which Molpro prints in Bohr
+pub const ANG_BOHR: f64 = 0.529177210903;
+
+/// the messages printed by Molpro when the SCF fails to converge
+const SCF_NOT_CONVERGED: [&str; 2] = [
+    "NO CONVERGENCE",
+    "No convergence in max. number of iterations",
+];
+
+static CELL: OnceLock<[Regex; 7]> = OnceLock::new();
+static MULTIPOLE_CELL: OnceLock<[Regex; 2]> = OnceLock::new();
+static INPUT_CELL: OnceLock<[Regex; 6]> = OnceLock::new();
+static FLOAT_CELL: OnceLock<Regex> = OnceLock::new();
+static TOTAL_CELL: OnceLock<Regex> = OnceLock::new();
+
+static ENERGY_PATTERN: LazyLock<RwLock<Option<Regex>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+thread_local! {
+    static THREAD_ENERGY_PATTERN: RefCell<Option<Regex>> =
+        const { RefCell::new(None) };
+}
+
+/// return the energy pattern in use on the current thread, which is the one
+/// installed with [set_energy_pattern] if there is one and otherwise the one
+/// installed with [set_global_energy_pattern], if any
+pub fn energy_pattern() -> Option<Regex> {
+    THREAD_ENERGY_PATTERN
+        .with(|p| p.borrow().clone())
+        .or_else(|| ENERGY_PATTERN.read().unwrap().clone())
+}
+
+/// use `pattern` to find the energy in [Molpro::read_output] on the current
+/// thread, or restore the default with `None`, and return the previous
+/// pattern for this thread. the energy is the last field of the last line
+/// matching `pattern`, so a pattern like `^ MP2 total energy` or `^ !RHF
+/// STATE \S+ Energy` can select the energy of a particular method. by
+/// default, the energy is taken from the `PBQFF` variable printed by the
+/// templates, or from the last correlated total energy or SCF energy line if
+/// there is no such variable
+pub fn set_energy_pattern(pattern: Option<Regex>) -> Option<Regex> {
+    THREAD_ENERGY_PATTERN.with(|p| p.replace(pattern))
+}
+
+/// like [set_energy_pattern], but for every thread without a pattern installed
+/// by [set_energy_pattern], including those reading outputs in parallel
+pub fn set_global_energy_pattern(pattern: Option<Regex>) -> Option<Regex> {
+    std::mem::replace(&mut *ENERGY_PATTERN.write().unwrap(), pattern)
+}
 
 impl Program for Molpro {
     fn new(
@@ -36,6 +503,17 @@ impl Program for Molpro {
             template,
             charge,
             geom,
+            spin: 0,
+            nocompress: None,
+            masses: Vec::new(),
+            thresholds: Thresholds::default(),
+            basis: None,
+            frozen: Vec::new(),
+            aux_basis: AuxBasis::default(),
+            guess: None,
+            maxit: None,
+            diis: None,
+            point_charges: None,
         }
     }
 
@@ -59,6 +537,33 @@ impl Program for Molpro {
         self.charge
     }
 
+    fn spin(&self) -> usize {
+        self.spin
+    }
+
+    /// summarize the calculation as `Molpro method/basis charge=c (n atoms)`,
+    /// taking the method from the last line of the template
+    fn summary(&self) -> String {
+        let header = &self.template.header;
+        let method = header
+            .lines()
+            .map(|l| l.trim().trim_start_matches('{'))
+            .filter(|l| !l.is_empty() && !l.to_lowercase().starts_with("optg"))
+            .last()
+            .and_then(|l| l.split(',').next())
+            .map(|l| l.trim_end_matches('}'))
+            .unwrap_or("unknown");
+        let level = match self.basis_names().first() {
+            Some(basis) => format!("{method}/{basis}"),
+            None => method.to_owned(),
+        };
+        format!(
+            "Molpro {level} charge={} ({} atoms)",
+            self.charge,
+            self.geom.natoms()
+        )
+    }
+
     /// Example [Template]:
     /// ```text
     /// memory,1,g
@@ -73,102 +578,72 @@ impl Program for Molpro {
     /// default,cc-pVTZ-f12
     /// }
     /// set,charge={{.charge}}
-    /// set,spin=0
+    /// set,spin={{.spin}}
     /// hf,accuracy=16,energy=1.0d-10
     /// {CCSD(T)-F12,thrden=1.0d-8,thrvar=1.0d-10}
     /// {optg,grms=1.d-8,srms=1.d-8}
     /// ```
     ///
     /// In line with [Go templates](https://pkg.go.dev/text/template),
-    /// `{{.geom}}` is replaced with `self.geom`, and `{{.charge}}` is
-    /// replaced with `self.charge`. If `proc` is `Procedure::Opt`, and the
-    /// template includes this optg line, the line is left there. If the
-    /// procedure is `Opt` and the line is absent, it will be added.
+    /// `{{.geom}}` is replaced with `self.geom`, `{{.charge}}` is replaced
+    /// with `self.charge`, and `{{.spin}}` is replaced with `self.spin`. If
+    /// `proc` is `Procedure::Opt`, and the template includes this optg line,
+    /// the line is left there. If the procedure is `Opt` and the line is
+    /// absent, it will be added.
     /// Similarly, if `proc` is not `Opt` and the line is present in the
     /// template, it will be deleted.
     ///
     /// The missing closing brace around the geometry allows for easier handling
     /// of ZMAT inputs since `write_input` can insert its own closing brace
     /// between the ZMAT and parameter values.
-    fn write_input(&mut self, proc: Procedure) {
-        use std::io::Write;
-        let mut body = self.template().clone().header;
-        // skip optgrad but accept optg at the end of a line
-        let [opt, optg_line, charge, geom_re] = INPUT_CELL.get_or_init(|| {
-            [
-                Regex::new(r"(?i)optg(,|\s*$)").unwrap(),
-                Regex::new(r"(?i)^.*optg(,|\s*$)").unwrap(),
-                Regex::new(r"\{\{.charge\}\}").unwrap(),
-                Regex::new(r"\{\{.geom\}\}").unwrap(),
-            ]
-        });
-        let mut found_opt = false;
-        if opt.is_match(&body) {
-            found_opt = true;
-        }
-        {
-            use std::fmt::Write;
-            match proc {
-                Procedure::Opt => {
-                    if !found_opt {
-                        writeln!(body, "{{optg,grms=1.d-8,srms=1.d-8}}")
-                            .unwrap();
-                    }
-                }
-                Procedure::Freq => todo!(),
-                Procedure::SinglePt => {
-                    if found_opt {
-                        let mut new = String::new();
-                        for line in body.lines() {
-                            if !optg_line.is_match(line) {
-                                writeln!(new, "{line}").unwrap();
-                            }
-                        }
-                        body = new;
-                    }
-                }
+    fn write_input(&mut self, proc: Procedure) -> Result<(), Error> {
+        let body = self.prepare_template(proc)?;
+        self.write_prepared(&body)
+    }
+
+    /// Write the input files for `programs`, processing each distinct
+    /// template and charge only once and then substituting the geometry for
+    /// each input. This produces the same files as calling `write_input` on
+    /// each program, but it avoids repeating the template processing for runs
+    /// of programs sharing the same template, as in a large set of single
+    /// points at different geometries
+    fn write_inputs<'a>(
+        programs: impl Iterator<Item = &'a mut Self>,
+        proc: Procedure,
+    ) -> Result<(), Error>
+    where
+        Self: 'a + Sized,
+    {
+        // the program the prepared template was built from
+        let mut prev: Option<(Self, String)> = None;
+        for p in programs {
+            let reuse = matches!(&prev, Some((q, _)) if q.same_template(p));
+            if !reuse {
+                prev = Some((p.clone(), p.prepare_template(proc)?));
             }
+            p.write_prepared(&prev.as_ref().unwrap().1)?;
         }
-        let geom = geom_string(&self.geom);
-        let geom = if let Geom::Zmat(_) = &self.geom {
-            use std::fmt::Write;
-            let mut new_lines = String::new();
-            let mut found = false;
-            for line in geom.lines() {
-                if line.contains('=') && !found {
-                    found = true;
-                    new_lines.push_str("}\n");
-                }
-                writeln!(new_lines, "{line}").unwrap();
-            }
-            new_lines
-        } else {
-            format!("{geom}\n}}\n")
-        };
-        body = geom_re.replace(&body, geom).to_string();
-        body = charge
-            .replace(&body, &format!("{}", self.charge))
-            .to_string();
-
-        let filename = format!("{}.{}", self.filename, self.extension());
-        let mut file = match File::create(&filename) {
-            Ok(f) => f,
-            Err(e) => panic!("failed to create {filename} with {e}"),
-        };
-        write!(file, "{body}").expect("failed to write input file");
+        Ok(())
     }
 
     fn read_output(filename: &str) -> Result<ProgramResult, ProgramError> {
+        Self::read_output_with(filename, ParseSelectors::all())
+    }
+
+    fn read_output_with(
+        filename: &str,
+        select: ParseSelectors,
+    ) -> Result<ProgramResult, ProgramError> {
         let outfile = format!("{}.out", &filename);
-        let contents = match read_to_string(&outfile) {
+        let contents = match fs::current().read_to_string(&outfile) {
             Ok(s) => s,
             Err(_) => {
                 return Err(ProgramError::FileNotFound(outfile));
             }
         };
 
-        let [panic_re, error_re, geom_re, blank_re, time_re, energy_re] = CELL
-            .get_or_init(|| {
+        let [panic_re, err_re, geom_re, blank_re, time_re, energy_re, cpu_re] =
+            CELL.get_or_init(|| {
                 [
                     Regex::new("(?i)panic").unwrap(),
                     Regex::new(r"(?i)\berror\b").unwrap(),
@@ -176,23 +651,216 @@ impl Program for Molpro {
                     Regex::new(r"^\s*$").unwrap(),
                     Regex::new(r"^ REAL TIME").unwrap(),
                     Regex::new(r"^ PBQFF\s+=").unwrap(),
+                    Regex::new(r"^ CPU TIMES").unwrap(),
                 ]
             });
+        // the fallback for outputs without a PBQFF line, like !CCSD(T)-F12b
+        // total energy or !RHF STATE 1.1 Energy
+        let total_re = TOTAL_CELL.get_or_init(|| {
+            Regex::new(r"^ !\S+ (total energy|STATE\s+\S+ Energy)\s").unwrap()
+        });
+        let [dipole_re, quad_re] = MULTIPOLE_CELL.get_or_init(|| {
+            [
+                Regex::new(r"^ !\S+ STATE\s+\S+ Dipole moment").unwrap(),
+                Regex::new(r"^ !\S+ STATE\s+\S+ Quadrupole moment").unwrap(),
+            ]
+        });
 
         if panic_re.is_match(&contents) {
             panic!("panic requested in read_output");
-        } else if error_re.is_match(&contents) {
+        } else if SCF_NOT_CONVERGED.iter().any(|s| contents.contains(s)) {
+            // checked before other errors since Molpro also reports these as
+            // an error
+            return Err(ProgramError::ScfNotConverged(outfile));
+        } else if err_re.is_match(&contents) {
             return Err(ProgramError::ErrorInOutput(outfile));
+        } else if contents.contains("No convergence in geometry optimization") {
+            // the energy of the last step is not a converged result
+            return Err(ProgramError::OptNotConverged(outfile));
         }
 
+        let pattern = energy_pattern();
         let mut energy = None;
+        let mut total_energy = None;
         let mut skip = 0;
         let mut geom = false;
         let mut atoms = Vec::new();
+        let mut bohr = false;
+        // only report the geometry of optimizations, since single points just
+        // echo their input geometry
+        let optimized = contents.contains(" END OF GEOMETRY OPTIMIZATION");
         let mut time = 0.0;
+        let mut cpu_time = None;
+        let mut opt_steps = Vec::new();
+        let mut in_steps = false;
+        let mut dipole = None;
+        let mut quadrupole = None;
+        let mut convergence = None;
+        let (mut optgrad, mut optstep) = (None, None);
+        let (mut nbasis, mut nprimitive) = (None, None);
+        let mut timings = Timings::default();
+        let mut in_timings = false;
+        let mut programs = Vec::new();
+        let mut spin = 0.0;
+        let mut spin_contamination = None;
+        let mut gradient = Vec::new();
+        let mut in_gradient = false;
+        let mut frequencies = Vec::new();
+        // the sign to apply to the wavenumbers in the current normal mode
+        // section, or `None` to skip them
+        let mut freq_sign = None;
         for line in contents.lines() {
             if skip > 0 {
                 skip -= 1;
+            } else if pattern.as_ref().is_some_and(|p| p.is_match(line)) {
+                energy = Some(last_energy(line, &outfile)?);
+            } else if select.timings
+                && line.starts_with(" Timing summary (sec):")
+            {
+                // skip the blank line and the STEP CPU(USER) SYS CPU(TOT) WALL
+                // header
+                skip = 2;
+                in_timings = true;
+            } else if in_timings {
+                let sp: Vec<_> = line.split_whitespace().collect();
+                match sp.len().checked_sub(4).and_then(|n| {
+                    Some((sp[..n].join(" "), sp[n + 2].parse().ok()?))
+                }) {
+                    Some((step, t)) if !step.is_empty() => {
+                        Timings::add(&mut timings.steps, &step, t)
+                    }
+                    _ => in_timings = false,
+                }
+            } else if select.gradient && line.contains(" GRADIENT FOR STATE ") {
+                // SCF GRADIENT FOR STATE 1.1, followed by a blank line, the
+                // Atom dE/dx dE/dy dE/dz header, and another blank line. only
+                // keep the last one, at the final geometry
+                skip = 3;
+                in_gradient = true;
+                gradient.clear();
+            } else if in_gradient {
+                // 1 0.000000000 0.000000000 -0.005715370
+                let sp: Vec<_> = line.split_whitespace().collect();
+                let row: Option<Vec<f64>> = match sp[..] {
+                    [atom, x, y, z] if atom.parse::<usize>().is_ok() => {
+                        [x, y, z].iter().map(|s| s.parse().ok()).collect()
+                    }
+                    _ => None,
+                };
+                match row {
+                    Some(row) => gradient.extend(row),
+                    None => in_gradient = false,
+                }
+            } else if select.spin && line.starts_with(" SETTING SPIN ") {
+                // SETTING SPIN = 1.00000000
+                spin = last_values(line).map_or(spin, |[s]| s);
+            } else if select.spin
+                && line.starts_with(" Spin contamination <S**2")
+            {
+                // Spin contamination <S**2-Sz**2-Sz> 0.00341310, printed after
+                // each open-shell correlated calculation, so keep the last one
+                // like the energy
+                spin_contamination = last_values(line).map(|[excess]| {
+                    SpinContamination::from_excess(spin, excess)
+                });
+            } else if select.timings && line.starts_with(" PROGRAMS   *") {
+                // PROGRAMS * TOTAL CCSD(T) HF-SCF INT, with the most recent
+                // program first
+                programs = line.split_whitespace().skip(3).collect();
+            } else if select.optimization
+                && line.starts_with(" ITER.")
+                && line.contains("GRADMAX")
+            {
+                in_steps = true;
+            } else if in_steps {
+                // ITER. ENERGY(OLD) ENERGY(NEW) DE GRADMAX GRADNORM GRADRMS
+                // STEPMAX STEPLEN STEPRMS CPU-time
+                let sp: Vec<_> = line.split_whitespace().collect();
+                match (sp.get(2), sp.get(4)) {
+                    (Some(e), Some(g)) => {
+                        if let (Ok(e), Ok(g)) =
+                            (e.parse::<f64>(), g.parse::<f64>())
+                        {
+                            opt_steps.push((e, g));
+                        }
+                        let get = |i: usize| sp.get(i)?.parse().ok();
+                        if let (Some(gr), Some(sm), Some(sr)) =
+                            (get(6), get(7), get(9))
+                        {
+                            convergence = Some(OptConvergence {
+                                grad_max: g.parse().unwrap_or_default(),
+                                grad_rms: gr,
+                                step_max: sm,
+                                step_rms: sr,
+                                ..Default::default()
+                            });
+                        }
+                    }
+                    _ => in_steps = false,
+                }
+            } else if select.optimization
+                && (line.contains("OPTGRAD =") || line.contains("OPTSTEP ="))
+            {
+                // the thresholds are printed before each program, so keep the
+                // last ones
+                optgrad = fortran_value(line, "OPTGRAD").or(optgrad);
+                optstep = fortran_value(line, "OPTSTEP").or(optstep);
+            } else if select.basis
+                && line.starts_with(" NUMBER OF PRIMITIVE AOS:")
+            {
+                // NUMBER OF PRIMITIVE AOS: 67
+                nprimitive = nprimitive.or_else(|| nth_value(line, 4));
+            } else if select.basis
+                && line.starts_with(" NUMBER OF CONTRACTIONS:")
+            {
+                // NUMBER OF CONTRACTIONS: 52 ( 38A' + 14A" )
+                nbasis = nbasis.or_else(|| nth_value(line, 3));
+            } else if select.frequencies
+                && line.trim_start().starts_with("Normal Modes")
+            {
+                // Normal Modes, then Normal Modes of imaginary frequencies and
+                // of low/zero frequencies, which are the translations and
+                // rotations. only keep the last frequency calculation
+                let header = line.trim();
+                if header == "Normal Modes" {
+                    frequencies.clear();
+                    freq_sign = Some(1.0);
+                } else if header.contains("imaginary") {
+                    freq_sign = Some(-1.0);
+                } else {
+                    freq_sign = None;
+                }
+            } else if select.frequencies
+                && line.starts_with(" Wavenumbers [cm-1]")
+            {
+                // Wavenumbers [cm-1] 1648.58 3794.37 3896.43
+                if let Some(sign) = freq_sign {
+                    frequencies.extend(
+                        line.split_whitespace()
+                            .skip(2)
+                            .filter_map(|s| s.parse::<f64>().ok())
+                            .map(|f: f64| sign * f.abs()),
+                    );
+                }
+            } else if select.multipoles && dipole_re.is_match(line) {
+                dipole = last_values(line);
+            } else if select.multipoles && quad_re.is_match(line) {
+                quadrupole = last_values(line);
+            } else if cpu_re.is_match(line) {
+                cpu_time = line
+                    .split_ascii_whitespace()
+                    .nth(3)
+                    .and_then(|s| s.parse().ok());
+                // the line is cumulative, so only the last one matters. the
+                // program names are only collected when timings are selected
+                timings.programs.clear();
+                let times: Vec<_> =
+                    line.split_ascii_whitespace().skip(4).collect();
+                for (p, t) in programs.iter().zip(times).rev() {
+                    if let Ok(t) = t.parse() {
+                        Timings::add(&mut timings.programs, p, t);
+                    }
+                }
             } else if time_re.is_match(line) {
                 time = line
                     .split_ascii_whitespace()
@@ -200,7 +868,7 @@ impl Program for Molpro {
                     .unwrap()
                     .parse()
                     .unwrap_or_else(|e| panic!("{e:#?}"));
-            } else if energy_re.is_match(line) {
+            } else if pattern.is_none() && energy_re.is_match(line) {
                 let energy_str = line.split_whitespace().nth(2);
                 if let Some(e) = energy_str {
                     energy = if let Ok(v) = e.parse::<f64>() {
@@ -211,9 +879,22 @@ impl Program for Molpro {
                 } else {
                     return Err(ProgramError::EnergyParseError(outfile));
                 }
-            } else if geom_re.is_match(line) {
+            } else if pattern.is_none() && total_re.is_match(line) {
+                total_energy = Some(last_energy(line, &outfile)?);
+            } else if select.geometry && geom_re.is_match(line) {
+                // only keep the last geometry, converted to Angstrom
                 skip = 3;
                 geom = true;
+                atoms.clear();
+                bohr = false;
+            } else if select.geometry && line.starts_with(" ATOMIC COORDINATES")
+            {
+                // followed by a blank line, the NR ATOM CHARGE X Y Z header,
+                // and another blank line, with the coordinates in Bohr
+                skip = 3;
+                geom = true;
+                atoms.clear();
+                bohr = true;
             } else if geom && blank_re.is_match(line) {
                 geom = false;
             } else if geom {
@@ -222,31 +903,710 @@ impl Program for Molpro {
                 // return a GeomParse error, but then that's irrelevant to a
                 // caller who only wants the energy. maybe we just set geom to
                 // false and reset atoms to be empty
+                // the ATOMIC COORDINATES block also has the atom number before
+                // the label and its charge after
+                let (label, xyz, scale) = if bohr {
+                    (sp[1], &sp[3..], ANG_BOHR)
+                } else {
+                    (sp[0], &sp[1..], 1.0)
+                };
+                let coord = |s: &str| scale * s.parse::<f64>().unwrap();
                 atoms.push(symm::Atom::new_from_label(
-                    sp[0],
-                    sp[1].parse().unwrap(),
-                    sp[2].parse().unwrap(),
-                    sp[3].parse().unwrap(),
+                    label,
+                    coord(xyz[0]),
+                    coord(xyz[1]),
+                    coord(xyz[2]),
                 ));
             }
         }
 
+        // the PBQFF variable is only printed at the end, but the other energy
+        // lines are printed as the calculation runs, so only trust them once
+        // Molpro has finished
+        let finished = contents.contains("Molpro calculation terminated");
+        let energy = if pattern.is_some() {
+            energy.filter(|_| finished)
+        } else {
+            energy.or(total_energy.filter(|_| finished))
+        };
         if let Some(energy) = energy {
             return Ok(ProgramResult {
                 energy,
-                cart_geom: if atoms.is_empty() { None } else { Some(atoms) },
+                cart_geom: (optimized && !atoms.is_empty()).then_some(atoms),
                 time,
+                gradient: (!gradient.is_empty()).then_some(Gradient {
+                    values: gradient,
+                    unit: GradientUnit::HartreeBohr,
+                }),
+                cpu_time,
+                wall_time: Some(time),
+                opt_steps,
+                dipole,
+                quadrupole,
+                convergence: convergence.map(|c| OptConvergence {
+                    optgrad,
+                    optstep,
+                    ..c
+                }),
+                nbasis,
+                nprimitive,
+                frequencies: (!frequencies.is_empty()).then(|| {
+                    frequencies.sort_by(f64::total_cmp);
+                    frequencies
+                }),
+                timings,
+                spin_contamination,
+                thermo: Vec::new(),
             });
         }
 
-        Err(ProgramError::EnergyNotFound(outfile))
+        if finished {
+            // Molpro finished without printing an energy, so waiting longer
+            // won't help
+            return Err(ProgramError::ErrorInOutput(outfile));
+        }
+
+        // otherwise the job is still running or was killed before finishing
+        Err(ProgramError::Truncated(outfile))
     }
 
     fn associated_files(&self) -> Vec<String> {
-        vec![self.infile(), self.outfile()]
+        let mut ret = self.input_files();
+        ret.push(self.outfile());
+        ret
     }
 
     fn infile(&self) -> String {
         self.filename() + ".inp"
     }
 }
+
+/// parse the last `N` whitespace-separated fields of `line` as floats, or
+/// return `None` if there are fewer than `N` or any of them fail to parse
+fn last_values<const N: usize>(line: &str) -> Option<[f64; N]> {
+    let sp: Vec<_> = line.split_whitespace().collect();
+    let start = sp.len().checked_sub(N)?;
+    let mut ret = [0.0; N];
+    for (r, s) in ret.iter_mut().zip(&sp[start..]) {
+        *r = s.parse().ok()?;
+    }
+    Some(ret)
+}
+
+/// parse the last field of `line` as an energy, returning an
+/// [ProgramError::EnergyParseError] for `outfile` if it is not a float
+fn last_energy(line: &str, outfile: &str) -> Result<f64, ProgramError> {
+    line.split_whitespace()
+        .last()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| ProgramError::EnergyParseError(outfile.to_owned()))
+}
+
+/// parse the `n`th whitespace-separated field of `line`
+fn nth_value<T: FromStr>(line: &str, n: usize) -> Option<T> {
+    line.split_whitespace().nth(n)?.parse().ok()
+}
+
+/// parse the value following `key =` in a line of Molpro's `THRESHOLDS`
+/// printout, like `OPTGRAD =  3.00D-04`, accounting for the Fortran `D`
+/// exponent
+fn fortran_value(line: &str, key: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(key)?;
+    let value = rest
+        .trim_start()
+        .strip_prefix('=')?
+        .split_whitespace()
+        .next()?;
+    value.replace(['D', 'd'], "E").parse().ok()
+}
+
+/// return the basis set names in the `basis` directive of `header`, either
+/// from a single-line `basis=name` or the entries of a `basis={...}` block
+/// like `default,name` or `H=name`
+fn basis_names(header: &str) -> Vec<String> {
+    let mut ret = Vec::new();
+    let mut block = false;
+    for line in header.lines().map(str::trim) {
+        let lower = line.to_lowercase();
+        let entries = if block {
+            if line.starts_with('}') {
+                block = false;
+                continue;
+            }
+            line.trim_end_matches('}')
+        } else if lower.starts_with("basis={") {
+            block = !line.ends_with('}');
+            line[7..].trim_end_matches('}')
+        } else if lower.starts_with("basis=") {
+            &line[6..]
+        } else {
+            continue;
+        };
+        for entry in entries.split(',').map(str::trim) {
+            let name = match entry.split_once('=') {
+                Some((_, name)) => name.trim(),
+                None => entry,
+            };
+            if !name.is_empty() && !name.eq_ignore_ascii_case("default") {
+                ret.push(name.to_owned());
+            }
+        }
+    }
+    ret
+}
+
+/// add the `nocompress` directive to `body` after the `memory` line (or at the
+/// top if there isn't one) if `nocompress` is true and it's missing, or remove
+/// it if `nocompress` is false
+fn set_nocompress(body: &str, nocompress: bool) -> String {
+    use std::fmt::Write;
+    let is_nocompress =
+        |l: &str| l.trim().to_lowercase().starts_with("nocompress");
+    let found = body.lines().any(is_nocompress);
+    if found == nocompress {
+        return body.to_owned();
+    }
+    let mut ret = String::with_capacity(body.len());
+    if !nocompress {
+        for line in body.lines().filter(|l| !is_nocompress(*l)) {
+            writeln!(ret, "{line}").unwrap();
+        }
+        return ret;
+    }
+    let memory = body
+        .lines()
+        .position(|l| l.trim().to_lowercase().starts_with("memory"));
+    if memory.is_none() {
+        writeln!(ret, "nocompress;").unwrap();
+    }
+    for (i, line) in body.lines().enumerate() {
+        writeln!(ret, "{line}").unwrap();
+        if Some(i) == memory {
+            writeln!(ret, "nocompress;").unwrap();
+        }
+    }
+    ret
+}
+
+/// report whether `s` is a valid Fortran floating-point literal, like `1.d-12`,
+/// `1.0D-8`, `1e-6`, or `0.5`
+pub fn is_fortran_float(s: &str) -> bool {
+    FLOAT_CELL
+        .get_or_init(|| {
+            Regex::new(r"^[+-]?(\d+\.?\d*|\.\d+)([eEdD][+-]?\d+)?$").unwrap()
+        })
+        .is_match(s.trim())
+}
+
+/// set the `gthresh` option `name` to `value` in `body`, replacing its value on
+/// an existing `gthresh` line or adding a new line after the last `gthresh`
+/// line, or after the `memory` line if there are none. returns an
+/// [Error::Input] if `value` is not a valid Fortran float
+fn set_gthresh(body: &str, name: &str, value: &str) -> Result<String, Error> {
+    use std::fmt::Write;
+    if !is_fortran_float(value) {
+        return Err(Error::Input(format!(
+            "invalid value `{value}` for gthresh option {name}"
+        )));
+    }
+    let re = Regex::new(&format!(
+        r"(?im)^([ \t]*gthresh\b.*[, \t]){name}[ \t]*=[ \t]*[^,;\s]+"
+    ))
+    .unwrap();
+    if re.is_match(body) {
+        return Ok(re
+            .replace(body, format!("${{1}}{name}={value}"))
+            .to_string());
+    }
+    let starts = |l: &str, p: &str| l.trim().to_lowercase().starts_with(p);
+    let lines: Vec<_> = body.lines().collect();
+    let pos = lines
+        .iter()
+        .rposition(|l| starts(l, "gthresh"))
+        .or_else(|| lines.iter().position(|l| starts(l, "memory")));
+    let mut ret = String::with_capacity(body.len());
+    if pos.is_none() {
+        writeln!(ret, "gthresh,{name}={value};").unwrap();
+    }
+    for (i, line) in lines.iter().enumerate() {
+        writeln!(ret, "{line}").unwrap();
+        if Some(i) == pos {
+            writeln!(ret, "gthresh,{name}={value};").unwrap();
+        }
+    }
+    Ok(ret)
+}
+
+/// add the auxiliary basis `sets`, as returned by [AuxBasis::sets], to the
+/// basis block in `body`, converting a single-line `basis` directive to a block
+/// if necessary, and add the corresponding options to any F12 methods. returns
+/// an [Error::Input] if there is no basis directive
+fn set_aux_basis(
+    body: &str,
+    sets: &[(&str, &str, &str)],
+) -> Result<String, Error> {
+    use std::fmt::Write;
+    let mut set_lines = String::new();
+    let mut options = String::new();
+    for (set, opt, basis) in sets {
+        writeln!(set_lines, "set,{set}\ndefault,{basis}").unwrap();
+        write!(options, ",{opt}={set}").unwrap();
+    }
+    let mut ret = String::with_capacity(body.len() + set_lines.len());
+    let mut in_block = false;
+    let mut found = false;
+    for line in body.lines() {
+        let trim = line.trim();
+        let lower = trim.to_lowercase();
+        if in_block && trim.starts_with('}') {
+            in_block = false;
+            ret.push_str(&set_lines);
+        } else if !found && lower.starts_with("basis={") {
+            found = true;
+            if let Some(inner) = trim[7..].strip_suffix('}') {
+                write!(ret, "basis={{\n{inner}\n{set_lines}}}\n").unwrap();
+                continue;
+            }
+            in_block = true;
+        } else if !found && lower.starts_with("basis=") {
+            found = true;
+            let name = trim[6..].trim();
+            write!(ret, "basis={{\ndefault,{name}\n{set_lines}}}\n").unwrap();
+            continue;
+        } else if !in_block && is_f12_method(&lower) {
+            let (start, method) = match trim.strip_prefix('{') {
+                Some(rest) => ("{", rest),
+                None => ("", trim),
+            };
+            let end = method.find([',', '}', ';']).unwrap_or(method.len());
+            let (name, rest) = method.split_at(end);
+            writeln!(ret, "{start}{name}{options}{rest}").unwrap();
+            continue;
+        }
+        writeln!(ret, "{line}").unwrap();
+    }
+    if !found {
+        return Err(Error::Input(
+            "auxiliary basis sets require a basis directive".to_owned(),
+        ));
+    }
+    Ok(ret)
+}
+
+/// report whether the lowercase `line` calls an explicitly-correlated method,
+/// like `{ccsd(t)-f12,thrden=1.0d-8}`
+fn is_f12_method(line: &str) -> bool {
+    line.trim_start_matches('{')
+        .split([',', '}', ';'])
+        .next()
+        .is_some_and(|m| m.trim().ends_with("-f12"))
+}
+
+/// report whether `line` runs a Hartree-Fock calculation, like
+/// `hf,accuracy=16` or `{rhf;wf,10,1,0}`
+fn is_hf_line(line: &str) -> bool {
+    let line = line.trim().trim_start_matches('{').to_ascii_lowercase();
+    let method = line.split([',', '}', ';']).next().unwrap_or_default();
+    matches!(method.trim(), "hf" | "rhf" | "uhf")
+}
+
+/// add `directives`, like `start,h0`, to the first Hartree-Fock line in `body`,
+/// wrapping it in braces if necessary. returns an [Error::Input] if there is no
+/// such line, since the directives would have nothing to apply to, or if it
+/// already has a directive with the same name
+fn set_hf_directives(
+    body: &str,
+    directives: &[String],
+) -> Result<String, Error> {
+    use std::fmt::Write;
+    let mut ret = String::with_capacity(body.len());
+    let mut found = false;
+    for line in body.lines() {
+        if found || !is_hf_line(line) {
+            writeln!(ret, "{line}").unwrap();
+            continue;
+        }
+        found = true;
+        let line = line.trim();
+        let lower = line.to_ascii_lowercase();
+        for d in directives {
+            let name = d.split(',').next().unwrap_or_default();
+            if lower.split([';', ',', '{', '}']).any(|w| w.trim() == name) {
+                return Err(Error::Input(format!(
+                    "`{d}` conflicts with `{line}`"
+                )));
+            }
+        }
+        let directives = directives.join(";");
+        match line.strip_suffix('}') {
+            Some(line) => writeln!(ret, "{line};{directives}}}").unwrap(),
+            None => writeln!(ret, "{{{line};{directives}}}").unwrap(),
+        }
+    }
+    if !found {
+        return Err(Error::Input(format!(
+            "`{}` requires a Hartree-Fock step",
+            directives.join(";")
+        )));
+    }
+    Ok(ret)
+}
+
+/// add a `{frequencies}` block to `body`, directly after the optimization
+/// matched by `optg_line` if there is one so that the frequencies are computed
+/// at the optimized geometry, and at the end otherwise
+fn set_frequencies(body: &str, optg_line: &Regex) -> String {
+    use std::fmt::Write;
+    let mut ret = String::new();
+    let mut found = false;
+    for line in body.lines() {
+        writeln!(ret, "{line}").unwrap();
+        if !found && optg_line.is_match(line) {
+            found = true;
+            writeln!(ret, "{{frequencies}}").unwrap();
+        }
+    }
+    if !found {
+        writeln!(ret, "{{frequencies}}").unwrap();
+    }
+    ret
+}
+
+/// add an `inactive` directive for `vars` to the `optg` lines in `body`,
+/// wrapping them in braces if necessary
+fn set_inactive(body: &str, optg_line: &Regex, vars: &[String]) -> String {
+    use std::fmt::Write;
+    let inactive = format!("inactive,{}", vars.join(","));
+    let mut ret = String::with_capacity(body.len() + inactive.len());
+    for line in body.lines() {
+        if !optg_line.is_match(line) {
+            writeln!(ret, "{line}").unwrap();
+            continue;
+        }
+        let line = line.trim();
+        match line.strip_suffix('}') {
+            Some(line) => writeln!(ret, "{line};{inactive}}}").unwrap(),
+            None => writeln!(ret, "{{{line};{inactive}}}").unwrap(),
+        }
+    }
+    ret
+}
+
+fn input_regexes() -> &'static [Regex; 6] {
+    // skip optgrad but accept optg at the end of a line
+    INPUT_CELL.get_or_init(|| {
+        [
+            Regex::new(r"(?i)optg(,|\s*$)").unwrap(),
+            Regex::new(r"(?i)^.*optg(,|\s*$)").unwrap(),
+            Regex::new(r"\{\{.charge\}\}").unwrap(),
+            Regex::new(r"\{\{.geom\}\}").unwrap(),
+            // a frequencies block, possibly abbreviated, like {freq} or
+            // {frequencies,symm=auto}
+            Regex::new(r"(?im)^\s*\{?\s*freq").unwrap(),
+            Regex::new(r"\{\{.spin\}\}").unwrap(),
+        ]
+    })
+}
+
+impl Molpro {
+    /// return the geometry and energy printed at each step of a geometry
+    /// optimization in the output file for `filename`, in order, for building
+    /// a trajectory of the optimization. Molpro prints each geometry in the xyz
+    /// format, with the energy on the comment line, like
+    ///
+    /// ```text
+    ///  Current geometry (xyz format, in Angstrom)
+    ///
+    ///     3
+    ///  CCSD(T)-F12/CC-PVTZ-F12  ENERGY=-76.36983962
+    ///  O          0.0000000000        0.0000000000       -0.0657441581
+    ///  ...
+    /// ```
+    ///
+    /// returns an empty vector if the output cannot be read or contains no
+    /// such geometries
+    pub fn trajectory(filename: &str) -> Vec<(Geom, f64)> {
+        let Ok(contents) =
+            fs::current().read_to_string(&format!("{filename}.out"))
+        else {
+            return Vec::new();
+        };
+        let mut ret = Vec::new();
+        let mut lines = contents.lines();
+        while let Some(line) = lines.next() {
+            if !line.starts_with(" Current geometry (xyz format") {
+                continue;
+            }
+            // skip the blank line and read the atom count and energy
+            let natoms = lines.nth(1).and_then(|l| l.trim().parse().ok());
+            let energy = lines
+                .next()
+                .and_then(|l| l.split_once("ENERGY="))
+                .and_then(|(_, e)| e.trim().parse().ok());
+            let (Some(natoms), Some(energy)) = (natoms, energy) else {
+                continue;
+            };
+            let atoms: Option<Vec<_>> = lines
+                .by_ref()
+                .take(natoms)
+                .map(|l| {
+                    let sp: Vec<_> = l.split_whitespace().collect();
+                    let [label, x, y, z] = sp[..] else {
+                        return None;
+                    };
+                    Some(symm::Atom::new_from_label(
+                        label,
+                        x.parse().ok()?,
+                        y.parse().ok()?,
+                        z.parse().ok()?,
+                    ))
+                })
+                .collect();
+            if let Some(atoms) = atoms.filter(|a| a.len() == natoms) {
+                ret.push((Geom::Xyz(atoms), energy));
+            }
+        }
+        ret
+    }
+
+    /// Check the basis sets named in `self.basis` or the template's `basis`
+    /// directive against [KNOWN_BASES], printing a warning for each
+    /// unrecognized name and returning them. Since custom basis sets are also
+    /// valid, this is only advisory and never prevents the job from being
+    /// written
+    pub fn check_basis(&self) -> Vec<String> {
+        let unknown: Vec<_> = self
+            .basis_names()
+            .into_iter()
+            .filter(|b| !KNOWN_BASES.iter().any(|k| k.eq_ignore_ascii_case(b)))
+            .collect();
+        for b in &unknown {
+            log::warn!("unrecognized basis set `{b}` in {}", self.filename);
+        }
+        unknown
+    }
+
+    /// the basis set names in `self.basis` if it is set, otherwise those in the
+    /// template's `basis` directive
+    fn basis_names(&self) -> Vec<String> {
+        match &self.basis {
+            Some(basis) => basis.names(),
+            None => basis_names(&self.template.header),
+        }
+    }
+
+    /// return the geometry string with the atoms in `self.masses` relabeled by
+    /// their 1-based index so they can be addressed individually, along with
+    /// the `mass` directive assigning their masses, if there are any. returns
+    /// an [Error::Input] if a mass is not positive or an index is out of range
+    fn isotope_geom(&self) -> Result<(String, Option<String>), Error> {
+        use std::fmt::Write;
+        let geom = geom_string(&self.geom);
+        if self.masses.is_empty() {
+            return Ok((geom, None));
+        }
+        let mut ret = String::with_capacity(geom.len());
+        let mut entries = Vec::new();
+        let mut atom = 0;
+        for line in geom.lines() {
+            // skip ZMAT variables and blank lines
+            if line.contains('=') || line.trim().is_empty() {
+                writeln!(ret, "{line}").unwrap();
+                continue;
+            }
+            let masses = self.masses.iter().filter(|(i, _)| *i == atom);
+            if let Some((_, mass)) = masses.last() {
+                if *mass <= 0.0 {
+                    return Err(Error::Input(format!(
+                        "invalid mass {mass} for atom {atom}"
+                    )));
+                }
+                let line = line.trim_start();
+                let end = line
+                    .find(|c: char| c == ',' || c.is_whitespace())
+                    .unwrap_or(line.len());
+                let (label, rest) = line.split_at(end);
+                let label = format!("{label}{}", atom + 1);
+                writeln!(ret, "{label}{rest}").unwrap();
+                entries.push(format!("{label}={mass}"));
+            } else {
+                writeln!(ret, "{line}").unwrap();
+            }
+            atom += 1;
+        }
+        if let Some((i, _)) = self.masses.iter().find(|(i, _)| *i >= atom) {
+            return Err(Error::Input(format!(
+                "mass given for missing atom {i}"
+            )));
+        }
+        Ok((ret, Some(format!("mass,init,{}", entries.join(",")))))
+    }
+
+    /// return an [Error::Input] if `self.frozen` is not empty and any of its
+    /// variables are not defined in the Z-matrix of `self.geom`
+    fn check_frozen(&self) -> Result<(), Error> {
+        if self.frozen.is_empty() {
+            return Ok(());
+        }
+        let Geom::Zmat(zmat) = &self.geom else {
+            return Err(Error::Input(
+                "frozen coordinates require a Z-matrix geometry".to_owned(),
+            ));
+        };
+        let defined: Vec<_> = zmat
+            .lines()
+            .filter_map(|l| l.split_once('='))
+            .map(|(name, _)| name.trim())
+            .collect();
+        for var in &self.frozen {
+            if !defined.iter().any(|d| d.eq_ignore_ascii_case(var)) {
+                return Err(Error::Input(format!(
+                    "frozen variable {var} is not defined in the Z-matrix"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// returns true if `prepare_template` gives the same result for `self` and
+    /// `other`
+    fn same_template(&self, other: &Self) -> bool {
+        self.template.header == other.template.header
+            && self.charge == other.charge
+            && self.spin == other.spin
+            && self.nocompress == other.nocompress
+            && self.thresholds == other.thresholds
+            && self.basis == other.basis
+            && self.frozen == other.frozen
+            && self.aux_basis == other.aux_basis
+            && self.guess == other.guess
+            && self.maxit == other.maxit
+            && self.diis == other.diis
+    }
+
+    /// the directives to add to the first Hartree-Fock line of the template
+    /// for `self.guess`, `self.maxit`, and `self.diis`
+    fn hf_directives(&self) -> Result<Vec<String>, Error> {
+        let mut ret = Vec::new();
+        if let Some(guess) = &self.guess {
+            ret.push(guess.directive()?);
+        }
+        if let Some(maxit) = self.maxit {
+            if maxit == 0 {
+                return Err(Error::Input("maxit must be positive".to_owned()));
+            }
+            ret.push(format!("maxit,{maxit}"));
+        }
+        if self.diis == Some(false) {
+            ret.push("nodiis".to_owned());
+        }
+        Ok(ret)
+    }
+
+    /// process `self.template` for `proc`, substituting everything except the
+    /// `{{.geom}}` placeholder
+    fn prepare_template(&self, proc: Procedure) -> Result<String, Error> {
+        use std::fmt::Write;
+        let mut body = self.template().clone().header;
+        let [opt, optg_line, charge, _, freq, spin] = input_regexes();
+        if let Some(nocompress) = self.nocompress {
+            body = set_nocompress(&body, nocompress);
+        }
+        for (name, value) in self.thresholds.iter() {
+            body = set_gthresh(&body, name, value)?;
+        }
+        if let Some(basis) = &self.basis {
+            body = set_basis(&body, &basis.render());
+        }
+        let sets = self.aux_basis.sets();
+        if !sets.is_empty() {
+            body = set_aux_basis(&body, &sets)?;
+        }
+        let hf = self.hf_directives()?;
+        if !hf.is_empty() {
+            body = set_hf_directives(&body, &hf)?;
+        }
+        let found_opt = opt.is_match(&body);
+        match proc {
+            Procedure::Opt => {
+                if !found_opt {
+                    writeln!(body, "{{optg,grms=1.d-8,srms=1.d-8}}").unwrap();
+                }
+                if !self.frozen.is_empty() {
+                    body = set_inactive(&body, optg_line, &self.frozen);
+                }
+            }
+            Procedure::Freq => {
+                if !freq.is_match(&body) {
+                    body = set_frequencies(&body, optg_line);
+                }
+                if found_opt && !self.frozen.is_empty() {
+                    body = set_inactive(&body, optg_line, &self.frozen);
+                }
+            }
+            Procedure::SinglePt => {
+                if found_opt {
+                    let mut new = String::new();
+                    for line in body.lines() {
+                        if !optg_line.is_match(line) {
+                            writeln!(new, "{line}").unwrap();
+                        }
+                    }
+                    body = new;
+                }
+            }
+        }
+        let body = charge.replace(&body, &format!("{}", self.charge));
+        Ok(spin.replace(&body, &format!("{}", self.spin)).to_string())
+    }
+
+    /// substitute `self.geom` into `body`, as returned by `prepare_template`,
+    /// and write the result to the input file. the element symbols of the
+    /// geometry are checked and normalized first
+    fn write_prepared(&mut self, body: &str) -> Result<(), Error> {
+        self.geom.normalize_elements().map_err(Error::Input)?;
+        let [_, _, _, geom_re, _, _] = input_regexes();
+        if let Some(basis) = &self.basis {
+            basis.check(&self.geom)?;
+        }
+        self.check_frozen()?;
+        let (geom, mass) = self.isotope_geom()?;
+        let geom = if let Geom::Zmat(_) = &self.geom {
+            use std::fmt::Write;
+            let mut new_lines = String::new();
+            let mut found = false;
+            for line in geom.lines() {
+                if line.contains('=') && !found {
+                    found = true;
+                    new_lines.push_str("}\n");
+                }
+                writeln!(new_lines, "{line}").unwrap();
+            }
+            new_lines
+        } else {
+            format!("{geom}\n}}\n")
+        };
+        let mut geom = match mass {
+            Some(mass) => format!("{geom}{mass}\n"),
+            None => geom,
+        };
+        if let Some(charges) = &self.point_charges {
+            if !fs::current().exists(charges) {
+                return Err(Error::io(
+                    charges,
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        "point charge file not found",
+                    ),
+                ));
+            }
+            geom.push_str(&format!("lattice,infile={charges}\n"));
+        }
+        let body = geom_re.replace(body, geom);
+
+        let filename = self.infile();
+        fs::current()
+            .write(&filename, body.as_bytes())
+            .map_err(|e| Error::io(&filename, e))
+    }
+}
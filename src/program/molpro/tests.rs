@@ -3,6 +3,7 @@ use std::{fs::read_to_string, str::FromStr};
 use crate::{
     geom::Geom,
     program::{molpro::Molpro, Procedure, Program, Template},
+    string, Error,
 };
 
 fn opt_templ() -> Template {
@@ -85,6 +86,10 @@ HCC =               147.81488230
 mod write_input {
     use super::*;
 
+    fn times(v: &[(&str, f64)]) -> Vec<(String, f64)> {
+        v.iter().map(|&(n, t)| (n.to_owned(), t)).collect()
+    }
+
     macro_rules! check {
         ($want_file: expr) => {
             let got_file = "/tmp/opt.inp";
@@ -100,7 +105,7 @@ mod write_input {
     #[test]
     fn opt_opt() {
         let mut m = test_molpro(Type::Opt);
-        m.write_input(Procedure::Opt);
+        m.write_input(Procedure::Opt).unwrap();
 
         check!("testfiles/molpro/opt_opt.want");
     }
@@ -108,7 +113,7 @@ mod write_input {
     #[test]
     fn opt_single() {
         let mut m = test_molpro(Type::Opt);
-        m.write_input(Procedure::SinglePt);
+        m.write_input(Procedure::SinglePt).unwrap();
 
         check!("testfiles/molpro/opt_single.want");
     }
@@ -116,7 +121,7 @@ mod write_input {
     #[test]
     fn single_opt() {
         let mut m = test_molpro(Type::Single);
-        m.write_input(Procedure::Opt);
+        m.write_input(Procedure::Opt).unwrap();
 
         check!("testfiles/molpro/opt_opt.want");
     }
@@ -124,14 +129,68 @@ mod write_input {
     #[test]
     fn single_single() {
         let mut m = test_molpro(Type::Single);
-        m.write_input(Procedure::SinglePt);
+        m.write_input(Procedure::SinglePt).unwrap();
 
         check!("testfiles/molpro/opt_single.want");
     }
+
+    #[test]
+    fn freq() {
+        let write = |t: Type| {
+            let mut m = Molpro {
+                filename: "/tmp/freq".to_owned(),
+                ..test_molpro(t)
+            };
+            m.write_input(Procedure::Freq).unwrap();
+            read_to_string("/tmp/freq.inp").unwrap()
+        };
+        // the optimization is kept and followed by the frequencies
+        let want = read_to_string("testfiles/molpro/opt_opt.want").unwrap();
+        assert_eq!(write(Type::Opt), want + "{frequencies}\n");
+
+        let want = read_to_string("testfiles/molpro/opt_single.want").unwrap();
+        assert_eq!(write(Type::Single), want + "{frequencies}\n");
+
+        // directly after the optimization, not at the end
+        let mut m = Molpro::new(
+            "/tmp/freq".to_owned(),
+            Template::from("geometry={\n{{.geom}}\n{optg}\npbqff=energy\n"),
+            0,
+            Geom::Xyz(Vec::new()),
+        );
+        m.write_input(Procedure::Freq).unwrap();
+        let got = read_to_string("/tmp/freq.inp").unwrap();
+        assert!(got.ends_with("{optg}\n{frequencies}\npbqff=energy\n"));
+
+        // an existing block is not duplicated
+        m.template =
+            Template::from("geometry={\n{{.geom}}\n{freq,symm=auto}\n");
+        m.write_input(Procedure::Freq).unwrap();
+        let got = read_to_string("/tmp/freq.inp").unwrap();
+        assert!(got.ends_with("}\n{freq,symm=auto}\n"), "{got}");
+        assert_eq!(got.matches("freq").count(), 1);
+        std::fs::remove_file("/tmp/freq.inp").unwrap();
+    }
+
+    #[test]
+    fn spin() {
+        let mut m = Molpro::new(
+            "/tmp/spin".to_owned(),
+            Template::from("set,charge={{.charge}}\nset,spin={{.spin}}\n"),
+            1,
+            Geom::Xyz(Vec::new()),
+        );
+        m.spin = 2;
+        assert_eq!(m.spin(), 2);
+        m.write_input(Procedure::SinglePt).unwrap();
+        let got = read_to_string("/tmp/spin.inp").unwrap();
+        assert_eq!(got, "set,charge=1\nset,spin=2\n");
+        std::fs::remove_file("/tmp/spin.inp").unwrap();
+    }
 }
 
 mod read_output {
-    use crate::program::{Program, ProgramResult};
+    use crate::program::{OptConvergence, Program, ProgramResult, Timings};
     use symm::Atom;
 
     use super::*;
@@ -163,6 +222,36 @@ mod read_output {
                 ),
             ]),
             time: 27.13,
+            cpu_time: Some(23.84),
+            wall_time: Some(27.13),
+            opt_steps: vec![(-76.36983962, 0.00000001)],
+            dipole: Some([0.0, 0.0, 0.78505665]),
+            convergence: Some(OptConvergence {
+                grad_max: 0.00000001,
+                grad_rms: 0.0,
+                step_max: 0.0,
+                step_rms: 0.0,
+                optgrad: Some(1e-8),
+                optstep: Some(1e-8),
+            }),
+            nbasis: Some(89),
+            nprimitive: Some(114),
+            timings: Timings {
+                programs: times(&[
+                    ("INT", 0.82),
+                    ("HF-SCF", 0.19),
+                    ("CCSD(T)", 3.10),
+                    ("OPTG", 19.59),
+                ]),
+                steps: times(&[
+                    ("Transformation", 0.04),
+                    ("CCSD iterations", 0.48),
+                    ("Triples", 0.12),
+                    ("MP2-F12", 2.56),
+                    ("F12b energy", 0.03),
+                ]),
+            },
+            ..Default::default()
         };
 
         assert_eq!(got, want);
@@ -176,11 +265,41 @@ mod read_output {
             energy: -76.470698498340,
             cart_geom: None,
             time: 4.73,
+            cpu_time: Some(3.38),
+            wall_time: Some(4.73),
+            dipole: Some([-0.00653291, 0.81065561, 0.0]),
+            nbasis: Some(52),
+            nprimitive: Some(67),
+            timings: Timings {
+                programs: times(&[
+                    ("INT", 1.26),
+                    ("HF-SCF", 0.07),
+                    ("CCSD(T)", 1.86),
+                ]),
+                steps: times(&[
+                    ("Transformation", 0.03),
+                    ("CCSD iterations", 0.30),
+                    ("Triples", 0.11),
+                    ("MP2-F12", 1.42),
+                    ("F12b energy", 0.01),
+                ]),
+            },
+            ..Default::default()
         };
 
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn quadrupole() {
+        let got = Molpro::read_output("testfiles/molpro/quadrupole").unwrap();
+        assert_eq!(got.dipole, Some([-0.00653291, 0.81065561, 0.0]));
+        assert_eq!(
+            got.quadrupole,
+            Some([-5.32470185, -4.21694528, -4.70181093, 0.0, 0.0, 0.53118217])
+        );
+    }
+
     #[test]
     fn error() {
         let got = Molpro::read_output("testfiles/molpro/error");
@@ -190,9 +309,804 @@ mod read_output {
         assert!(e.is_error_in_output());
     }
 
+    #[test]
+    fn opt_not_converged() {
+        let got = Molpro::read_output("testfiles/molpro/opt_noconv");
+        let Err(e) = got else {
+            panic!("expected error got {got:?}");
+        };
+        assert!(e.is_opt_not_converged());
+        assert!(e.is_failure());
+    }
+
+    #[test]
+    fn scf_not_converged() {
+        use crate::fs::{FileSystem, MemFs};
+        use std::sync::Arc;
+
+        let dzccr = read_to_string("testfiles/molpro/dzccr.out").unwrap();
+        let mem = Arc::new(MemFs::new());
+        let out = dzccr.replacen(
+            "\n",
+            "\n ? No convergence in max. number of iterations\n",
+            1,
+        );
+        mem.write("mem/scf.out", out.as_bytes()).unwrap();
+        let prev = crate::fs::set(mem);
+        let got = Molpro::read_output("mem/scf");
+        crate::fs::set(prev);
+        let Err(e) = got else {
+            panic!("expected error got {got:?}");
+        };
+        assert!(e.is_scf_not_converged());
+        assert!(e.is_failure());
+    }
+
     #[test]
     fn ignore_error() {
         let got = Molpro::read_output("testfiles/molpro/ignore_error");
         assert!(got.is_ok());
     }
+
+    #[test]
+    fn spin_contamination() {
+        let got = Molpro::read_output("testfiles/molpro/ignore_error").unwrap();
+        let spin = got.spin_contamination.unwrap();
+        assert_eq!(spin.expected, 0.75);
+        assert!((spin.deviation() - 0.00341310).abs() < 1e-12);
+        assert!(!spin.is_excessive(0.075));
+        assert!(spin.is_excessive(1e-3));
+
+        let got = Molpro::read_output("testfiles/molpro/dzccr").unwrap();
+        assert!(got.spin_contamination.is_none());
+    }
+
+    #[test]
+    fn no_step_timings() {
+        let got = Molpro::read_output("testfiles/molpro/ignore_error").unwrap();
+        assert!(got.timings.steps.is_empty());
+        assert_eq!(got.timings.get("CCSD(T)"), Some(100.91));
+    }
+
+    #[test]
+    fn energy_pattern() {
+        use crate::fs::{FileSystem, MemFs};
+        use crate::program::molpro::set_energy_pattern;
+        use regex::Regex;
+        use std::sync::Arc;
+
+        // dzccr.out without its PBQFF line to exercise the fallback
+        let contents: String = read_to_string("testfiles/molpro/dzccr.out")
+            .unwrap()
+            .lines()
+            .filter(|l| !l.starts_with(" PBQFF"))
+            .map(|l| format!("{l}\n"))
+            .collect();
+        let mem = Arc::new(MemFs::new());
+        mem.write("mem/dzccr.out", contents.as_bytes()).unwrap();
+        let prev = crate::fs::set(mem);
+
+        // the last correlated total energy by default
+        let got = Molpro::read_output("mem/dzccr").map(|r| r.energy);
+        assert_eq!(got, Ok(-76.292725972499));
+
+        set_energy_pattern(Some(Regex::new(r"^ !RHF STATE").unwrap()));
+        let got = Molpro::read_output("mem/dzccr").map(|r| r.energy);
+        assert_eq!(got, Ok(-76.077766546670));
+
+        set_energy_pattern(Some(Regex::new(r"^ MP2 total energy").unwrap()));
+        let got = Molpro::read_output("mem/dzccr").map(|r| r.energy);
+        assert_eq!(got, Ok(-76.28009104));
+
+        set_energy_pattern(None);
+        crate::fs::set(prev);
+    }
+
+    #[test]
+    fn unfinished() {
+        use crate::fs::{FileSystem, MemFs};
+        use crate::program::ProgramError;
+        use std::sync::Arc;
+
+        /// the lines of dzccr.out before the first one containing `stop`,
+        /// without its PBQFF line, plus the termination banner if
+        /// `finished`
+        fn output(stop: &str, finished: bool) -> Vec<u8> {
+            let mut ret: String = read_to_string("testfiles/molpro/dzccr.out")
+                .unwrap()
+                .lines()
+                .take_while(|l| !l.contains(stop))
+                .filter(|l| !l.starts_with(" PBQFF"))
+                .map(|l| format!("{l}\n"))
+                .collect();
+            if finished {
+                ret.push_str(" Molpro calculation terminated\n");
+            }
+            ret.into_bytes()
+        }
+
+        let mem = Arc::new(MemFs::new());
+        // cut off during the first correlated calculation, after the SCF
+        // energy has been printed
+        let stop = "CCSD-F12a total energy";
+        mem.write("mem/running.out", &output(stop, false)).unwrap();
+        mem.write("mem/scf.out", &output(stop, true)).unwrap();
+        mem.write("mem/failed.out", &output("!RHF STATE", true))
+            .unwrap();
+        let prev = crate::fs::set(mem);
+
+        let running = Molpro::read_output("mem/running");
+        let scf = Molpro::read_output("mem/scf").map(|r| r.energy);
+        let failed = Molpro::read_output("mem/failed");
+        let missing = Molpro::read_output("mem/missing");
+        crate::fs::set(prev);
+
+        assert_eq!(
+            running,
+            Err(ProgramError::Truncated("mem/running.out".to_owned()))
+        );
+        assert_eq!(scf, Ok(-76.057860851587));
+        assert_eq!(
+            failed,
+            Err(ProgramError::ErrorInOutput("mem/failed.out".to_owned()))
+        );
+        assert_eq!(
+            missing,
+            Err(ProgramError::FileNotFound("mem/missing.out".to_owned()))
+        );
+    }
+
+    #[test]
+    fn gradient() {
+        use crate::fs::{FileSystem, MemFs};
+        use crate::program::GradientUnit;
+        use std::sync::Arc;
+
+        let block = |z: &str| {
+            format!(
+                "
+ SCF GRADIENT FOR STATE 1.1
+
+ Atom          dE/dx               dE/dy               dE/dz
+
+   1         0.000000000         0.000000000        {z}
+   2         0.000000000         0.014441922        -0.006299717
+   3        -0.000000000        -0.014441922        -0.006299717
+
+ Nuclear force contribution to virial =         0.025482438
+"
+            )
+        };
+        let dzccr = read_to_string("testfiles/molpro/dzccr.out").unwrap();
+        let mem = Arc::new(MemFs::new());
+        // only the last gradient is kept
+        let out = block("0.100000000") + &block("0.012599434") + &dzccr;
+        mem.write("mem/grad.out", out.as_bytes()).unwrap();
+        let prev = crate::fs::set(mem);
+        let got = Molpro::read_output("mem/grad").unwrap().gradient.unwrap();
+        crate::fs::set(prev);
+        assert_eq!(got.unit, GradientUnit::HartreeBohr);
+        assert_eq!(
+            got.values,
+            vec![
+                0.0,
+                0.0,
+                0.012599434,
+                0.0,
+                0.014441922,
+                -0.006299717,
+                -0.0,
+                -0.014441922,
+                -0.006299717
+            ]
+        );
+
+        // single points don't print a gradient
+        let got = Molpro::read_output("testfiles/molpro/dzccr").unwrap();
+        assert!(got.gradient.is_none());
+    }
+
+    #[test]
+    fn last_geometry() {
+        use crate::fs::{FileSystem, MemFs};
+        use crate::program::molpro::ANG_BOHR;
+        use std::sync::Arc;
+
+        let opt = read_to_string("testfiles/molpro/opt.out").unwrap();
+        let want = Molpro::read_output("testfiles/molpro/opt").unwrap();
+        let want = want.cart_geom.unwrap();
+
+        let mem = Arc::new(MemFs::new());
+        // earlier geometries are replaced by the final one
+        let traj = read_to_string("testfiles/molpro/trajectory.out").unwrap();
+        mem.write("mem/steps.out", (traj + &opt).as_bytes())
+            .unwrap();
+        // without the final xyz block, the geometry comes from the ATOMIC
+        // COORDINATES block, which is printed in Bohr. this optimization
+        // started at its converged geometry, so they agree
+        let start = opt.find(" Current geometry").unwrap();
+        let end = start + opt[start..].find(" ENERGY=").unwrap();
+        let end = end + opt[end..].find("\n\n").unwrap();
+        let bohr = format!("{}{}", &opt[..start], &opt[end + 2..]);
+        mem.write("mem/bohr.out", bohr.as_bytes()).unwrap();
+        let prev = crate::fs::set(mem);
+        let steps = Molpro::read_output("mem/steps").unwrap().cart_geom;
+        let bohr = Molpro::read_output("mem/bohr").unwrap().cart_geom.unwrap();
+        crate::fs::set(prev);
+
+        assert_eq!(steps.as_ref(), Some(&want));
+        assert_eq!(bohr.len(), want.len());
+        for (b, w) in bohr.iter().zip(&want) {
+            assert_eq!(b.label(), w.label());
+            assert!((b.x - w.x).abs() < 1e-8);
+            assert!((b.y - w.y).abs() < 1e-8);
+            assert!((b.z - w.z).abs() < 1e-8);
+        }
+        assert_eq!(bohr[1].y, 1.431390211 * ANG_BOHR);
+
+        // single points don't print a geometry
+        let got = Molpro::read_output("testfiles/molpro/dzccr").unwrap();
+        assert!(got.cart_geom.is_none());
+    }
+
+    #[test]
+    fn frequencies() {
+        use crate::fs::{FileSystem, MemFs};
+        use std::sync::Arc;
+        // the normal mode sections printed by {frequencies}, with only the
+        // wavenumbers kept from the imaginary and low/zero sections
+        const MODES: &str = "
+ Normal Modes
+
+                                1 A1          2 A1          3 B2
+ Wavenumbers [cm-1]          1648.58       3794.37       3896.43
+ Intensities [km/mol]          68.28          2.77         20.56
+
+ Normal Modes of imaginary frequencies
+
+                                1 B1
+ Wavenumbers [cm-1]           512.40
+ Intensities [km/mol]          10.02
+
+ Normal Modes of low/zero frequencies
+
+                                1 A1          2 B2          3 B1
+ Wavenumbers [cm-1]             0.01          0.02          5.90
+";
+        let opt = read_to_string("testfiles/molpro/opt.out").unwrap();
+        let freq = opt.replace(
+            " Molpro calculation terminated",
+            &format!("{MODES}\n Molpro calculation terminated"),
+        );
+        let mem = Arc::new(MemFs::new());
+        mem.write("mem/freq.out", freq.as_bytes()).unwrap();
+        let prev = crate::fs::set(mem);
+        let got = Molpro::read_output("mem/freq").unwrap();
+        crate::fs::set(prev);
+        assert_eq!(
+            got.frequencies,
+            Some(vec![-512.40, 1648.58, 3794.37, 3896.43])
+        );
+
+        let got = Molpro::read_output("testfiles/molpro/opt").unwrap();
+        assert!(got.frequencies.is_none());
+    }
+
+    #[test]
+    fn selectors() {
+        use crate::program::ParseSelectors;
+        let all = Molpro::read_output("testfiles/molpro/opt").unwrap();
+
+        // only the energy and times by default
+        let got = Molpro::read_output_with(
+            "testfiles/molpro/opt",
+            ParseSelectors::default(),
+        )
+        .unwrap();
+        let want = ProgramResult {
+            energy: all.energy,
+            time: all.time,
+            cpu_time: all.cpu_time,
+            wall_time: all.wall_time,
+            ..Default::default()
+        };
+        assert_eq!(got, want);
+
+        let select = ParseSelectors {
+            geometry: true,
+            optimization: true,
+            ..Default::default()
+        };
+        let got =
+            Molpro::read_output_with("testfiles/molpro/opt", select).unwrap();
+        assert_eq!(got.cart_geom, all.cart_geom);
+        assert_eq!(got.opt_steps, all.opt_steps);
+        assert_eq!(got.convergence, all.convergence);
+        assert!(got.dipole.is_none());
+        assert!(got.nbasis.is_none());
+        assert_eq!(got.timings, Timings::default());
+
+        assert_eq!(
+            Molpro::read_output_with(
+                "testfiles/molpro/opt",
+                ParseSelectors::all()
+            )
+            .unwrap(),
+            all
+        );
+    }
+}
+
+#[test]
+fn write_inputs() {
+    let mut batch: Vec<_> = (0..3)
+        .map(|i| {
+            let mut m = test_molpro(Type::Opt);
+            m.set_filename(&format!("/tmp/batch{i}"));
+            m
+        })
+        .collect();
+    batch[1].charge = 1;
+    Molpro::write_inputs(batch.iter_mut(), Procedure::SinglePt).unwrap();
+    for (i, mut m) in batch.into_iter().enumerate() {
+        let got = read_to_string(format!("/tmp/batch{i}.inp")).unwrap();
+        m.set_filename(&format!("/tmp/single{i}"));
+        m.write_input(Procedure::SinglePt).unwrap();
+        let want = read_to_string(format!("/tmp/single{i}.inp")).unwrap();
+        assert_eq!(got, want);
+    }
+}
+
+#[test]
+fn template_builder() {
+    use crate::program::molpro::TemplateBuilder;
+    let got = TemplateBuilder::new()
+        .memory("8,g")
+        .gthresh("energy", "1.d-10")
+        .basis("cc-pVDZ")
+        .method(&["hf", "ccsd(t)"])
+        .build()
+        .unwrap()
+        .header;
+    let want = "memory,8,g
+gthresh,energy=1.d-10,zero=1.d-22,oneint=1.d-22,twoint=1.d-22,\
+optgrad=1.d-8,optstep=1.d-8;
+nocompress;
+
+geometry={
+{{.geom}}
+basis={
+default,cc-pVDZ
+}
+set,charge={{.charge}}
+set,spin={{.spin}}
+hf
+ccsd(t)
+";
+    assert_eq!(got, want);
+
+    // the default should write the same input as the example template
+    let mut m = test_molpro(Type::Single);
+    m.template = TemplateBuilder::default().build().unwrap();
+    m.set_filename("/tmp/builder");
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/builder.inp").unwrap();
+    let want = "default,cc-pVTZ-f12\n}\nset,charge=0\nset,spin=0\n";
+    assert!(got.contains(want));
+    assert!(got.contains("{CCSD(T)-F12,thrden=1.0d-8,thrvar=1.0d-10}\n"));
+
+    let got = TemplateBuilder::new().gthresh("energy", "1e-10x").build();
+    assert!(matches!(got, Err(Error::Input(_))));
+}
+
+#[test]
+fn trajectory() {
+    let got = Molpro::trajectory("testfiles/molpro/trajectory");
+    let energies: Vec<_> = got.iter().map(|(_, e)| *e).collect();
+    assert_eq!(energies, vec![-76.36982974, -76.36983962]);
+    let atoms = got[0].0.xyz().unwrap();
+    assert_eq!(atoms.len(), 3);
+    assert_eq!(atoms[1].label(), "H");
+    assert_eq!(atoms[1].y, 0.7582261943);
+
+    // the last geometry should match the one from read_output
+    let got = Molpro::trajectory("testfiles/molpro/opt");
+    let want = Molpro::read_output("testfiles/molpro/opt").unwrap();
+    assert_eq!(got.len(), 1);
+    assert_eq!(got[0].0.xyz(), want.cart_geom.as_ref());
+
+    assert!(Molpro::trajectory("testfiles/molpro/dzccr").is_empty());
+}
+
+#[test]
+fn check_binary() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = "/tmp/fake_molpro";
+    std::fs::write(
+        path,
+        "#!/bin/sh\n[ \"$1\" = --version ] && echo '\n Molpro 2022.3.0'\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+        .unwrap();
+    assert_eq!(Molpro::check_binary(path).unwrap(), "Molpro 2022.3.0");
+    std::fs::remove_file(path).unwrap();
+    assert!(Molpro::check_binary(path).is_err());
+}
+
+#[test]
+fn efficiency() {
+    let got = Molpro::read_output("testfiles/molpro/opt").unwrap();
+    let eff = got.efficiency(1).unwrap();
+    assert!((eff - 23.84 / 27.13).abs() < 1e-12);
+    let eff = got.efficiency(8).unwrap();
+    assert!((eff - 23.84 / (27.13 * 8.0)).abs() < 1e-12);
+    assert!(got.efficiency(0).is_none());
+}
+
+#[test]
+fn check_basis() {
+    let m = test_molpro(Type::Opt);
+    assert!(m.check_basis().is_empty());
+
+    let mut m = test_molpro(Type::Single);
+    m.template = Template::from(
+        "basis={
+default,cc-pVTX-f12,H=aug-cc-pvdz
+}
+hf
+",
+    );
+    assert_eq!(m.check_basis(), vec!["cc-pVTX-f12"]);
+
+    m.template = Template::from("basis=vtz-f13\nhf\n");
+    assert_eq!(m.check_basis(), vec!["vtz-f13"]);
+}
+
+#[test]
+fn nocompress() {
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/nocompress");
+    m.nocompress = Some(false);
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/nocompress.inp").unwrap();
+    assert!(!got.contains("nocompress"));
+
+    m.template = Template::from("memory,1,g\nhf\n");
+    m.nocompress = Some(true);
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/nocompress.inp").unwrap();
+    assert!(got.starts_with("memory,1,g\nnocompress;\nhf\n"));
+}
+
+#[test]
+fn masses() {
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/masses");
+    m.masses = vec![(3, 2.014101778)];
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/masses.inp").unwrap();
+    assert!(got.contains("\nH4 2 CH 1 HCC 3 180.0\nH 3 CH 1 HCC 2 180.0\n"));
+    assert!(got.contains(
+        "HCC =               147.81488230\n\
+                          mass,init,H4=2.014101778\n"
+    ));
+
+    m.geom = Geom::Xyz(vec![
+        symm::Atom::new(8, 0.0, 0.0, -0.0657441581),
+        symm::Atom::new(1, 0.0, 0.7574590773, 0.5217905246),
+        symm::Atom::new(1, 0.0, -0.7574590773, 0.5217905246),
+    ]);
+    m.masses = vec![(1, 2.014101778), (2, 2.014101778)];
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/masses.inp").unwrap();
+    assert!(got.contains("\nH2    0.000000000000   0.757459077300"));
+    assert!(got.contains("\nH3    0.000000000000  -0.757459077300"));
+    assert!(got.contains("}\nmass,init,H2=2.014101778,H3=2.014101778\n"));
+}
+
+#[test]
+fn point_charges() {
+    let charges = "/tmp/point_charges.latt";
+    let _ = std::fs::remove_file(charges);
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/point_charges");
+    m.geom = Geom::Xyz(vec![symm::Atom::new(1, 0.0, 0.0, 0.0)]);
+    m.point_charges = Some(charges.to_owned());
+    let err = m.write_input(Procedure::SinglePt).unwrap_err();
+    let not_found = std::io::ErrorKind::NotFound;
+    assert!(
+        matches!(&err, crate::Error::Io(e) if e.kind() == not_found),
+        "{err}"
+    );
+
+    std::fs::write(charges, "charges\n1\n1.0,0.0,0.0,-0.5,1\n").unwrap();
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/point_charges.inp").unwrap();
+    assert!(
+        got.contains("}\nlattice,infile=/tmp/point_charges.latt\n\nbasis={")
+    );
+    assert_eq!(
+        m.associated_files(),
+        vec!["/tmp/point_charges.inp", "/tmp/point_charges.out"]
+    );
+    std::fs::remove_file(charges).unwrap();
+    std::fs::remove_file("/tmp/point_charges.inp").unwrap();
+}
+
+#[test]
+fn negative_mass() {
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/negative_mass");
+    m.masses = vec![(0, -1.0)];
+    assert!(matches!(
+        m.write_input(Procedure::SinglePt),
+        Err(Error::Input(_))
+    ));
+}
+
+#[test]
+fn invalid_element() {
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/invalid_element");
+    m.geom = Geom::Zmat("c\nQq 1 CC\nCC = 1.0\n".to_owned());
+    assert!(matches!(
+        m.write_input(Procedure::SinglePt),
+        Err(Error::Input(e)) if e.contains("`Qq`")
+    ));
+    // valid symbols are normalized before they are written
+    m.geom = Geom::Zmat("c\nh 1 CH\nCH = 1.0\n".to_owned());
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/invalid_element.inp").unwrap();
+    assert!(got.contains("C\nH 1 CH\n"));
+}
+
+#[test]
+fn summary() {
+    assert_eq!(
+        test_molpro(Type::Opt).summary(),
+        "Molpro CCSD(T)-F12/cc-pVTZ-f12 charge=0 (5 atoms)"
+    );
+    assert_eq!(
+        test_molpro(Type::Single).summary(),
+        "Molpro CCSD(T)-F12/cc-pVTZ-f12 charge=0 (5 atoms)"
+    );
+}
+
+#[test]
+fn is_fortran_float() {
+    use crate::program::molpro::is_fortran_float;
+    for s in ["1.d-12", "1.0D-8", "1e-6", "0.5", ".5", "-1.d+3", "10"] {
+        assert!(is_fortran_float(s), "{s}");
+    }
+    for s in ["", "d-8", "1.x-8", "1.d", "1.d-8;", "1..0"] {
+        assert!(!is_fortran_float(s), "{s}");
+    }
+}
+
+#[test]
+fn thresholds() {
+    use crate::program::molpro::Thresholds;
+    let mut m = test_molpro(Type::Opt);
+    m.set_filename("/tmp/thresholds");
+    m.thresholds = Thresholds {
+        energy: Some("1.d-10".to_owned()),
+        optgrad: None,
+        optstep: Some("1.d-6".to_owned()),
+    };
+    m.write_input(Procedure::Opt).unwrap();
+    let got = read_to_string("/tmp/thresholds.inp").unwrap();
+    assert!(got.contains(
+        "\ngthresh,energy=1.d-10,zero=1.d-22,oneint=1.d-22,twoint=1.d-22;\n\
+         gthresh,optgrad=1.d-8,optstep=1.d-6;\n"
+    ));
+
+    // missing thresholds are added after the last gthresh line
+    let mut m = Molpro::new(
+        "/tmp/thresholds".to_owned(),
+        Template::from(
+            "memory,1,g\ngthresh,zero=1.d-22;\ngeometry={\n{{.geom}}\nhf\n",
+        ),
+        0,
+        Geom::default(),
+    );
+    m.thresholds.optgrad = Some("1.d-7".to_owned());
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/thresholds.inp").unwrap();
+    assert!(got.starts_with(
+        "memory,1,g\ngthresh,zero=1.d-22;\ngthresh,optgrad=1.d-7;\n"
+    ));
+}
+
+#[test]
+fn bad_threshold() {
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/bad_threshold");
+    m.thresholds.energy = Some("1.x-8".to_owned());
+    assert!(matches!(
+        m.write_input(Procedure::SinglePt),
+        Err(Error::Input(_))
+    ));
+}
+
+#[test]
+fn basis_spec() {
+    use crate::program::molpro::BasisSpec;
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/basis_spec");
+    m.basis = Some(BasisSpec {
+        atoms: vec![("H".to_owned(), "cc-pVDZ".to_owned())],
+        ecp: vec![("C".to_owned(), "ECP10MDF".to_owned())],
+        ..BasisSpec::new("aug-cc-pVTZ")
+    });
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/basis_spec.inp").unwrap();
+    assert!(got.contains(
+        "\nbasis={\ndefault,aug-cc-pVTZ\nH=cc-pVDZ\necp,C,ECP10MDF\n}\n\
+         set,charge=0\n"
+    ));
+    assert!(!got.contains("cc-pVTZ-f12"));
+    assert_eq!(
+        m.summary(),
+        "Molpro CCSD(T)-F12/aug-cc-pVTZ charge=0 (5 atoms)"
+    );
+}
+
+#[test]
+fn basis_spec_missing_element() {
+    use crate::program::molpro::BasisSpec;
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/basis_spec_missing");
+    m.basis = Some(BasisSpec {
+        atoms: vec![("O".to_owned(), "cc-pVDZ".to_owned())],
+        ..BasisSpec::new("cc-pVTZ")
+    });
+    assert!(matches!(
+        m.write_input(Procedure::SinglePt),
+        Err(Error::Input(_))
+    ));
+}
+
+#[test]
+fn frozen() {
+    let mut m = test_molpro(Type::Opt);
+    m.set_filename("/tmp/frozen");
+    m.frozen = string!["CC", "HCC"];
+    m.write_input(Procedure::Opt).unwrap();
+    let got = read_to_string("/tmp/frozen.inp").unwrap();
+    assert!(got.ends_with("{optg,grms=1.d-8,srms=1.d-8;inactive,CC,HCC}\n"));
+
+    // the optg line is added for single-point templates too
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/frozen");
+    m.frozen = string!["CH"];
+    m.write_input(Procedure::Opt).unwrap();
+    let got = read_to_string("/tmp/frozen.inp").unwrap();
+    assert!(got.ends_with("{optg,grms=1.d-8,srms=1.d-8;inactive,CH}\n"));
+
+    // and left out of single points
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/frozen.inp").unwrap();
+    assert!(!got.contains("inactive"));
+}
+
+#[test]
+fn frozen_undefined() {
+    let mut m = test_molpro(Type::Opt);
+    m.set_filename("/tmp/frozen_undefined");
+    m.frozen = string!["OH"];
+    assert!(matches!(
+        m.write_input(Procedure::Opt),
+        Err(Error::Input(_))
+    ));
+}
+
+#[test]
+fn aux_basis() {
+    use crate::program::molpro::AuxBasis;
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/aux_basis");
+    m.aux_basis = AuxBasis {
+        jkfit: Some("cc-pVTZ-F12/jkfit".to_owned()),
+        mp2fit: None,
+        cabs: Some("cc-pVTZ-F12/optri".to_owned()),
+    };
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/aux_basis.inp").unwrap();
+    assert!(got.contains(
+        "\nbasis={
+default,cc-pVTZ-f12
+set,jkfit
+default,cc-pVTZ-F12/jkfit
+set,ri
+default,cc-pVTZ-F12/optri
+}
+"
+    ));
+    assert!(got.contains(
+        "\nhf,accuracy=16,energy=1.0d-10
+{CCSD(T)-F12,df_basis_exch=jkfit,ri_basis=ri,thrden=1.0d-8,thrvar=1.0d-10}
+"
+    ));
+
+    // single-line basis directives are converted to blocks
+    let mut m = Molpro::new(
+        "/tmp/aux_basis".to_owned(),
+        Template::from("geometry={\n{{.geom}}\nbasis=vdz-f12\nmp2-f12\n"),
+        0,
+        Geom::default(),
+    );
+    m.aux_basis.mp2fit = Some("vdz/mp2fit".to_owned());
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/aux_basis.inp").unwrap();
+    assert!(got.ends_with(
+        "basis={\ndefault,vdz-f12\nset,mp2fit\ndefault,vdz/mp2fit\n}\n\
+         mp2-f12,df_basis=mp2fit\n"
+    ));
+}
+
+#[test]
+fn guess() {
+    use crate::program::molpro::Guess;
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/guess");
+    m.guess = Some(Guess::Core);
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/guess.inp").unwrap();
+    assert!(got.contains("\n{hf,accuracy=16,energy=1.0d-10;start,h0}\n"));
+
+    let mut m = Molpro::new(
+        "/tmp/guess".to_owned(),
+        Template::from("geometry={\n{{.geom}}\nbasis=vdz\n{rhf;wf,10,1,0}\n"),
+        0,
+        Geom::default(),
+    );
+    m.guess = Some(Guess::Record("2100.2".to_owned()));
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/guess.inp").unwrap();
+    assert!(got.ends_with("{rhf;wf,10,1,0;start,2100.2}\n"));
+    std::fs::remove_file("/tmp/guess.inp").unwrap();
+}
+
+#[test]
+fn guess_without_hf() {
+    use crate::program::molpro::Guess;
+    let mut m = Molpro::new(
+        "/tmp/guess_without_hf".to_owned(),
+        Template::from("geometry={\n{{.geom}}\nbasis=vdz\nccsd(t)\n"),
+        0,
+        Geom::default(),
+    );
+    m.guess = Some(Guess::AtomicDensity);
+    assert!(matches!(
+        m.write_input(Procedure::SinglePt),
+        Err(Error::Input(e)) if e.contains("requires a Hartree-Fock step")
+    ));
+}
+
+#[test]
+fn scf_options() {
+    use crate::program::molpro::Guess;
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/scf_options");
+    m.guess = Some(Guess::AtomicDensity);
+    m.maxit = Some(200);
+    m.diis = Some(false);
+    m.write_input(Procedure::SinglePt).unwrap();
+    let got = read_to_string("/tmp/scf_options.inp").unwrap();
+    assert!(got.contains(
+        "\n{hf,accuracy=16,energy=1.0d-10;start,atden;maxit,200;nodiis}\n"
+    ));
+    std::fs::remove_file("/tmp/scf_options.inp").unwrap();
+}
+
+#[test]
+fn zero_maxit() {
+    let mut m = test_molpro(Type::Single);
+    m.set_filename("/tmp/zero_maxit");
+    m.maxit = Some(0);
+    assert!(matches!(
+        m.write_input(Procedure::SinglePt),
+        Err(Error::Input(e)) if e.contains("maxit must be positive")
+    ));
 }
@@ -1,19 +1,34 @@
 use crate::geom::{geom_string, Geom};
 use crate::program::{Program, ProgramError};
+use crate::{fs, Error};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use symm::Atom;
 
-use super::{Job, Procedure, ProgramResult, Template};
+use super::{
+    run_binary, Gradient, GradientUnit, Job, ParseSelectors, Procedure,
+    ProgramResult, Template, Thermo, Timings,
+};
 use std::collections::hash_map::DefaultHasher;
-use std::fs::{read_to_string, File};
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::io::{BufRead, BufReader, Write};
 use std::sync::OnceLock;
 
 /// kcal/mol per hartree
 pub const KCALHT: f64 = 627.5091809;
 
+/// Debye per atomic unit of dipole moment
+pub const DEBYE: f64 = 2.541746473;
+
+/// the semi-empirical Hamiltonians recognized by [Mopac::summary] and
+/// [Keywords::render]
+const METHODS: [&str; 8] = [
+    "AM1", "MNDO", "MNDOD", "PM3", "PM6", "PM7", "RM1", "PM6-D3H4",
+];
+
+pub use self::keywords::*;
+pub mod keywords;
+
 pub use self::params::*;
 pub mod params;
 
@@ -49,8 +64,17 @@ pub struct Mopac {
     /// molecular charge, included in the input file via the CHARGE keyword
     pub charge: isize,
 
+    /// the number of unpaired electrons, included in the input file via the
+    /// multiplicity keyword, like `DOUBLET` for 1, if it is nonzero. must be
+    /// at most 8. defaults to 0
+    pub spin: usize,
+
     /// [Template] for the input file
     pub template: Template,
+
+    /// typed keywords to append to the template header. defaults to `None`,
+    /// using the header unchanged
+    pub keywords: Option<Keywords>,
 }
 
 impl Program for Mopac {
@@ -65,9 +89,11 @@ impl Program for Mopac {
             geom,
             param_file: None,
             charge,
+            spin: 0,
             template,
             params: None,
             param_dir: None,
+            keywords: None,
         }
     }
 
@@ -90,13 +116,23 @@ impl Program for Mopac {
     /// Writes the parameters of self to a parameter file, then writes the MOPAC
     /// input file with external=paramfile. Also update self.paramfile to point
     /// to the generated name for the parameter file
-    fn write_input(&mut self, proc: Procedure) {
+    fn write_input(&mut self, proc: Procedure) -> Result<(), Error> {
         use std::fmt::Write;
+        self.geom.normalize_elements().map_err(Error::Input)?;
         // header should look like
         //   scfcrt=1.D-21 aux(precision=14) PM6
         // so that the charge, and optionally XYZ, A0, and 1SCF can be added
         let mut header = self.template().clone().header;
+        if let Some(keywords) = &self.keywords {
+            let words = keywords.render(&header)?;
+            if !words.is_empty() {
+                write!(header, " {words}").unwrap();
+            }
+        }
         write!(header, " charge={}", self.charge).unwrap();
+        if self.spin > 0 {
+            write!(header, " {}", multiplicity(self.spin)?).unwrap();
+        }
         match proc {
             Procedure::Opt => {
                 // optimization is the default, so just don't add 1SCF
@@ -111,7 +147,7 @@ impl Program for Mopac {
             self.filename.hash(&mut s);
             let param_file =
                 format!("{}/{}", self.param_dir.as_ref().unwrap(), s.finish());
-            Self::write_params(params, &param_file);
+            Self::write_params(params, &param_file)?;
             write!(header, " external={param_file}").unwrap();
             self.param_file = Some(param_file);
         }
@@ -119,37 +155,53 @@ impl Program for Mopac {
             header.push_str(" XYZ");
         }
         let geom = geom_string(&self.geom);
-        let filename = format!("{}.mop", self.filename);
-        let mut file = match File::create(&filename) {
-            Ok(f) => f,
-            Err(e) => panic!("failed to create {filename} with {e}"),
-        };
-        write!(
-            file,
+        let filename = self.infile();
+        let body = format!(
             "{header}
 Comment line 1
 Comment line 2
 {geom}
 ",
-        )
-        .expect("failed to write input file");
+        );
+        fs::current()
+            .write(&filename, body.as_bytes())
+            .map_err(|e| Error::io(&filename, e))
     }
 
     /// Reads a MOPAC output file. If normal termination occurs, also try
     /// reading the `.aux` file to extract the energy from there. This function
     /// panics if an error is found in the output file. If a non-fatal error
-    /// occurs (file not found, not written to yet, etc) None is returned.
+    /// occurs (file not found, not written to yet, etc) None is returned. A
+    /// failure to converge the SCF or a geometry optimization is reported from
+    /// the output file even if the aux file was written, and an output file
+    /// cut off before `== MOPAC DONE ==` is reported as
+    /// [ProgramError::Truncated]
     fn read_output(filename: &str) -> Result<ProgramResult, ProgramError> {
-        let res = Self::read_aux(filename);
+        Self::read_output_with(filename, ParseSelectors::all())
+    }
+
+    fn read_output_with(
+        filename: &str,
+        select: ParseSelectors,
+    ) -> Result<ProgramResult, ProgramError> {
+        let res = Self::read_aux_with(filename, select);
+        let outfile = format!("{}.out", &filename);
+        let contents = fs::current().read_to_string(&outfile);
+        // MOPAC still writes the aux file after these failures, so check for
+        // them before trusting it
+        if let Ok(contents) = &contents {
+            if contents.contains("UNABLE TO ACHIEVE SELF-CONSISTENCE") {
+                return Err(ProgramError::ScfNotConverged(outfile));
+            } else if contents.contains("EXCESS NUMBER OF OPTIMIZATION CYCLES")
+            {
+                return Err(ProgramError::OptNotConverged(outfile));
+            }
+        }
         if res.is_ok() {
             return res;
         }
-        let outfile = format!("{}.out", &filename);
-        let contents = match read_to_string(&outfile) {
-            Ok(s) => s,
-            Err(_) => {
-                return Err(ProgramError::FileNotFound(outfile));
-            }
+        let Ok(contents) = contents else {
+            return Err(ProgramError::FileNotFound(outfile));
         };
 
         let [panic, error] = READ_OUT_CELL.get_or_init(|| {
@@ -163,18 +215,40 @@ Comment line 2
             return Err(ProgramError::ErrorInOutput(filename.to_owned()));
         } else if panic.is_match(&contents) {
             panic!("panic requested in read_output");
+        } else if !contents.contains("== MOPAC DONE ==") {
+            return Err(ProgramError::Truncated(outfile));
         }
         res
     }
 
+    /// MOPAC has no `--version` flag, so run a trivial H2 single point in a
+    /// temporary directory and take the version from the header of its
+    /// output
+    fn check_binary(path: &str) -> Result<String, Error> {
+        let dir = tempfile::Builder::new()
+            .prefix("psqs")
+            .tempdir()
+            .map_err(|e| Error::io(path, e))?;
+        let input = dir.path().join("check.mop");
+        std::fs::write(&input, "PM6 1SCF\ncheck\n\nH 0 0 0\nH 0 0 0.74\n")
+            .map_err(|e| Error::io(&input.to_string_lossy(), e))?;
+        run_binary(path, &["check.mop"], Some(dir.path()))?;
+        let outfile = dir.path().join("check.out");
+        let contents = std::fs::read_to_string(&outfile)
+            .map_err(|e| Error::io(&outfile.to_string_lossy(), e))?;
+        output_version(&contents).ok_or_else(|| {
+            Error::io(path, std::io::Error::other("no version in output"))
+        })
+    }
+
     fn associated_files(&self) -> Vec<String> {
         let fname = self.filename();
-        let mut ret = vec![
-            format!("{fname}.mop"),
+        let mut ret = self.input_files();
+        ret.extend([
             format!("{fname}.out"),
             format!("{fname}.arc"),
             format!("{fname}.aux"),
-        ];
+        ]);
         if let Some(f) = self.param_file.clone() {
             ret.push(f);
         }
@@ -185,13 +259,140 @@ Comment line 2
         self.charge
     }
 
+    fn spin(&self) -> usize {
+        self.spin
+    }
+
+    /// summarize the calculation as `Mopac method charge=c (n atoms)`, where
+    /// the method is the first semi-empirical Hamiltonian in the template
+    fn summary(&self) -> String {
+        let method = match self.keywords.as_ref().and_then(|k| k.method) {
+            Some(m) => m.to_string(),
+            None => self
+                .template
+                .header
+                .split_whitespace()
+                .find(|w| METHODS.iter().any(|m| w.eq_ignore_ascii_case(m)))
+                .unwrap_or("unknown")
+                .to_owned(),
+        };
+        format!(
+            "Mopac {method} charge={} ({} atoms)",
+            self.charge,
+            self.geom.natoms()
+        )
+    }
+
     fn infile(&self) -> String {
         self.filename() + ".mop"
     }
 }
 
+/// return the MOPAC keyword for the multiplicity of a state with `spin`
+/// unpaired electrons. returns an [Error::Input] if `spin` is more than MOPAC
+/// supports
+fn multiplicity(spin: usize) -> Result<&'static str, Error> {
+    const KEYWORDS: [&str; 9] = [
+        "SINGLET", "DOUBLET", "TRIPLET", "QUARTET", "QUINTET", "SEXTET",
+        "SEPTET", "OCTET", "NONET",
+    ];
+    KEYWORDS.get(spin).copied().ok_or_else(|| {
+        Error::Input(format!(
+            "MOPAC does not support {spin} unpaired electrons"
+        ))
+    })
+}
+
+/// return the version line from the header of a MOPAC output file, like
+/// `MOPAC2016 (Version: 22.047L)`
+fn output_version(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find(|l| l.contains("(Version:"))
+        .map(|l| l.trim().to_owned())
+}
+
+/// collect the numbers following each of `keys` in the contents of an aux
+/// file, from the rest of the key's line and the lines up to the next key, like
+///
+/// ```text
+///  VIB._FREQ:CM(-1)[0009]=
+///    214.220   767.557   911.426  1009.926  1042.994
+/// ```
+///
+/// keys are compared to the part of the line before any `[` or `=`
+fn aux_arrays<'a>(
+    contents: &str,
+    keys: &[&'a str],
+) -> HashMap<&'a str, Vec<f64>> {
+    let values = |s: &str| -> Vec<f64> {
+        s.split_whitespace()
+            .filter_map(|v| v.replace('D', "E").parse().ok())
+            .collect()
+    };
+    let mut ret: HashMap<_, Vec<f64>> = HashMap::new();
+    let mut cur = None;
+    for line in contents.lines() {
+        let line = line.trim_start();
+        if line.starts_with(|c: char| c.is_alphabetic()) {
+            let name = line.split(['[', '=']).next().unwrap_or_default();
+            cur = keys.iter().find(|&&k| k == name).copied();
+            if let (Some(key), Some((_, rest))) = (cur, line.split_once('=')) {
+                ret.entry(key).or_default().extend(values(rest));
+            }
+        } else if let Some(key) = cur {
+            ret.entry(key).or_default().extend(values(line));
+        }
+    }
+    ret
+}
+
+/// read the dipole vector from a `DIP_VEC` line of an aux file, converting it
+/// from Debye to atomic units. returns `None` if there are not exactly 3 valid
+/// components
+fn read_dipole(line: &str) -> Option<[f64; 3]> {
+    let (_, values) = line.split_once('=')?;
+    let values: Vec<f64> = values
+        .split_ascii_whitespace()
+        .map(|s| s.replace('D', "E").parse::<f64>().map(|d| d / DEBYE))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    values.try_into().ok()
+}
+
+/// read the vibrational frequencies and thermochemical quantities printed to
+/// an aux file by the `FORCE` and `THERMO` keywords. imaginary frequencies are
+/// already printed as negative values
+fn read_vibrations(contents: &str) -> (Option<Vec<f64>>, Vec<Thermo>) {
+    const FREQ: &str = "VIB._FREQ:CM(-1)";
+    const ZPE: &str = "ZERO_POINT_ENERGY:KCAL/MOL";
+    const TEMPS: &str = "THERMODYNAMIC_PROPERTIES_TEMPS:KELVIN";
+    const HOF: &str = "H_O_F(T):KCAL/MOL";
+    const ENTHALPY: &str = "ENTHALPY_TOT:CAL/MOL";
+    const ENTROPY: &str = "ENTROPY_TOT:CAL/K/MOL";
+    let mut arrays =
+        aux_arrays(contents, &[FREQ, ZPE, TEMPS, HOF, ENTHALPY, ENTROPY]);
+    let zpe = arrays.get(ZPE).and_then(|v| v.first().copied());
+    let get = |key| arrays.get(key).cloned().unwrap_or_default();
+    let (hof, enthalpy, entropy) = (get(HOF), get(ENTHALPY), get(ENTROPY));
+    let thermo = get(TEMPS)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, temperature)| {
+            Some(Thermo {
+                temperature,
+                zpe,
+                heat_of_formation: *hof.get(i)?,
+                enthalpy: *enthalpy.get(i)?,
+                entropy: *entropy.get(i)?,
+            })
+        })
+        .collect();
+    (arrays.remove(FREQ), thermo)
+}
+
 static READ_OUT_CELL: OnceLock<[Regex; 2]> = OnceLock::new();
-static READ_AUX_CELL: OnceLock<[Regex; 5]> = OnceLock::new();
+static READ_AUX_CELL: OnceLock<[Regex; 7]> = OnceLock::new();
 
 impl Mopac {
     pub fn new_full(
@@ -208,7 +409,9 @@ impl Mopac {
             param_file: None,
             param_dir: Some("tmparam".to_string()),
             charge,
+            spin: 0,
             template,
+            keywords: None,
         }
     }
 
@@ -218,7 +421,7 @@ impl Mopac {
     pub fn build_jobs(
         moles: Vec<Geom>,
         params: Option<&Params>,
-        dir: &'static str,
+        dir: &str,
         start_index: usize,
         coeff: f64,
         job_num: usize,
@@ -249,43 +452,52 @@ impl Mopac {
     }
 
     /// write the `params` to `filename`
-    pub fn write_params(params: &Params, filename: &str) {
-        let mut file = match File::create(filename) {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("failed to create {filename} with {e}");
-                std::process::exit(1);
-            }
-        };
-        write!(file, "{params}").expect("failed to write params file");
+    pub fn write_params(params: &Params, filename: &str) -> Result<(), Error> {
+        let body = params.to_string();
+        fs::current()
+            .write(filename, body.as_bytes())
+            .map_err(|e| Error::io(filename, e))
     }
 
     /// return the heat of formation from a MOPAC aux file in Hartrees.
-    /// `filename` should not include the .aux extension
+    /// `filename` should not include the .aux extension. If the aux file
+    /// contains a `GRADIENTS` block after the atomic charges, it is returned in
+    /// the original kcal/mol/Å units. the dipole moment is read from the
+    /// `DIP_VEC` line and converted from Debye to atomic units
     pub fn read_aux(filename: &str) -> Result<ProgramResult, ProgramError> {
+        Self::read_aux_with(filename, ParseSelectors::all())
+    }
+
+    /// like [Mopac::read_aux], but only extract the quantities chosen by
+    /// `select`
+    pub fn read_aux_with(
+        filename: &str,
+        select: ParseSelectors,
+    ) -> Result<ProgramResult, ProgramError> {
         let auxfile = format!("{}.aux", &filename);
-        let f = if let Ok(file) = File::open(&auxfile) {
-            file
-        } else {
+        let Ok(contents) = fs::current().read_to_string(&auxfile) else {
             return Err(ProgramError::FileNotFound(auxfile));
         };
-        let lines = BufReader::new(f).lines().flatten();
         let mut energy = None;
 
-        let [heat_re, atom_re, elt_re, charge_re, time_re] = READ_AUX_CELL
-            .get_or_init(|| {
+        let [heat_re, atom_re, elt_re, charge_re, time_re, grad_re, dip_re] =
+            READ_AUX_CELL.get_or_init(|| {
                 [
                     Regex::new("^ HEAT_OF_FORMATION").unwrap(),
                     Regex::new("^ ATOM_X_OPT").unwrap(),
                     Regex::new("^ ATOM_EL").unwrap(),
                     Regex::new("^ ATOM_CHARGES").unwrap(),
                     Regex::new("^ CPU_TIME:SEC=").unwrap(),
+                    Regex::new("^ GRADIENTS:KCAL/MOL/ANGSTROM").unwrap(),
+                    Regex::new(r"^ DIP_VEC:DEBYE\[3\]=").unwrap(),
                 ]
             });
         #[derive(PartialEq)]
         enum State {
             Geom,
             Labels,
+            Charges,
+            Gradient,
             Done,
             None,
         }
@@ -295,6 +507,7 @@ impl Mopac {
             atom: bool,
             element: bool,
             time: bool,
+            dipole: bool,
         }
         let mut state = State::None;
         let mut guard = Guard {
@@ -302,14 +515,17 @@ impl Mopac {
             atom: false,
             element: false,
             time: false,
+            dipole: false,
         };
         // atomic labels
         let mut labels = Vec::new();
         // coordinates
         let mut coords = Vec::new();
+        let mut gradient = Vec::new();
         let mut time = 0.0;
-        for line in lines {
-            if !guard.element && elt_re.is_match(&line) {
+        let mut dipole = None;
+        for line in contents.lines() {
+            if !guard.element && elt_re.is_match(line) {
                 state = State::Labels;
                 guard.element = true;
             } else if state == State::Labels {
@@ -318,7 +534,7 @@ impl Mopac {
                     .collect_into(&mut labels);
                 state = State::None;
             // line like HEAT_OF_FORMATION:KCAL/MOL=+0.97127947459164715838D+02
-            } else if !guard.heat && heat_re.is_match(&line) {
+            } else if !guard.heat && heat_re.is_match(line) {
                 let fields: Vec<&str> = line.trim().split('=').collect();
                 match fields[1].replace('D', "E").parse::<f64>() {
                     Ok(f) => {
@@ -329,7 +545,7 @@ impl Mopac {
                     }
                 }
                 guard.heat = true;
-            } else if !guard.time && time_re.is_match(&line) {
+            } else if !guard.time && time_re.is_match(line) {
                 time = line
                     .split('=')
                     .nth(1)
@@ -338,20 +554,44 @@ impl Mopac {
                     .parse()
                     .unwrap();
                 guard.time = true;
-            } else if !guard.atom && atom_re.is_match(&line) {
+            // line like DIP_VEC:DEBYE[3]= -0.23D-14 -0.44D-02 +0.46D+01
+            } else if !guard.dipole && dip_re.is_match(line) {
+                if select.multipoles {
+                    dipole = read_dipole(line);
+                }
+                guard.dipole = true;
+            } else if !guard.atom && atom_re.is_match(line) {
                 state = State::Geom;
                 guard.atom = true;
-            } else if state == State::Geom && charge_re.is_match(&line) {
+            } else if state == State::Geom && charge_re.is_match(line) {
+                state = State::Charges;
+            } else if state == State::Charges && grad_re.is_match(line) {
+                state = State::Gradient;
+            } else if matches!(state, State::Charges | State::Gradient)
+                && line.trim_start().starts_with(|c: char| c.is_alphabetic())
+            {
+                // the first key after the charges or gradient ends the section
                 state = State::Done;
                 break;
-            } else if state == State::Geom {
+            } else if state == State::Gradient && select.gradient {
+                for s in line.split_ascii_whitespace() {
+                    match s.replace('D', "E").parse::<f64>() {
+                        Ok(f) => gradient.push(f),
+                        Err(_) => {
+                            return Err(ProgramError::GradientParseError(
+                                auxfile,
+                            ))
+                        }
+                    }
+                }
+            } else if state == State::Geom && select.geometry {
                 coords.extend(
                     line.split_ascii_whitespace()
                         .map(|s| s.parse::<f64>().unwrap()),
                 );
             }
         }
-        if state != State::Done {
+        if !matches!(state, State::Charges | State::Gradient | State::Done) {
             return Err(ProgramError::GeomNotFound(auxfile));
         }
         let ret = coords
@@ -361,11 +601,39 @@ impl Mopac {
                 Atom::new_from_label(&l, coord[0], coord[1], coord[2])
             })
             .collect();
+        // the vibrational and thermochemical data follow the sections above,
+        // so only search for them when they are present
+        let (frequencies, thermo) =
+            if select.frequencies && contents.contains("VIB._FREQ") {
+                read_vibrations(&contents)
+            } else {
+                (None, Vec::new())
+            };
         if let Some(energy) = energy {
             Ok(ProgramResult {
                 energy,
-                cart_geom: Some(ret),
+                cart_geom: select.geometry.then_some(ret),
                 time,
+                gradient: if gradient.is_empty() {
+                    None
+                } else {
+                    Some(Gradient {
+                        values: gradient,
+                        unit: GradientUnit::KcalMolAng,
+                    })
+                },
+                cpu_time: Some(time),
+                wall_time: None,
+                opt_steps: Vec::new(),
+                dipole,
+                quadrupole: None,
+                convergence: None,
+                nbasis: None,
+                nprimitive: None,
+                frequencies,
+                timings: Timings::default(),
+                spin_contamination: None,
+                thermo,
             })
         } else {
             Err(ProgramError::EnergyNotFound(auxfile))
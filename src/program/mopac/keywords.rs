@@ -0,0 +1,136 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+use super::METHODS;
+
+/// The semi-empirical Hamiltonians that can be selected with [Keywords]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MopacMethod {
+    Am1,
+    Pm3,
+    Pm6,
+    Pm7,
+    Rm1,
+}
+
+impl Display for MopacMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            MopacMethod::Am1 => "AM1",
+            MopacMethod::Pm3 => "PM3",
+            MopacMethod::Pm6 => "PM6",
+            MopacMethod::Pm7 => "PM7",
+            MopacMethod::Rm1 => "RM1",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Typed MOPAC keywords, rendered by [Keywords::render] and appended to the
+/// [super::Template] header by `write_input`. `raw` holds any other keywords,
+/// which are emitted verbatim
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Keywords {
+    pub method: Option<MopacMethod>,
+
+    /// the `PRECISE` keyword, tightening the SCF and geometry criteria
+    pub precise: bool,
+
+    /// the `GEO-OK` keyword, skipping the check for atoms that are too close
+    pub geo_ok: bool,
+
+    /// the SCF convergence criterion in kcal/mol, via `SCFCRT`
+    pub scfcrt: Option<f64>,
+
+    /// the maximum number of SCF iterations, via `ITRY`. must be positive if
+    /// set
+    pub itry: Option<usize>,
+
+    /// the `PULAY` keyword, using Pulay's DIIS method to converge the SCF
+    pub pulay: bool,
+
+    pub raw: Vec<String>,
+}
+
+/// return the keyword name of `word`, the part before any `=` or `(`, in upper
+/// case
+fn name(word: &str) -> String {
+    word.split(['=', '('])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase()
+}
+
+impl Keywords {
+    pub fn new(method: MopacMethod) -> Self {
+        Self {
+            method: Some(method),
+            ..Self::default()
+        }
+    }
+
+    /// render the keywords as a space-separated string, after checking that
+    /// they do not conflict with each other or with the keywords already in
+    /// `header`. returns an [Error::Input] if the method is given more than
+    /// once, if any keyword is repeated, if `PRECISE` is combined with
+    /// `SCFCRT`, or if `ITRY` is zero
+    pub fn render(&self, header: &str) -> Result<String, Error> {
+        let mut words: Vec<String> = Vec::new();
+        if let Some(method) = self.method {
+            words.push(method.to_string());
+        }
+        if self.precise {
+            words.push("PRECISE".to_owned());
+        }
+        if self.geo_ok {
+            words.push("GEO-OK".to_owned());
+        }
+        if let Some(scfcrt) = self.scfcrt {
+            words.push(format!("SCFCRT={scfcrt:e}").replace('e', "D"));
+        }
+        if let Some(itry) = self.itry {
+            if itry == 0 {
+                return Err(Error::Input("ITRY must be positive".to_owned()));
+            }
+            words.push(format!("ITRY={itry}"));
+        }
+        if self.pulay {
+            words.push("PULAY".to_owned());
+        }
+        words.extend(self.raw.iter().cloned());
+
+        let all: Vec<_> = header
+            .split_whitespace()
+            .map(name)
+            .chain(words.iter().map(|w| name(w)))
+            .collect();
+        let methods: Vec<_> = all
+            .iter()
+            .filter(|w| METHODS.contains(&w.as_str()))
+            .collect();
+        if methods.len() > 1 {
+            return Err(Error::Input(format!(
+                "multiple MOPAC methods given: {methods:?}"
+            )));
+        }
+        for (i, w) in all.iter().enumerate() {
+            if all[..i].contains(w) {
+                return Err(Error::Input(format!(
+                    "MOPAC keyword {w} given twice"
+                )));
+            }
+        }
+        if all.iter().any(|w| w == "PRECISE")
+            && all.iter().any(|w| w == "SCFCRT")
+        {
+            return Err(Error::Input(
+                "MOPAC keywords PRECISE and SCFCRT are mutually exclusive"
+                    .to_owned(),
+            ));
+        }
+        Ok(words.join(" "))
+    }
+}
@@ -1,10 +1,11 @@
 use std::collections::HashSet;
-use std::fs::{self, read_to_string};
+use std::fs::{self, read_to_string, File};
+use std::io::Write;
 
 use crate::program::Procedure;
 use crate::string;
 
-use crate::queue::{self, Queue, SubQueue, Submit};
+use crate::queue::{self, Queue, SubQueue, Submission, Submit};
 
 use super::*;
 
@@ -45,7 +46,7 @@ fn test_write_input() {
         ..test_mopac()
     };
     tm.param_dir = Some("/tmp".to_string());
-    tm.write_input(Procedure::SinglePt);
+    tm.write_input(Procedure::SinglePt).unwrap();
     let got = fs::read_to_string("/tmp/test.mop").expect("file not found");
     let want = "scfcrt=1.D-21 aux(precision=14) PM6 A0 charge=0 1SCF XYZ
 Comment line 1
@@ -57,11 +58,97 @@ Comment line 2
     fs::remove_file("/tmp/test.mop").unwrap();
 }
 
+#[test]
+fn test_mem_fs() {
+    use crate::fs::{FileSystem, MemFs};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let prev = crate::fs::set(mem.clone());
+    let mut tm = Mopac {
+        params: None,
+        filename: "mem/test".to_owned(),
+        ..test_mopac()
+    };
+    tm.write_input(Procedure::SinglePt).unwrap();
+    assert_eq!(mem.paths(), vec!["mem/test.mop"]);
+    assert!(!std::path::Path::new("mem/test.mop").exists());
+    let got = mem.read_to_string("mem/test.mop").unwrap();
+    assert!(got.starts_with("scfcrt=1.D-21 aux(precision=14) PM6 A0 charge=0"));
+
+    mem.write("mem/job.aux", &fs::read("testfiles/job.aux").unwrap())
+        .unwrap();
+    let got = Mopac::read_output("mem/job").unwrap();
+
+    crate::fs::set(prev);
+    let want = Mopac::read_output("testfiles/job").unwrap();
+    assert_eq!(got, want);
+}
+
+#[test]
+fn test_not_converged() {
+    use crate::fs::{FileSystem, MemFs};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let aux = fs::read("testfiles/opt.aux").unwrap();
+    let out = read_to_string("testfiles/opt.out").unwrap();
+    for (name, msg) in [
+        ("scf", " UNABLE TO ACHIEVE SELF-CONSISTENCE\n"),
+        ("opt", " EXCESS NUMBER OF OPTIMIZATION CYCLES\n"),
+    ] {
+        mem.write(&format!("mem/{name}.aux"), &aux).unwrap();
+        mem.write(&format!("mem/{name}.out"), (out.clone() + msg).as_bytes())
+            .unwrap();
+    }
+    let prev = crate::fs::set(mem);
+    let scf = Mopac::read_output("mem/scf");
+    let opt = Mopac::read_output("mem/opt");
+    crate::fs::set(prev);
+    assert_eq!(
+        scf.unwrap_err(),
+        ProgramError::ScfNotConverged("mem/scf.out".to_owned())
+    );
+    assert_eq!(
+        opt.unwrap_err(),
+        ProgramError::OptNotConverged("mem/opt.out".to_owned())
+    );
+}
+
+#[test]
+fn test_write_input_error() {
+    let mut tm = Mopac {
+        filename: "/tmp/missing/dir/test".to_owned(),
+        params: None,
+        ..test_mopac()
+    };
+    let err = tm.write_input(Procedure::SinglePt).unwrap_err();
+    assert!(matches!(err, crate::Error::Io(_)));
+    assert!(err.to_string().contains("/tmp/missing/dir/test.mop"));
+}
+
+#[test]
+fn test_input_files() {
+    let tm = Mopac {
+        param_file: Some("/tmp/params".to_owned()),
+        ..test_mopac()
+    };
+    assert_eq!(tm.input_files(), vec!["/tmp/test.mop"]);
+    assert_eq!(
+        tm.associated_files(),
+        vec![
+            "/tmp/test.mop",
+            "/tmp/test.out",
+            "/tmp/test.arc",
+            "/tmp/test.aux",
+            "/tmp/params"
+        ]
+    );
+}
+
 #[test]
 fn test_write_input_with_params() {
     let mut tm = test_mopac();
     tm.param_dir = Some("/tmp".to_string());
-    tm.write_input(Procedure::SinglePt);
+    tm.write_input(Procedure::SinglePt).unwrap();
     let got = fs::read_to_string("/tmp/test.mop").expect("file not found");
     let want = format!(
         "scfcrt=1.D-21 aux(precision=14) PM6 A0 charge=0 1SCF \
@@ -79,7 +166,8 @@ Comment line 2
 #[test]
 fn test_write_params() {
     let tm = test_mopac();
-    Mopac::write_params(&tm.params.unwrap(), &String::from("/tmp/params.dat"));
+    Mopac::write_params(&tm.params.unwrap(), &String::from("/tmp/params.dat"))
+        .unwrap();
     let got = fs::read_to_string("/tmp/params.dat").expect("file not found");
     let want = "USS H -11.246958000000
 ZS H 1.268641000000
@@ -171,11 +259,10 @@ fn test_read_output() {
     ];
     assert_eq!(got, Some(want));
 
-    // failure (no termination message) in output - now catches noaux error
-    // instead
+    // failure (no termination message) in output
     let f = String::from("testfiles/nojob");
     let got = Mopac::read_output(&f);
-    assert_eq!(got.err().unwrap(), ProgramError::FileNotFound(f + ".aux"));
+    assert_eq!(got.err().unwrap(), ProgramError::Truncated(f + ".out"));
 
     // failure in aux
     let f = String::from("testfiles/noaux");
@@ -194,14 +281,18 @@ struct TestQueue;
 impl Submit<Mopac> for TestQueue {}
 
 impl Queue<Mopac> for TestQueue {
-    fn write_submit_script(&self, infiles: &[String], filename: &str) {
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), crate::Error> {
         let mut body = String::new();
         for f in infiles {
             body.push_str(&format!("echo {f}\n"));
         }
-        let mut file =
-            File::create(filename).expect("failed to create params file");
-        write!(file, "{body}").expect("failed to write params file");
+        let mut file = File::create(filename)?;
+        write!(file, "{body}")?;
+        Ok(())
     }
 
     fn default_submit_script(&self) -> String {
@@ -232,11 +323,11 @@ impl SubQueue<Mopac> for TestQueue {
         "inp"
     }
 
-    fn stat_cmd(&self) -> String {
+    fn stat_cmd(&self) -> Result<String, crate::Error> {
         todo!()
     }
 
-    fn status(&self) -> HashSet<String> {
+    fn status(&self) -> Result<HashSet<String>, crate::Error> {
         todo!()
     }
 
@@ -251,9 +342,10 @@ fn test_submit() {
     tq.write_submit_script(
         &string!["input1.mop", "input2.mop", "input3.mop"],
         "/tmp/main.pbs",
-    );
-    let got = tq.submit("/tmp/main.pbs");
-    let want = "input3.mop";
+    )
+    .unwrap();
+    let got = tq.submit("/tmp/main.pbs").unwrap();
+    let want = Submission::new("input3.mop".to_owned(), "/tmp/main.pbs");
     assert_eq!(got, want);
 }
 
@@ -262,7 +354,7 @@ fn test_resubmit() {
     use std::path::Path;
     let tq = TestQueue;
     std::fs::copy("testfiles/job.mop", "/tmp/job.mop").unwrap();
-    let got = tq.resubmit("/tmp/job.mop");
+    let got = tq.resubmit("/tmp/job.mop").unwrap();
     assert!(Path::new("/tmp/job_redo.mop").exists());
     assert!(Path::new("/tmp/job_redo.pbs").exists());
     assert_eq!(
@@ -280,3 +372,267 @@ fn test_resubmit() {
         std::fs::remove_file(f).unwrap();
     }
 }
+
+#[test]
+fn test_export_bundle() {
+    let tq = TestQueue;
+    let dir = "/tmp/export_bundle";
+    std::fs::create_dir_all(dir).unwrap();
+    let mut jobs: Vec<_> = (0..2)
+        .map(|i| {
+            Job::new(
+                Mopac::new(
+                    format!("{dir}/job.{i}"),
+                    Template::from("PM6"),
+                    0,
+                    Geom::default(),
+                ),
+                i,
+            )
+        })
+        .collect();
+    let got = tq
+        .export_bundle(dir, &mut jobs, Procedure::SinglePt)
+        .unwrap();
+    assert_eq!(got, vec![format!("{dir}/main0.pbs")]);
+    assert!(std::path::Path::new(&format!("{dir}/job.1.mop")).exists());
+    assert_eq!(
+        read_to_string(format!("{dir}/manifest.txt")).unwrap(),
+        format!("{dir}/main0.pbs {dir}/job.0.mop {dir}/job.1.mop\n")
+    );
+    assert_eq!(
+        read_to_string(format!("{dir}/submit_all.sh")).unwrap(),
+        format!("#!/bin/sh\nset -e\nbash {dir}/main0.pbs\n")
+    );
+    assert!(jobs.iter().all(|j| j.job_id.is_empty()));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// write an executable script standing in for a program binary to `path`
+fn fake_binary(path: &str, body: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::write(path, format!("#!/bin/sh\n{body}\n")).unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn test_check_binary() {
+    let path = "/tmp/fake_mopac";
+    // copy the header of a real output to the one MOPAC would write
+    let job = concat!(env!("CARGO_MANIFEST_DIR"), "/testfiles/job.out");
+    fake_binary(path, &format!(r#"cp {job} "${{1%.mop}}.out""#));
+    let got = Mopac::check_binary(path).unwrap();
+    assert_eq!(got, "MOPAC2016 (Version: 22.047L)");
+
+    let tq = TestQueue;
+    let dir = "/tmp/export_checked_bundle";
+    fs::create_dir_all(dir).unwrap();
+    let mut jobs = vec![Job::new(
+        Mopac::new(
+            format!("{dir}/job.0"),
+            Template::from("PM6"),
+            0,
+            Geom::default(),
+        ),
+        0,
+    )];
+    tq.export_checked_bundle(dir, &mut jobs, Procedure::SinglePt, path)
+        .unwrap();
+    assert_eq!(
+        read_to_string(format!("{dir}/manifest.txt")).unwrap(),
+        format!(
+            "# {path} MOPAC2016 (Version: 22.047L)\n\
+             {dir}/main0.pbs {dir}/job.0.mop\n"
+        )
+    );
+    fs::remove_dir_all(dir).unwrap();
+
+    // a binary that fails to run
+    fake_binary(path, "echo 'license expired' >&2; exit 1");
+    let err = Mopac::check_binary(path).unwrap_err().to_string();
+    assert!(err.contains("license expired"), "{err}");
+    fs::remove_file(path).unwrap();
+
+    // and one that doesn't exist, including the bundle not being written
+    let err = tq
+        .export_checked_bundle(dir, &mut jobs, Procedure::SinglePt, path)
+        .unwrap_err();
+    let not_found = std::io::ErrorKind::NotFound;
+    assert!(matches!(err, crate::Error::Io(e) if e.kind() == not_found));
+    assert!(!std::path::Path::new(dir).exists());
+}
+
+#[test]
+fn test_read_dipole() {
+    let got = Mopac::read_output("testfiles/job").unwrap().dipole.unwrap();
+    let want = [
+        -0.2306836538587687394e-14,
+        -0.4419218370564692577e-02,
+        0.4648006068598752982e+01,
+    ]
+    .map(|d| d / DEBYE);
+    assert_eq!(got, want);
+
+    let got = Mopac::read_aux_with(
+        "testfiles/job",
+        ParseSelectors {
+            multipoles: false,
+            ..ParseSelectors::all()
+        },
+    )
+    .unwrap();
+    assert!(got.dipole.is_none());
+}
+
+#[test]
+fn test_read_gradient() {
+    let got = Mopac::read_output("testfiles/opt")
+        .unwrap()
+        .gradient
+        .unwrap();
+    assert_eq!(got.unit, crate::program::GradientUnit::KcalMolAng);
+    let want = [
+        -0.487990964009920702,
+        -0.134000773448502114,
+        0.386866740695844813,
+        -0.055800016418922821,
+    ];
+    assert!(crate::util::gradient_close(&got.values, &want, 1e-12));
+
+    // single point without the GRADIENTS keyword
+    let got = Mopac::read_output("testfiles/job").unwrap();
+    assert!(got.gradient.is_none());
+}
+
+#[test]
+fn test_read_output_with() {
+    use crate::program::ParseSelectors;
+    let all = Mopac::read_output("testfiles/opt").unwrap();
+    let got =
+        Mopac::read_output_with("testfiles/opt", ParseSelectors::default())
+            .unwrap();
+    assert_eq!(got.energy, all.energy);
+    assert_eq!(got.time, all.time);
+    assert!(got.cart_geom.is_none());
+    assert!(got.gradient.is_none());
+
+    let select = ParseSelectors {
+        gradient: true,
+        ..Default::default()
+    };
+    let got = Mopac::read_output_with("testfiles/opt", select).unwrap();
+    assert_eq!(got.gradient, all.gradient);
+    assert!(got.cart_geom.is_none());
+
+    let got = Mopac::read_output_with("testfiles/freq", select).unwrap();
+    assert!(got.frequencies.is_none());
+    assert!(got.thermo.is_empty());
+}
+
+#[test]
+fn test_read_vibrations() {
+    use crate::program::StationaryPoint;
+    let got = Mopac::read_output("testfiles/freq").unwrap();
+    let freqs = got.frequencies.as_ref().unwrap();
+    assert_eq!(freqs.len(), 9);
+    assert_eq!(freqs[0], 214.220);
+    assert_eq!(freqs[8], 3180.678);
+    assert_eq!(got.stationary_point(10.0), Some(StationaryPoint::Minimum));
+
+    assert_eq!(got.thermo.len(), 2);
+    let thermo = got.thermo[0];
+    assert_eq!(thermo.temperature, 298.0);
+    assert_eq!(thermo.zpe, Some(0.21046413924363626522e2));
+    assert_eq!(thermo.heat_of_formation, 0.99650841968250389882e2);
+    assert_eq!(thermo.enthalpy, 0.25228944606392698035e4);
+    assert_eq!(thermo.entropy, 0.64763234735429848487e2);
+    let want = 0.99650841968250389882e2 - 298.0 * 0.64763234735429848487e-1;
+    assert!((thermo.gibbs() - want).abs() < 1e-12);
+    assert_eq!(got.thermo[1].temperature, 400.0);
+
+    // without FORCE or THERMO
+    let got = Mopac::read_output("testfiles/job").unwrap();
+    assert!(got.frequencies.is_none());
+    assert!(got.thermo.is_empty());
+}
+
+#[test]
+fn test_summary() {
+    let mut tm = test_mopac();
+    assert_eq!(tm.summary(), "Mopac PM6 charge=0 (0 atoms)");
+    tm.template = Template::from("scfcrt=1.D-21 aux(precision=14)");
+    tm.charge = 1;
+    assert_eq!(tm.summary(), "Mopac unknown charge=1 (0 atoms)");
+}
+
+#[test]
+fn test_keywords() {
+    let mut tm = Mopac {
+        params: None,
+        filename: "/tmp/keywords".to_owned(),
+        template: Template::from("aux(precision=14)"),
+        keywords: Some(Keywords {
+            precise: true,
+            geo_ok: true,
+            raw: string!["A0"],
+            ..Keywords::new(MopacMethod::Pm7)
+        }),
+        ..test_mopac()
+    };
+    assert_eq!(tm.summary(), "Mopac PM7 charge=0 (0 atoms)");
+    tm.write_input(Procedure::SinglePt).unwrap();
+    let got = fs::read_to_string("/tmp/keywords.mop").unwrap();
+    assert!(got.starts_with(
+        "aux(precision=14) PM7 PRECISE GEO-OK A0 charge=0 1SCF XYZ\n"
+    ));
+    fs::remove_file("/tmp/keywords.mop").unwrap();
+
+    let kw = Keywords {
+        scfcrt: Some(1e-21),
+        itry: Some(500),
+        pulay: true,
+        ..Keywords::default()
+    };
+    assert_eq!(kw.render("PM6").unwrap(), "SCFCRT=1D-21 ITRY=500 PULAY");
+}
+
+#[test]
+fn test_keywords_two_methods() {
+    let got = Keywords::new(MopacMethod::Pm7).render("scfcrt=1.D-21 PM6");
+    assert!(matches!(got, Err(Error::Input(e)) if e.contains("multiple")));
+}
+
+#[test]
+fn test_keywords_exclusive() {
+    let kw = Keywords {
+        precise: true,
+        ..Keywords::default()
+    };
+    let got = kw.render("scfcrt=1.D-21 aux(precision=14) PM6");
+    assert!(
+        matches!(got, Err(Error::Input(e)) if e.contains("mutually exclusive"))
+    );
+}
+
+#[test]
+fn test_spin() {
+    let mut tm = Mopac {
+        params: None,
+        filename: "/tmp/spin".to_owned(),
+        spin: 2,
+        ..test_mopac()
+    };
+    assert_eq!(tm.spin(), 2);
+    tm.write_input(Procedure::SinglePt).unwrap();
+    let got = fs::read_to_string("/tmp/spin.mop").unwrap();
+    assert!(got.starts_with(
+        "scfcrt=1.D-21 aux(precision=14) PM6 A0 charge=0 TRIPLET 1SCF"
+    ));
+    fs::remove_file("/tmp/spin.mop").unwrap();
+
+    tm.spin = 9;
+    assert!(matches!(
+        tm.write_input(Procedure::SinglePt),
+        Err(Error::Input(_))
+    ));
+}
@@ -1,18 +1,21 @@
 use std::{
     cell::LazyCell,
-    collections::{HashMap, HashSet},
-    path::Path,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
     process::Command,
-    str,
-    time::Duration,
+    sync::{Condvar, Mutex},
+    time::{Duration, SystemTime},
 };
 
 use crate::{
     geom::Geom,
     program::{Procedure, Program, ProgramError},
+    Error,
 };
 use crate::{
-    program::{Job, ProgramResult},
+    program::{h2, Job, ProgramResult, Template},
     time,
 };
 
@@ -21,11 +24,10 @@ pub mod pbs;
 pub mod slurm;
 use drain::*;
 use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
 mod drain;
 
-pub use drain::Check;
-
-static DEBUG: bool = false;
+pub use drain::{Check, Eta, Progress};
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct Resubmit {
@@ -34,35 +36,666 @@ pub struct Resubmit {
     pub job_id: String,
 }
 
+/// a counting semaphore limiting the number of concurrent calls to
+/// [Submit::submit] across the whole process, to avoid spawning a storm of
+/// `qsub` processes when chunks are built in parallel
+struct SubmitLimiter {
+    count: Mutex<usize>,
+    cvar: Condvar,
+}
+
+static SUBMIT_LIMITER: SubmitLimiter = SubmitLimiter {
+    count: Mutex::new(0),
+    cvar: Condvar::new(),
+};
+
+impl SubmitLimiter {
+    /// block until fewer than `limit` submissions are in progress, returning
+    /// a permit that releases its slot when dropped
+    fn acquire(&self, limit: usize) -> SubmitPermit<'_> {
+        let mut count = self.count.lock().unwrap();
+        while *count >= limit.max(1) {
+            count = self.cvar.wait(count).unwrap();
+        }
+        *count += 1;
+        SubmitPermit(self)
+    }
+}
+
+struct SubmitPermit<'a>(&'a SubmitLimiter);
+
+impl Drop for SubmitPermit<'_> {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap() -= 1;
+        self.0.cvar.notify_one();
+    }
+}
+
+/// Bounds for adaptively sizing chunks of jobs. When enabled via
+/// [SubQueue::adaptive_chunks], the chunk size starts at
+/// [SubQueue::chunk_size] and is adjusted between `min` and `max` so that each
+/// chunk takes about `target_time` seconds to run, based on the job times
+/// observed so far. The chunk size is also halved whenever a job has to be
+/// resubmitted, which usually means it ran out of time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptiveChunks {
+    pub min: usize,
+    pub max: usize,
+    pub target_time: usize,
+}
+
+/// Settings for detecting chunks with a high fraction of failed jobs, which
+/// usually share a fixable cause. When the fraction of failed jobs in a chunk
+/// exceeds `threshold`, the chunk and its first error are reported, and the
+/// run is stopped with [ProgramError::ChunkFailure] if `pause` is set
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkFailure {
+    pub threshold: f64,
+    pub pause: bool,
+}
+
+/// Settings for backing off when the scheduler is slow to respond. When a
+/// `qstat` or `qsub` call takes longer than `threshold` seconds, the polling
+/// interval is stretched by the latency in multiples of `threshold`, up to
+/// `max_factor` times
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Throttle {
+    pub threshold: f64,
+    pub max_factor: u32,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self {
+            threshold: 5.0,
+            max_factor: 8,
+        }
+    }
+}
+
+/// When to keep the scratch directory of a Molpro submit script instead of
+/// removing it with `rm -rf $TMPDIR` after the last job
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeepScratch {
+    /// always remove the scratch directory
+    #[default]
+    Never,
+
+    /// keep the scratch directory for inspection if any job in the script
+    /// exits with a non-zero status, and remove it otherwise
+    OnFailure,
+
+    /// never remove the scratch directory, so that it is available for
+    /// restarting
+    Always,
+}
+
+/// Options shared by all of the queue types, embedded in each of them as their
+/// `options` field and returned by the corresponding [SubQueue] methods
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueueOptions {
+    /// the total number of job failures and resubmissions to allow before
+    /// aborting a run. defaults to `None`, meaning no limit
+    pub retry_budget: Option<usize>,
+
+    /// gzip the files of successfully-parsed jobs instead of deleting them,
+    /// or keeping them uncompressed if `no_del` is set. defaults to false
+    pub compress_on_success: bool,
+
+    /// the maximum number of concurrent submissions. defaults to 4
+    pub submit_limit: usize,
+
+    /// shuffle the jobs with this seed before submitting them, spreading
+    /// correlated failures across the run. defaults to `None`, submitting the
+    /// jobs in input order
+    pub shuffle_seed: Option<u64>,
+
+    /// how long, in seconds, to wait for a newly-submitted job to appear in
+    /// the queue status before treating it as never-started and resubmitting
+    /// it. defaults to `None`, resubmitting such jobs immediately
+    pub start_grace: Option<usize>,
+
+    /// adjust the chunk size within these bounds based on the observed job
+    /// times. defaults to `None`, always using `chunk_size`
+    pub adaptive_chunks: Option<AdaptiveChunks>,
+
+    /// report, and optionally stop on, chunks with a high fraction of failed
+    /// jobs. defaults to `None`, disabling the check
+    pub chunk_failure: Option<ChunkFailure>,
+
+    /// the number of seconds of clock skew to allow when checking for output
+    /// files older than their job's submission. defaults to `None`, disabling
+    /// the check
+    pub stale_tolerance: Option<usize>,
+
+    /// skip rewriting submit scripts whose files already have the same
+    /// contents, reducing filesystem churn when resuming a run. defaults to
+    /// false, always writing them
+    pub skip_unchanged_scripts: bool,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            retry_budget: None,
+            compress_on_success: false,
+            submit_limit: 4,
+            shuffle_seed: None,
+            start_grace: None,
+            adaptive_chunks: None,
+            chunk_failure: None,
+            stale_tolerance: None,
+            skip_unchanged_scripts: false,
+        }
+    }
+}
+
+/// record the job id of `sub` in `qstat`, the map of outstanding job ids to
+/// the submit scripts that produced them. schedulers have occasionally been
+/// observed to reuse or mis-report ids under heavy load, so print a warning if
+/// the id is already outstanding rather than silently attributing two scripts
+/// to the same job. returns false in the case of such a collision. empty ids,
+/// as returned by queues without real job ids, are never reported
+pub(crate) fn insert_job_id(
+    qstat: &mut HashMap<String, String>,
+    sub: Submission,
+) -> bool {
+    let Submission { job_id, script } = sub;
+    if let Some(prev) = qstat.get(&job_id).filter(|_| !job_id.is_empty()) {
+        log::warn!(
+            "job id {job_id} returned for {script} is already \
+             outstanding for {prev}"
+        );
+        return false;
+    }
+    qstat.insert(job_id, script);
+    true
+}
+
+/// push an `export KEY=VALUE` line onto `body` for each pair in `env`. the
+/// values are emitted verbatim, so any shell quoting must be included in them
+pub(crate) fn push_exports(body: &mut String, env: &[(String, String)]) {
+    for (key, value) in env {
+        body.push_str(&format!("export {key}={value}\n"));
+    }
+}
+
+/// An environment module to load in a submit script, rendered as `name` or
+/// `name/version` in a `module load` line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module {
+    pub name: String,
+
+    /// the version to load, or `None` for the module system's default
+    pub version: Option<String>,
+}
+
+impl Module {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: None,
+        }
+    }
+
+    pub fn with_version(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            version: Some(version.to_owned()),
+        }
+    }
+}
+
+impl std::fmt::Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(v) => write!(f, "{}/{v}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// push a `module purge` line onto `body` if `purge` is true, followed by a
+/// `module load` line for `modules` in order, if there are any. returns an
+/// [Error::Input] if any of the names or versions contain characters other than
+/// ASCII letters, digits, and `._+-`, or `/` in a name, since they are emitted
+/// unquoted
+pub(crate) fn push_modules(
+    body: &mut String,
+    modules: &[Module],
+    purge: bool,
+) -> Result<(), Error> {
+    let safe = |s: &str, extra: &str| {
+        !s.is_empty()
+            && s.chars().all(|c| {
+                c.is_ascii_alphanumeric()
+                    || "._+-".contains(c)
+                    || extra.contains(c)
+            })
+    };
+    for m in modules {
+        if !safe(&m.name, "/") {
+            return Err(Error::Input(format!(
+                "invalid module name `{}`",
+                m.name
+            )));
+        }
+        if let Some(v) = m.version.as_ref().filter(|v| !safe(v, "")) {
+            return Err(Error::Input(format!(
+                "invalid version `{v}` for module {}",
+                m.name
+            )));
+        }
+    }
+    if purge {
+        body.push_str("module purge\n");
+    }
+    if !modules.is_empty() {
+        let modules: Vec<_> = modules.iter().map(Module::to_string).collect();
+        body.push_str(&format!("module load {}\n", modules.join(" ")));
+    }
+    Ok(())
+}
+
+/// write the submit script `body` to `filename`, unless `skip_unchanged` is
+/// set and the file already holds exactly `body`, as for most chunks when
+/// resuming a run. the existing file is only read when its length matches
+pub(crate) fn write_script(
+    filename: &str,
+    body: &str,
+    skip_unchanged: bool,
+) -> Result<(), Error> {
+    if skip_unchanged
+        && std::fs::metadata(filename)
+            .is_ok_and(|meta| meta.len() == body.len() as u64)
+        && std::fs::read(filename).is_ok_and(|old| old == body.as_bytes())
+    {
+        return Ok(());
+    }
+    std::fs::write(filename, body).map_err(|e| Error::io(filename, e))
+}
+
+/// create a new temporary directory for running a whole campaign in, as for
+/// [local::Local::in_temp_dir], returning its path along with the [TempDir]
+/// that removes it, and everything in it, when dropped
+pub(crate) fn temp_dir() -> std::io::Result<(String, TempDir)> {
+    let tmp = tempfile::Builder::new().prefix("psqs").tempdir()?;
+    let Some(path) = tmp.path().to_str() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("temporary directory {:?} is not valid UTF-8", tmp.path()),
+        ));
+    };
+    Ok((path.to_owned(), tmp))
+}
+
+/// split `infiles` into the groups to pass to each invocation of a program
+/// that accepts multiple input files, like MOPAC. each group has at most
+/// `batch_size` files, or one if `batch_size` is `None`
+pub(crate) fn batches(
+    infiles: &[String],
+    batch_size: Option<usize>,
+) -> std::slice::Chunks<'_, String> {
+    infiles.chunks(batch_size.unwrap_or(1).max(1))
+}
+
+/// return the MOPAC input files for the input names in `batch`, separated by
+/// spaces for a single `mopac` command line
+pub(crate) fn mop_files(batch: &[String]) -> String {
+    batch
+        .iter()
+        .map(|f| format!("{f}.mop"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// insert `directives` into the submit script `body` after its last line
+/// starting with `prefix`, or after the first line if there are none. returns
+/// an [Error::Input] if any of the directives do not start with `prefix`
+pub(crate) fn insert_directives(
+    body: &str,
+    prefix: &str,
+    directives: &[String],
+) -> Result<String, Error> {
+    if directives.is_empty() {
+        return Ok(body.to_owned());
+    }
+    if let Some(d) = directives.iter().find(|d| !d.starts_with(prefix)) {
+        return Err(Error::Input(format!(
+            "extra directive `{d}` does not start with `{prefix}`"
+        )));
+    }
+    let lines: Vec<_> = body.lines().collect();
+    let pos = lines
+        .iter()
+        .rposition(|l| l.starts_with(prefix))
+        .map_or(lines.len().min(1), |p| p + 1);
+    let mut ret = String::with_capacity(body.len());
+    for line in lines[..pos]
+        .iter()
+        .copied()
+        .chain(directives.iter().map(String::as_str))
+        .chain(lines[pos..].iter().copied())
+    {
+        ret.push_str(line);
+        ret.push('\n');
+    }
+    Ok(ret)
+}
+
+/// The state of a job in the queue, as reported by
+/// [SubQueue::status_detailed]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Queued,
+
+    /// held by the user or the scheduler, so it won't run without
+    /// intervention
+    Held,
+
+    /// exiting after running, which is part of finishing normally on PBS
+    Exiting,
+
+    /// any other state letter reported by the scheduler
+    Other(String),
+
+    /// the queue doesn't report job states
+    Unknown,
+}
+
+impl JobState {
+    /// parse a PBS state letter, like the `S` column of `qstat`
+    pub fn from_pbs(state: &str) -> Self {
+        match state {
+            "R" => Self::Running,
+            "Q" => Self::Queued,
+            "H" => Self::Held,
+            "E" => Self::Exiting,
+            s => Self::Other(s.to_owned()),
+        }
+    }
+
+    /// whether a job in this state will never finish on its own, meaning
+    /// that it should be treated as no longer in the queue. only held jobs
+    /// qualify, since exiting jobs are still writing their output
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Held)
+    }
+}
+
+/// The result of [Submit::submit], pairing the job id returned by the queue
+/// with the submit script that produced it
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Submission {
+    pub job_id: String,
+    pub script: String,
+}
+
+impl Submission {
+    pub fn new(job_id: String, script: &str) -> Self {
+        Self {
+            job_id,
+            script: script.to_owned(),
+        }
+    }
+}
+
+/// The error returned by [Submit::submit] when the queue rejects a submit
+/// script or the submit command cannot be run, carrying enough of the submit
+/// command's output for a driver to decide whether to back off and try again
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubmitError {
+    /// the submit script that was not submitted
+    pub script: String,
+
+    /// the submit command that was run, with its arguments, or empty if it
+    /// is not known
+    pub command: String,
+
+    /// the exit code of the last attempt, or `None` if the submit command
+    /// could not be run or was killed by a signal
+    pub status: Option<i32>,
+
+    /// the standard output of the last attempt, or empty if the submit
+    /// command could not be run
+    pub stdout: String,
+
+    /// the standard error of the last attempt, or the reason the submit
+    /// command could not be run
+    pub stderr: String,
+}
+
+impl SubmitError {
+    /// build a [SubmitError] for `script` from the `output` of its last
+    /// submission attempt with `command`
+    pub fn from_output(
+        script: &str,
+        command: &Command,
+        output: &std::process::Output,
+    ) -> Self {
+        Self {
+            script: script.to_owned(),
+            command: command_line(command),
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+
+    /// build a [SubmitError] for `script` from an error preventing the submit
+    /// command from running at all
+    pub fn io(script: &str, e: impl Display) -> Self {
+        Self {
+            script: script.to_owned(),
+            stderr: e.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Display for SubmitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to submit {}", self.script)?;
+        if let Some(status) = self.status {
+            write!(f, " with exit status {status}")?;
+        }
+        write!(f, ": {}", self.stderr.trim())
+    }
+}
+
+impl std::error::Error for SubmitError {}
+
+impl From<SubmitError> for Error {
+    fn from(e: SubmitError) -> Self {
+        Self::Submit(e.to_string())
+    }
+}
+
 pub trait Submit<P>: SubQueue<P>
 where
     P: Program + Clone + Serialize + for<'a> Deserialize<'a>,
 {
-    /// submit `filename` to the queue and return the jobid
-    fn submit(&self, filename: &str) -> String {
-        loop {
-            match Command::new(self.submit_command()).arg(filename).output() {
-                Ok(s) => {
-                    if s.status.success() {
-                        let raw = str::from_utf8(&s.stdout)
-                            .unwrap()
-                            .trim()
-                            .to_string();
-                        return raw
-                            .split_whitespace()
-                            .last()
-                            .unwrap_or("")
-                            .to_string();
+    /// submit `filename` to the queue and return the jobid along with
+    /// `filename`, or a [SubmitError] if the queue rejected it even after
+    /// retrying
+    fn submit(&self, filename: &str) -> Result<Submission, SubmitError> {
+        submit_script(self.submit_command(), filename)
+    }
+
+    /// like [Submit::submit] but panic with the [SubmitError] instead of
+    /// returning it, for callers that can't recover from a failed submission
+    fn submit_or_panic(&self, filename: &str) -> Submission {
+        self.submit(filename).unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
+/// return `cmd` and its arguments separated by spaces, for error messages
+fn command_line(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|s| s.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// run `cmd` and collect its output, through the current
+/// [crate::record::Recorder] if there is one
+#[cfg(feature = "record")]
+pub(crate) fn command_output(
+    cmd: &mut Command,
+) -> std::io::Result<std::process::Output> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let args: Vec<_> = cmd
+        .get_args()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    crate::record::intercept(&program, &args, || cmd.output())
+}
+
+/// run `cmd` and collect its output
+#[cfg(not(feature = "record"))]
+pub(crate) fn command_output(
+    cmd: &mut Command,
+) -> std::io::Result<std::process::Output> {
+    cmd.output()
+}
+
+/// run the queue status command `program` with `args` through the current
+/// [crate::fs::FileSystem], and the current [crate::record::Recorder] if
+/// there is one, returning its standard output
+#[cfg(feature = "record")]
+pub(crate) fn status_output(
+    program: &str,
+    args: &[&str],
+) -> std::io::Result<Vec<u8>> {
+    use std::os::unix::process::ExitStatusExt;
+    crate::record::intercept(program, args, || {
+        Ok(std::process::Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout: crate::fs::current().run(program, args)?,
+            stderr: Vec::new(),
+        })
+    })
+    .map(|output| output.stdout)
+}
+
+/// run the queue status command `program` with `args` through the current
+/// [crate::fs::FileSystem], returning its standard output
+#[cfg(not(feature = "record"))]
+pub(crate) fn status_output(
+    program: &str,
+    args: &[&str],
+) -> std::io::Result<Vec<u8>> {
+    crate::fs::current().run(program, args)
+}
+
+/// run `submit_command` on `filename`, retrying until it succeeds, and return
+/// the last word of its output as the job id. this is the default
+/// implementation of [Submit::submit], which only returns an error if
+/// `submit_command` cannot be run at all
+pub(crate) fn submit_script(
+    submit_command: &str,
+    filename: &str,
+) -> Result<Submission, SubmitError> {
+    let mut cmd = Command::new(submit_command);
+    cmd.arg(filename);
+    loop {
+        match command_output(&mut cmd) {
+            Ok(s) => {
+                if s.status.success() {
+                    let raw = String::from_utf8_lossy(&s.stdout);
+                    let job_id =
+                        raw.split_whitespace().last().unwrap_or("").to_string();
+                    return Ok(Submission::new(job_id, filename));
+                }
+                log::warn!(
+                    "failed to submit {filename} with `{}`",
+                    String::from_utf8_lossy(&s.stderr)
+                );
+                std::thread::sleep(Duration::from_secs(1));
+            }
+            Err(e) => {
+                return Err(SubmitError {
+                    command: command_line(&cmd),
+                    ..SubmitError::io(filename, e)
+                })
+            }
+        };
+    }
+}
+
+/// run `cmd` to submit `script`, retrying up to `retries` times with
+/// `sleep_int` seconds between attempts if it fails, and return the last word
+/// of its output as the job id. with `backoff`, the wait doubles after each
+/// attempt. this is shared by the queues whose submit commands need special
+/// arguments, like [pbs::Pbs] and [slurm::Slurm]
+pub(crate) fn submit_inner(
+    cmd: &mut Command,
+    script: &str,
+    mut retries: usize,
+    sleep_int: usize,
+    backoff: bool,
+) -> Result<String, SubmitError> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let mut sleep_int = sleep_int as u64;
+    loop {
+        match command_output(cmd) {
+            Ok(s) => {
+                if !s.status.success() {
+                    if retries > 0 {
+                        log::warn!(
+                            "{program} failed with output: {s:#?}, \
+                             retrying {retries} more times"
+                        );
+                        retries -= 1;
+                        std::thread::sleep(Duration::from_secs(sleep_int));
+                        if backoff {
+                            sleep_int = sleep_int.saturating_mul(2);
+                        }
+                        continue;
                     }
-                    eprintln!(
-                        "failed to submit {filename} with `{}`",
-                        String::from_utf8_lossy(&s.stderr)
-                    );
-                    std::thread::sleep(Duration::from_secs(1));
+                    return Err(SubmitError::from_output(script, cmd, &s));
                 }
-                Err(e) => panic!("{e:?}"),
-            };
+                let raw = String::from_utf8_lossy(&s.stdout);
+                return Ok(raw
+                    .split_whitespace()
+                    .last()
+                    .unwrap_or("no jobid")
+                    .to_string());
+            }
+            Err(e) => {
+                return Err(SubmitError {
+                    command: command_line(cmd),
+                    ..SubmitError::io(script, e)
+                })
+            }
+        }
+    }
+}
+
+/// return a stop condition for [Queue::drain_until] on a scan with its jobs
+/// indexed from 0 in scan order. the condition is satisfied once the energies
+/// of the jobs finished so far, taken in order from job 0 up to the first one
+/// that hasn't finished, rise more than `rise` above their minimum
+pub fn rising_after_minimum(
+    rise: f64,
+) -> impl FnMut(usize, &ProgramResult) -> bool {
+    let mut energies = BTreeMap::new();
+    move |index, res| {
+        energies.insert(index, res.energy);
+        let mut min = f64::INFINITY;
+        for (i, (&index, &energy)) in energies.iter().enumerate() {
+            if index != i {
+                break;
+            }
+            min = min.min(energy);
+            if energy - min > rise {
+                return true;
+            }
         }
+        false
     }
 }
 
@@ -84,15 +717,120 @@ where
 
     fn sleep_int(&self) -> usize;
 
-    /// the command to check the status of jobs in the queue
-    fn stat_cmd(&self) -> String;
+    /// run the command to check the status of jobs in the queue and return
+    /// its output
+    fn stat_cmd(&self) -> Result<String, Error>;
 
     /// return a HashSet of jobs found in the queue based on the output of
     /// `stat_cmd`
-    fn status(&self) -> HashSet<String>;
+    fn status(&self) -> Result<HashSet<String>, Error>;
+
+    /// return the jobs found in the queue along with their states, for queues
+    /// that report them. the default implementation calls `status` and marks
+    /// every job [JobState::Unknown]
+    fn status_detailed(&self) -> Result<HashMap<String, JobState>, Error> {
+        Ok(self
+            .status()?
+            .into_iter()
+            .map(|id| (id, JobState::Unknown))
+            .collect())
+    }
+
+    /// the command for removing a job from the queue by its id, like `qdel`.
+    /// jobs in a [JobState::is_terminal] state are removed with it before
+    /// being resubmitted, so that they can't be released later and run
+    /// alongside their replacements. defaults to `None`, leaving them queued
+    fn delete_command(&self) -> Option<&str> {
+        None
+    }
 
     /// return `true` if all output files should be preserved
     fn no_del(&self) -> bool;
+
+    /// the maximum number of failed or resubmitted jobs to tolerate across a
+    /// whole run before aborting it, or `None` for no limit
+    fn retry_budget(&self) -> Option<usize> {
+        None
+    }
+
+    /// return `true` if the files of successfully-parsed jobs should be
+    /// gzipped instead of deleted or kept uncompressed
+    fn compress_on_success(&self) -> bool {
+        false
+    }
+
+    /// the number of CPUs requested for each job, used for reporting parallel
+    /// efficiency
+    fn ncpus(&self) -> usize {
+        1
+    }
+
+    /// the maximum number of submissions to run at once. this limits the rate
+    /// of submissions rather than the number of running jobs, which is
+    /// governed by `job_limit`
+    fn submit_limit(&self) -> usize {
+        4
+    }
+
+    /// the seed for shuffling the jobs before submission, or `None` to submit
+    /// them in input order
+    fn shuffle_seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// the number of seconds to wait for a submitted job to appear in
+    /// `status` before treating it as never-started, or `None` to treat
+    /// missing jobs as finished immediately
+    fn start_grace(&self) -> Option<usize> {
+        None
+    }
+
+    /// the bounds for adaptively resizing chunks, or `None` to always use
+    /// `chunk_size`
+    fn adaptive_chunks(&self) -> Option<AdaptiveChunks> {
+        None
+    }
+
+    /// the settings for reporting chunks with many failed jobs, or `None` to
+    /// disable the check
+    fn chunk_failure(&self) -> Option<ChunkFailure> {
+        None
+    }
+
+    /// the settings for slowing down when the scheduler is slow to respond, or
+    /// `None` to always poll at `sleep_int`
+    fn throttle(&self) -> Option<Throttle> {
+        None
+    }
+
+    /// the number of seconds of clock skew to allow when checking for output
+    /// files left over from before a job was submitted, or `None` to disable
+    /// the check. See [crate::program::Job::is_stale]
+    fn stale_tolerance(&self) -> Option<usize> {
+        None
+    }
+
+    /// the maximum number of finished files waiting to be deleted or
+    /// compressed before the polling loop blocks for the deletion thread to
+    /// catch up, or `None` for no limit
+    fn dump_capacity(&self) -> Option<usize> {
+        None
+    }
+
+    /// an existing directory to move the files of successfully-parsed jobs
+    /// into instead of deleting them, or `None` to delete them. ignored if
+    /// `no_del` or `compress_on_success` is set
+    fn trash_dir(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// submit each batch of chunks as a single array job, written by
+    /// [Queue::write_array_script], instead of one job per chunk. the jobs in
+    /// a failed element are only resubmitted once the whole array leaves the
+    /// queue
+    fn array_jobs(&self) -> bool {
+        false
+    }
 }
 
 pub trait Queue<P>: SubQueue<P> + Submit<P>
@@ -106,51 +844,79 @@ where
 {
     fn default_submit_script(&self) -> String;
 
-    fn write_submit_script(&self, infiles: &[String], filename: &str);
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), Error>;
+
+    /// write the submit script `filename` for an array job running each of
+    /// the chunk submit `scripts` as one of its elements, for queues
+    /// supporting [SubQueue::array_jobs]
+    fn write_array_script(
+        &self,
+        _scripts: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
+        Err(Error::Submit(format!(
+            "array jobs are not supported for {filename}"
+        )))
+    }
 
     /// take a name of a Program input file with the extension attached, replace
     /// the extension (ext) with _redo.ext and write _redo.SCRIPT_EXT, then
     /// submit the redo script
-    fn resubmit(&self, filename: &str) -> Resubmit {
+    fn resubmit(&self, filename: &str) -> Result<Resubmit, Error> {
         let path = Path::new(filename);
         let dir = path.parent().unwrap().to_str().unwrap();
         let base = path.file_stem().unwrap().to_str().unwrap();
         {
             let ext = path.extension().unwrap().to_str().unwrap();
             let inp_file = format!("{dir}/{base}_redo.{ext}");
-            match std::fs::copy(filename, &inp_file) {
-                Ok(_) => (),
-                Err(e) => {
-                    panic!("failed to copy {filename} to {inp_file} with `{e}`")
-                }
-            };
+            std::fs::copy(filename, &inp_file)
+                .map_err(|e| Error::io(filename, e))?;
         }
         // nothing but the copy needs the name with extension
         let inp_name = format!("{dir}/{base}_redo");
         let pbs_file = format!("{}/{}_redo.{}", dir, base, Self::SCRIPT_EXT);
-        self.write_submit_script(&[inp_name.clone()], &pbs_file);
-        let job_id = self.submit(&pbs_file);
-        Resubmit {
+        self.write_submit_script(&[inp_name.clone()], &pbs_file)?;
+        let job_id = {
+            let _permit = SUBMIT_LIMITER.acquire(self.submit_limit());
+            self.submit(&pbs_file)?.job_id
+        };
+        Ok(Resubmit {
             inp_file: inp_name,
             pbs_file,
             job_id,
-        }
+        })
     }
 
     /// Build a chunk of jobs by writing the Program input file and the
     /// corresponding submission script and then submitting the script. returns
-    /// the total durations spent writing input files, writing the submit
-    /// script, and submitting the script
+    /// the [Submission] of the script, the number of jobs in it, and the total
+    /// durations spent writing input files, writing the submit script, and
+    /// submitting the script
+    #[allow(clippy::type_complexity)]
     fn build_chunk(
         &self,
         dir: &str,
         jobs: &mut [Job<P>],
         chunk_num: usize,
         proc: Procedure,
-    ) -> (HashMap<String, usize>, Duration, Duration, Duration) {
+    ) -> Result<
+        (
+            Submission,
+            HashMap<String, usize>,
+            Duration,
+            Duration,
+            Duration,
+        ),
+        Error,
+    > {
         self.build_chunk_inner(dir, "main", chunk_num, jobs, proc)
     }
 
+    #[allow(clippy::type_complexity)]
     fn build_chunk_inner(
         &self,
         dir: &str,
@@ -158,69 +924,164 @@ where
         chunk_num: usize,
         jobs: &mut [Job<P>],
         proc: Procedure,
-    ) -> (HashMap<String, usize>, Duration, Duration, Duration) {
+    ) -> Result<
+        (
+            Submission,
+            HashMap<String, usize>,
+            Duration,
+            Duration,
+            Duration,
+        ),
+        Error,
+    > {
+        let mut submit = Duration::default();
+        let (queue_file, input, script) =
+            self.write_chunk(dir, base, chunk_num, jobs, proc)?;
+        let mut slurm_jobs = HashMap::new();
+        slurm_jobs.insert(queue_file.clone(), jobs.len());
+        // run jobs
+        let sub;
+        let permit = SUBMIT_LIMITER.acquire(self.submit_limit());
+        time!(e, {
+            sub = self.submit(&queue_file)?;
+        });
+        drop(permit);
+        submit += e;
+        let submitted = SystemTime::now();
+        for job in jobs {
+            job.job_id = sub.job_id.clone();
+            job.submitted = Some(submitted);
+        }
+        Ok((sub, slurm_jobs, input, script, submit))
+    }
+
+    /// write the input files for `jobs` and the submit script running them,
+    /// named `{dir}/{base}{chunk_num}.{SCRIPT_EXT}`, without submitting it.
+    /// returns the name of the submit script and the durations spent writing
+    /// the input files and the script
+    fn write_chunk(
+        &self,
+        dir: &str,
+        base: &str,
+        chunk_num: usize,
+        jobs: &mut [Job<P>],
+        proc: Procedure,
+    ) -> Result<(String, Duration, Duration), Error> {
         let mut input = Duration::default();
         let mut script = Duration::default();
-        let mut submit = Duration::default();
         let queue_file =
             format!("{}/{base}{}.{}", dir, chunk_num, Self::SCRIPT_EXT);
-        let jl = jobs.len();
         let mut filenames = Vec::with_capacity(jobs.len());
-        let mut slurm_jobs = HashMap::new();
+        time!(e, {
+            P::write_inputs(jobs.iter_mut().map(|job| &mut job.program), proc)?;
+        });
+        input += e;
         jobs.iter_mut()
             .map(|job| {
-                time!(e, {
-                    job.program.write_input(proc);
-                });
-                input += e;
                 job.pbs_file = queue_file.to_string();
                 job.program.filename()
             })
             .collect_into(&mut filenames);
-        slurm_jobs.insert(queue_file.clone(), jl);
         time!(e, {
-            self.write_submit_script(&filenames, &queue_file);
+            self.write_submit_script(&filenames, &queue_file)?;
         });
         script += e;
-        // run jobs
-        let job_id;
-        time!(e, {
-            job_id = self.submit(&queue_file);
-        });
-        submit += e;
-        for job in jobs {
-            job.job_id = job_id.clone();
+        Ok((queue_file, input, script))
+    }
+
+    /// write the input files and submit scripts for `jobs` to `dir` in chunks
+    /// of `self.chunk_size()`, without submitting them, for copying to and
+    /// submitting from another machine. the scheduler is selected by the
+    /// choice of `self`. alongside the scripts, this writes a `manifest.txt`
+    /// with a line for each script followed by the input files it runs, and a
+    /// `submit_all.sh` submitting each script with `self.submit_command()`.
+    /// the paths in these are the same as those in the scripts, so
+    /// `submit_all.sh` should be run from the directory the bundle was
+    /// generated in. returns the names of the submit scripts
+    fn export_bundle(
+        &self,
+        dir: &str,
+        jobs: &mut [Job<P>],
+        proc: Procedure,
+    ) -> Result<Vec<String>, Error> {
+        use std::fmt::Write;
+        let mut manifest = String::new();
+        let mut submit_all = String::from("#!/bin/sh\nset -e\n");
+        let mut scripts = Vec::new();
+        for (i, chunk) in jobs.chunks_mut(self.chunk_size()).enumerate() {
+            let (script, _, _) =
+                self.write_chunk(dir, "main", i, chunk, proc)?;
+            write!(manifest, "{script}").unwrap();
+            for f in chunk.iter().flat_map(|job| job.program.input_files()) {
+                write!(manifest, " {f}").unwrap();
+            }
+            manifest.push('\n');
+            writeln!(submit_all, "{} {script}", self.submit_command()).unwrap();
+            scripts.push(script);
         }
-        (slurm_jobs, input, script, submit)
+        for (name, body) in
+            [("manifest.txt", manifest), ("submit_all.sh", submit_all)]
+        {
+            let filename = format!("{dir}/{name}");
+            std::fs::write(&filename, body)
+                .map_err(|e| Error::io(&filename, e))?;
+        }
+        Ok(scripts)
+    }
+
+    /// like [Queue::export_bundle], but first check the program binary at
+    /// `binary` with [Program::check_binary] and record the version it
+    /// reports in a `# {binary} {version}` line at the top of `manifest.txt`,
+    /// for provenance
+    fn export_checked_bundle(
+        &self,
+        dir: &str,
+        jobs: &mut [Job<P>],
+        proc: Procedure,
+        binary: &str,
+    ) -> Result<Vec<String>, Error> {
+        let version = P::check_binary(binary)?;
+        let scripts = self.export_bundle(dir, jobs, proc)?;
+        let filename = format!("{dir}/manifest.txt");
+        let manifest = std::fs::read_to_string(&filename)
+            .map_err(|e| Error::io(&filename, e))?;
+        std::fs::write(&filename, format!("# {binary} {version}\n{manifest}"))
+            .map_err(|e| Error::io(&filename, e))?;
+        Ok(scripts)
     }
 
     fn drain_err_case(
         &self,
         e: ProgramError,
-        qstat: &mut HashSet<String>,
+        qstat: &mut HashMap<String, String>,
         slurm_jobs: &mut HashMap<String, usize>,
         job: &mut Job<P>,
-    ) {
+    ) -> Result<(), Error> {
         let no_resub = LazyCell::new(|| std::env::var("SEMP_RESUB").is_ok());
         // just overwrite the existing job with the resubmitted
         // version
-        if !qstat.contains(&job.job_id) {
+        if !qstat.contains_key(&job.job_id) {
             let time = job.modtime();
             if time > job.modtime {
                 // file has been updated since we last looked at it, so need to
                 // look again
                 job.modtime = time;
-                return;
+                return Ok(());
             }
-            eprintln!(
+            log::info!(
                 "resubmitting {} (id={}) for {:?}",
                 job.program.filename(),
                 job.job_id,
                 e
             );
             if *no_resub {
-                eprintln!("resubmission disabled by SEMP_RESUB environment variable, exiting");
-                std::process::exit(1);
+                log::error!(
+                    "resubmission disabled by SEMP_RESUB environment \
+                     variable, stopping"
+                );
+                return Err(Error::Program(ProgramError::ResubmitDisabled(
+                    job.program.filename(),
+                )));
             }
             let resub = format!(
                 "{}.{}",
@@ -231,13 +1092,18 @@ where
                 inp_file,
                 pbs_file,
                 job_id,
-            } = self.resubmit(&resub);
+            } = self.resubmit(&resub)?;
             job.program.set_filename(&inp_file);
             job.pbs_file = pbs_file.clone();
             slurm_jobs.insert(pbs_file, 1);
-            qstat.insert(job_id.clone());
+            insert_job_id(
+                qstat,
+                Submission::new(job_id.clone(), &job.pbs_file),
+            );
             job.job_id = job_id;
+            job.submitted = Some(SystemTime::now());
         }
+        Ok(())
     }
 
     /// optimize is a copy of drain for optimizing jobs
@@ -250,7 +1116,15 @@ where
     where
         Self: std::marker::Sync,
     {
-        Opt.drain(dir, self, jobs, dst, Check::None)
+        Opt.drain(
+            dir,
+            self,
+            jobs,
+            dst,
+            Check::None,
+            &mut |_, _| false,
+            &mut |_| (),
+        )
     }
 
     /// resume draining from the checkpoint file in `checkpoint`
@@ -264,8 +1138,8 @@ where
     where
         Self: Sync,
     {
-        let jobs = Single::load_checkpoint(checkpoint, dst);
-        eprintln!(
+        let jobs = Single::load_checkpoint(checkpoint, dst)?;
+        log::info!(
             "resuming from checkpoint in '{checkpoint}' with {} jobs remaining",
             jobs.len()
         );
@@ -284,7 +1158,82 @@ where
     where
         Self: std::marker::Sync,
     {
-        Single.drain(dir, self, jobs, dst, check)
+        Single.drain(
+            dir,
+            self,
+            jobs,
+            dst,
+            check,
+            &mut |_, _| false,
+            &mut |_| (),
+        )
+    }
+
+    /// like [Queue::drain], but stop submitting new chunks of jobs once `stop`
+    /// returns `true`, such as to skip the tail of a scan after its minimum
+    /// has been passed. `stop` is called with the index and result of each job
+    /// in the order they finish, which is not necessarily the order of `jobs`.
+    /// the jobs already submitted when `stop` is satisfied are still
+    /// collected, but the entries in `dst` for the jobs never submitted are
+    /// left unchanged
+    fn drain_until(
+        &self,
+        dir: &str,
+        jobs: Vec<Job<P>>,
+        dst: &mut [f64],
+        check: Check,
+        mut stop: impl FnMut(usize, &ProgramResult) -> bool,
+    ) -> Result<f64, ProgramError>
+    where
+        Self: std::marker::Sync,
+    {
+        Single.drain(dir, self, jobs, dst, check, &mut stop, &mut |_| ())
+    }
+
+    /// like [Queue::drain], but call `progress` with the number of jobs
+    /// finished and remaining, and the estimated time left, after each
+    /// iteration in which some jobs finished, such as for updating a progress
+    /// bar or deciding whether to wait for a run
+    fn drain_with_progress(
+        &self,
+        dir: &str,
+        jobs: Vec<Job<P>>,
+        dst: &mut [f64],
+        check: Check,
+        mut progress: impl FnMut(&Progress),
+    ) -> Result<f64, ProgramError>
+    where
+        Self: std::marker::Sync,
+    {
+        Single.drain(
+            dir,
+            self,
+            jobs,
+            dst,
+            check,
+            &mut |_, _| false,
+            &mut progress,
+        )
+    }
+
+    /// run only the single-point energy calculations in `jobs[range]`, such as
+    /// to split a large run across several machines or to debug one part of
+    /// it. the jobs keep their filenames and indices into `dst`, so the
+    /// results match those from draining all of `jobs` and can be combined
+    /// afterward. panics if `range` is out of bounds for `jobs`
+    fn drain_range(
+        &self,
+        dir: &str,
+        mut jobs: Vec<Job<P>>,
+        range: Range<usize>,
+        dst: &mut [f64],
+        check: Check,
+    ) -> Result<f64, ProgramError>
+    where
+        Self: std::marker::Sync,
+    {
+        let jobs = jobs.drain(range).collect();
+        self.drain(dir, jobs, dst, check)
     }
 
     fn energize(
@@ -296,6 +1245,45 @@ where
     where
         Self: std::marker::Sync,
     {
-        Both.drain(dir, self, jobs, dst, Check::None)
+        Both.drain(
+            dir,
+            self,
+            jobs,
+            dst,
+            Check::None,
+            &mut |_, _| false,
+            &mut |_| (),
+        )
+    }
+
+    /// run a single-point energy on a tiny H2 molecule from `template` through
+    /// the whole submit, status, read, and cleanup cycle in `self.dir()`, and
+    /// compare the energy to `want` within `tol`. This is meant for validating
+    /// a new queue setup before starting a real run, so the submit script
+    /// should allow a generous walltime
+    fn self_test(
+        &self,
+        template: &Template,
+        want: f64,
+        tol: f64,
+    ) -> Result<(), String>
+    where
+        Self: std::marker::Sync,
+    {
+        let dir = self.dir().to_owned();
+        let prog =
+            P::new(format!("{dir}/self_test"), template.clone(), 0, h2());
+        let mut dst = [0.0];
+        self.drain(&dir, vec![Job::new(prog, 0)], &mut dst, Check::None)
+            .map_err(|e| format!("self test failed with `{e}`"))?;
+        let got = dst[0];
+        if (got - want).abs() > tol {
+            return Err(format!(
+                "self test failed: energy {got} differs from {want} by more \
+                 than {tol}"
+            ));
+        }
+        log::info!("self test passed with energy {got}");
+        Ok(())
     }
 }
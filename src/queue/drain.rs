@@ -2,20 +2,22 @@ use core::time;
 use std::{
     borrow::BorrowMut,
     collections::{HashMap, HashSet},
-    iter::{Enumerate, Fuse, Peekable},
     marker::{Send, Sync},
-    slice::ChunksMut,
     sync::LazyLock,
     thread,
 };
 
 use crate::{
     geom::Geom,
-    program::{Job, Procedure, Program, ProgramError, ProgramResult},
+    program::{
+        Job, ParseSelectors, Procedure, Program, ProgramError, ProgramResult,
+    },
     queue::drain::{dump::Dump, resub::ResubOutput},
+    util::shuffle,
+    Error,
 };
 
-use super::{Queue, DEBUG};
+use super::{insert_job_id, JobState, Queue, Submission};
 
 /// time the duration of `$body` and store the resulting Duration in `$elapsed`
 #[macro_export]
@@ -27,11 +29,24 @@ macro_rules! time {
     };
 }
 
+mod chunks;
 mod dump;
+mod eta;
+mod failures;
+mod latency;
+mod pending;
 mod resub;
 mod timer;
 
+#[cfg(test)]
+mod tests;
+
+pub use eta::Eta;
+
+use chunks::{ChunkSizer, Chunks};
+use failures::ChunkFailures;
 use libc::{timeval, RUSAGE_SELF};
+use pending::Pending;
 use resub::Resub;
 use serde::{Deserialize, Serialize};
 
@@ -43,11 +58,44 @@ pub enum Check {
     None,
 }
 
+/// A snapshot of a running drain, passed to the callback of
+/// [super::Queue::drain_with_progress] after each iteration in which some jobs
+/// finished
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// the number of jobs finished so far, including failed ones
+    pub finished: usize,
+
+    /// the number of jobs left to finish
+    pub remaining: usize,
+
+    /// the estimated time until the remaining jobs finish, as returned by
+    /// [Eta::eta]
+    pub eta: Option<time::Duration>,
+
+    /// the fraction of failed jobs in each chunk, identified by its submit
+    /// script. empty unless [super::SubQueue::chunk_failure] is set
+    pub failure_rates: Vec<(String, f64)>,
+
+    /// the number of output files deleted, compressed, or moved to the trash
+    /// so far
+    pub cleaned: usize,
+
+    /// the number of output files that could not be cleaned up
+    pub clean_failed: usize,
+}
+
 pub(crate) trait Drain {
     type Item;
 
     fn procedure(&self) -> Procedure;
 
+    /// the quantities to read from the output of jobs without their own
+    /// [Job::parse]. defaults to only the energy
+    fn parse(&self) -> ParseSelectors {
+        ParseSelectors::default()
+    }
+
     fn set_result<P: Program>(
         &self,
         dst: &mut [Self::Item],
@@ -55,7 +103,13 @@ pub(crate) trait Drain {
         res: ProgramResult,
     );
 
-    /// on success, return the total job time, as returned by `P::read_output`
+    /// on success, return the total job time, as returned by `P::read_output`.
+    /// `stop` is called with the index and result of each job finishing while
+    /// there are still jobs left to submit, and no more chunks are submitted
+    /// once it returns `true`, although the jobs already submitted are still
+    /// collected. `progress` is called with the state of the run after each
+    /// iteration in which some jobs finished
+    #[allow(clippy::too_many_arguments)]
     fn drain<P, Q>(
         &self,
         dir: &str,
@@ -63,6 +117,8 @@ pub(crate) trait Drain {
         mut jobs: Vec<Job<P>>,
         dst: &mut [Self::Item],
         check: Check,
+        stop: &mut dyn FnMut(usize, &ProgramResult) -> bool,
+        progress: &mut dyn FnMut(&Progress),
     ) -> Result<f64, ProgramError>
     where
         Self: Sync,
@@ -72,19 +128,50 @@ pub(crate) trait Drain {
     {
         // total time for the jobs to run as returned from Program::read_output
         let mut job_time = 0.0;
+        // total CPU and wall time for the jobs reporting both, for computing
+        // the aggregate parallel efficiency
+        let mut cpu_time = 0.0;
+        let mut wall_time = 0.0;
 
         let mut cur_jobs = Vec::new();
         let mut slurm_jobs = HashMap::new();
+        // the array script and number of unfinished jobs for each array job
+        let mut arrays = HashMap::new();
         let mut remaining = jobs.len();
 
         let job_limit = queue.job_limit();
 
         let mut out_of_jobs = false;
 
-        let dump = Dump::new(queue.no_del());
+        let dump = match queue.trash_dir() {
+            Some(dir) if !queue.no_del() && !queue.compress_on_success() => {
+                Dump::new_with_trash(dir, queue.dump_capacity())
+            }
+            _ => Dump::with_capacity(
+                queue.no_del(),
+                queue.compress_on_success(),
+                queue.dump_capacity(),
+            ),
+        };
         let mut time = timer::Timer::default();
+        let mut eta = Eta::new(10);
+        let mut failures = queue.chunk_failure().map(ChunkFailures::new);
+        let stale_tolerance = queue
+            .stale_tolerance()
+            .map(|t| time::Duration::from_secs(t as u64));
+        let mut pending = Pending::new(
+            queue
+                .start_grace()
+                .map(|g| time::Duration::from_secs(g as u64)),
+        );
 
-        let mut qstat = HashSet::<String>::new();
+        // the outstanding job ids and the submit scripts that produced them
+        let mut qstat = HashMap::<String, String>::new();
+        // results are stored by job index, so shuffling only changes the
+        // submission order
+        if let Some(seed) = queue.shuffle_seed() {
+            shuffle(&mut jobs, seed);
+        }
         // this is a bit sad, but I need the original jobs for checkpoints and I
         // can't get an immutable reference to them while chunks is holding a
         // mutable reference. also can't use a Cow because the chunks_mut call
@@ -105,21 +192,28 @@ pub(crate) trait Drain {
         let mut cleanup_intervals =
             (0..total_jobs).step_by(job_limit).peekable();
 
-        let mut chunks = jobs
-            .chunks_mut(queue.chunk_size())
-            .enumerate()
-            .fuse()
-            .peekable();
+        let mut sizer = queue
+            .adaptive_chunks()
+            .map(|config| ChunkSizer::new(config, queue.chunk_size()));
+        let mut chunks = Chunks::new(
+            &mut jobs,
+            sizer.as_ref().map_or(queue.chunk_size(), ChunkSizer::size),
+        );
         // the index of the last chunk consumed. used for writing remaining jobs
         // to checkpoints. None initially and then Some(chunk_num)
         let mut last_chunk = None;
         let mut to_remove = Vec::new();
         let mut resub = Resub::new(queue, dir, self.procedure());
         let mut failed_jobs = 0;
+        // the names of the jobs that have failed or been resubmitted, checked
+        // against the queue's retry budget. a set because failed jobs are
+        // re-read on every iteration
+        let retry_budget = queue.retry_budget();
+        let mut budget_jobs = HashSet::new();
         let mut iter = 0;
         loop {
             let loop_time = std::time::Instant::now();
-            if chunks.peek().is_none() {
+            if chunks.is_empty() {
                 out_of_jobs = true;
             }
             if !out_of_jobs {
@@ -130,28 +224,65 @@ pub(crate) trait Drain {
                     queue,
                     dir,
                     &mut slurm_jobs,
+                    &mut arrays,
                     &mut time,
                     &mut qstat,
+                    &mut pending,
+                    &mut failures,
                     &mut last_chunk,
-                );
+                )?;
             }
 
             // collect output
             let mut finished = 0;
             to_remove.clear();
             let now = std::time::Instant::now();
-            let outfiles: Vec<_> =
-                cur_jobs.iter().map(|job| job.program.filename()).collect();
+            // outputs older than their submission are left over from a
+            // previous run with the same filename, so don't read them
+            let outfiles: Vec<_> = cur_jobs
+                .iter()
+                .map(|job| {
+                    let stale =
+                        stale_tolerance.is_some_and(|tol| job.is_stale(tol));
+                    let select = job.parse.unwrap_or_else(|| self.parse());
+                    (job.program.filename(), stale, select)
+                })
+                .collect();
             use rayon::prelude::*;
-            let results: Vec<_> =
-                outfiles.par_iter().map(|out| P::read_output(out)).collect();
+            let results: Vec<_> = outfiles
+                .par_iter()
+                .map(|(out, stale, select)| {
+                    if *stale {
+                        Err(ProgramError::StaleOutput(out.clone()))
+                    } else {
+                        P::read_output_with(out, *select)
+                    }
+                })
+                .collect();
             time.reading += now.elapsed();
             for (i, (job, res)) in cur_jobs.iter_mut().zip(results).enumerate()
             {
                 match res {
                     Ok(res) => {
                         to_remove.push(i);
+                        if !out_of_jobs && stop(job.index, &res) {
+                            log::info!(
+                                "stop condition met by job {}, not \
+                                 submitting the remaining jobs",
+                                job.index
+                            );
+                            out_of_jobs = true;
+                        }
                         job_time += res.time;
+                        if let Some(sizer) = &mut sizer {
+                            sizer.observe(res.time);
+                        }
+                        if let (Some(cpu), Some(wall)) =
+                            (res.cpu_time, res.wall_time)
+                        {
+                            cpu_time += cpu;
+                            wall_time += wall;
+                        }
                         self.set_result(dst, job, res);
                         for f in job.program.associated_files() {
                             dump.send(f);
@@ -162,7 +293,7 @@ pub(crate) trait Drain {
                         let mut count = match slurm_jobs.get_mut(job_name) {
                             Some(n) => *n,
                             None => {
-                                eprintln!(
+                                log::warn!(
                                     "failed to find {job_name} in slurm_jobs"
                                 );
                                 1
@@ -174,12 +305,43 @@ pub(crate) trait Drain {
                             dump.send(job_name.to_string());
                             dump.send(format!("{job_name}.out"));
                         }
+                        // and those of the array job running it, once all of
+                        // its jobs have finished
+                        if let Some((file, n)) = arrays.get_mut(&job.job_id) {
+                            *n -= 1;
+                            if *n == 0 {
+                                dump.send(file.clone());
+                                dump.send(format!("{file}.out"));
+                                arrays.remove(&job.job_id);
+                            }
+                        }
                     }
                     Err(e) => {
-                        if e.is_error_in_output() {
-                            eprintln!("warning: job failed with `{e}`");
+                        if e.is_failure() {
+                            log::warn!(
+                                "job {} failed with `{e}`",
+                                job.program.summary()
+                            );
                             failed_jobs += 1;
-                        } else if !qstat.contains(&job.job_id) {
+                            budget_jobs.insert(job.program.filename());
+                            let report = failures.as_mut().and_then(|f| {
+                                f.fail(
+                                    &job.pbs_file,
+                                    &job.program.filename(),
+                                    &e,
+                                )
+                            });
+                            if let Some(report) = report {
+                                log::warn!("{report}");
+                                if failures.as_ref().is_some_and(|f| f.pause())
+                                {
+                                    dump.shutdown();
+                                    return Err(ProgramError::ChunkFailure(
+                                        report,
+                                    ));
+                                }
+                            }
+                        } else if !qstat.contains_key(&job.job_id) {
                             // just overwrite the existing job with
                             // the resubmitted version
                             let time = job.modtime();
@@ -189,29 +351,51 @@ pub(crate) trait Drain {
                                 job.modtime = time;
                             } else {
                                 // actual resubmission path
-                                eprintln!(
-                                    "resubmitting {} (id={}) for {:?}",
+                                log::info!(
+                                    "resubmitting {} [{}] (id={}) for {:?}",
                                     job.program.filename(),
+                                    job.program.summary(),
                                     job.job_id,
                                     e
                                 );
                                 if *NO_RESUB {
-                                    eprintln!(
-                                        "resubmission disabled by \
-					 NO_RESUB environment \
-					 variable, exiting"
+                                    log::error!(
+                                        "resubmission disabled by NO_RESUB \
+                                         environment variable, stopping"
+                                    );
+                                    dump.shutdown();
+                                    return Err(
+                                        ProgramError::ResubmitDisabled(
+                                            job.program.filename(),
+                                        ),
                                     );
-                                    std::process::exit(1);
                                 }
                                 // copy the job into resub and plan to remove it
                                 // from cur_jobs
                                 resub.push(job.clone());
                                 to_remove.push(i);
+                                budget_jobs.insert(job.program.filename());
+                                if let Some(sizer) = &mut sizer {
+                                    sizer.timed_out();
+                                }
                             }
                         };
                     }
                 }
             }
+            if let Some(budget) = retry_budget {
+                if budget_jobs.len() > budget {
+                    log::error!(
+                        "retry budget of {budget} exhausted by {} jobs:",
+                        budget_jobs.len()
+                    );
+                    for job in &budget_jobs {
+                        log::error!("\t{job}");
+                    }
+                    dump.shutdown();
+                    return Err(ProgramError::RetryBudgetExhausted(budget));
+                }
+            }
             // have to remove the highest index first so sort and reverse
             let r = std::time::Instant::now();
             to_remove.sort();
@@ -221,11 +405,11 @@ pub(crate) trait Drain {
             }
             time.removing += r.elapsed();
             // submit resubs
-            let works = resub.resubmit();
+            let works = resub.resubmit()?;
             for ResubOutput {
                 jobs,
                 slurm_jobs: sj,
-                job_id,
+                submission,
                 writing_input: wi,
                 writing_script: ws,
                 submitting: ss,
@@ -235,15 +419,36 @@ pub(crate) trait Drain {
                 time.writing_input += wi;
                 time.writing_script += ws;
                 time.submitting_script += ss;
-                qstat.insert(job_id);
+                time.latency.record(ss);
+                pending.submit(&submission);
+                if let Some(f) = &mut failures {
+                    f.register(&jobs[0].pbs_file, jobs.len());
+                }
+                insert_job_id(&mut qstat, submission);
                 cur_jobs.extend(jobs);
             }
-            if DEBUG {
-                eprintln!(
-                    "finished {} jobs in {:.1} s",
-                    finished,
-                    loop_time.elapsed().as_millis() as f64 / 1000.0
-                );
+            log::debug!(
+                "finished {} jobs in {:.1} s",
+                finished,
+                loop_time.elapsed().as_millis() as f64 / 1000.0
+            );
+            if finished > 0 {
+                eta.record(total_jobs - remaining, remaining);
+                let (cleaned, clean_failed) = dump.stats();
+                progress(&Progress {
+                    finished: total_jobs - remaining,
+                    remaining,
+                    eta: eta.eta(),
+                    failure_rates: failures
+                        .as_ref()
+                        .map(|f| f.rates())
+                        .unwrap_or_default(),
+                    cleaned,
+                    clean_failed,
+                });
+            }
+            if let Some(sizer) = &sizer {
+                chunks.size = sizer.size().min(job_limit);
             }
             if cur_jobs.is_empty() && out_of_jobs {
                 dump.shutdown();
@@ -251,27 +456,50 @@ pub(crate) trait Drain {
                     if let Check::Some { check_dir, .. } = &check {
                         Self::do_checkpoint(
                             &cur_jobs,
-                            last_chunk,
+                            chunks.end(last_chunk),
                             &jobs_init,
-                            queue.chunk_size(),
                             check_dir,
                             dst,
-                        );
+                        )?;
                     }
                     return Err(ProgramError::ErrorInOutput(format!(
                         "{failed_jobs} jobs failed"
                     )));
                 }
-                eprintln!("{time}");
+                log::info!("{time}");
+                if wall_time > 0.0 {
+                    log::info!(
+                        "{:.1}% parallel efficiency on {} CPUs",
+                        100.0 * cpu_time
+                            / (wall_time * queue.ncpus().max(1) as f64),
+                        queue.ncpus()
+                    );
+                }
                 return Ok(job_time);
             }
             if finished == 0 {
-                wait(queue, &mut time, iter, remaining);
-                qstat = queue.status();
+                wait(queue, &mut time, iter, remaining, eta.eta());
+                time!(e, {
+                    match queue.status_detailed() {
+                        Ok(status) => {
+                            qstat = active_jobs(
+                                status,
+                                &qstat,
+                                queue.delete_command(),
+                            )
+                        }
+                        // keep the last status and try again next time
+                        Err(err) => {
+                            log::warn!("failed to get queue status with {err}")
+                        }
+                    }
+                });
+                time.latency.record(e);
+                pending.update(&mut qstat);
             } else if total_jobs - remaining
                 > *cleanup_intervals.peek().unwrap_or(&total_jobs)
             {
-                wait(queue, &mut time, iter, remaining);
+                wait(queue, &mut time, iter, remaining, eta.eta());
                 cleanup_intervals.next();
             }
             if let Check::Some {
@@ -282,12 +510,11 @@ pub(crate) trait Drain {
                 if *check_int > 0 && iter % check_int == 0 {
                     Self::do_checkpoint(
                         &cur_jobs,
-                        last_chunk,
+                        chunks.end(last_chunk),
                         &jobs_init,
-                        queue.chunk_size(),
                         check_dir,
                         dst,
-                    );
+                    )?;
                 }
             }
             iter += 1;
@@ -299,108 +526,129 @@ pub(crate) trait Drain {
     fn load_checkpoint<P>(
         checkpoint: &str,
         dst: &mut [Self::Item],
-    ) -> Vec<Job<P>>
+    ) -> Result<Vec<Job<P>>, Error>
     where
         P: Program + Clone + Send + Sync + Serialize + for<'a> Deserialize<'a>,
         Self::Item: Clone + for<'a> Deserialize<'a>,
     {
-        let Ok(f) = std::fs::File::open(checkpoint) else {
-            panic!("failed to open {checkpoint}");
-        };
-        let Checkpoint { dst: d, jobs } = serde_json::from_reader(f).unwrap();
+        let f = std::fs::File::open(checkpoint)
+            .map_err(|e| Error::io(checkpoint, e))?;
+        let Checkpoint { dst: d, jobs } = serde_json::from_reader(f)
+            .map_err(|e| Error::Parse(format!("{checkpoint}: {e}")))?;
         dst.clone_from_slice(&d);
-        jobs
+        Ok(jobs)
     }
 
     fn write_checkpoint<P>(
         checkpoint: &str,
         dst: Vec<Self::Item>,
         jobs: Vec<Job<P>>,
-    ) where
+    ) -> Result<(), Error>
+    where
         P: Program + Clone + Send + Sync + Serialize + for<'a> Deserialize<'a>,
         Self::Item: Serialize,
     {
         let c = Checkpoint { dst, jobs };
-        eprintln!("writing checkpoint to {checkpoint}");
-        let f = std::fs::File::create(checkpoint).unwrap();
-        serde_json::to_writer_pretty(f, &c).unwrap();
+        log::info!("writing checkpoint to {checkpoint}");
+        let f = std::fs::File::create(checkpoint)
+            .map_err(|e| Error::io(checkpoint, e))?;
+        serde_json::to_writer_pretty(f, &c)
+            .map_err(|e| Error::Parse(format!("{checkpoint}: {e}")))
     }
 
     fn do_checkpoint<P>(
         cur_jobs: &[Job<P>],
-        last_chunk: Option<usize>,
+        next_job: usize,
         jobs_init: &Vec<Job<P>>,
-        chunk_size: usize,
         check_dir: &str,
         dst: &mut [<Self as Drain>::Item],
-    ) where
+    ) -> Result<(), Error>
+    where
         P: Program + Clone + Send + Sync + Serialize + for<'a> Deserialize<'a>,
         Job<P>: Clone,
         Self::Item: Serialize + Clone,
     {
         let mut cur_jobs = cur_jobs.to_vec();
-        // +1 because after the first chunk (chunk_num = 0) is written,
-        // we want to slice from the next chunk on
-        let cn = match last_chunk {
-            Some(n) => n + 1,
-            None => 0,
-        };
-        cur_jobs.extend(
-            jobs_init[(cn * chunk_size).min(jobs_init.len())..].to_vec(),
-        );
+        // next_job is the offset of the first job after the last chunk
+        // consumed, so slice from there on
+        cur_jobs.extend(jobs_init[next_job.min(jobs_init.len())..].to_vec());
         Self::write_checkpoint(
             &format!("{check_dir}/chk.json"),
             dst.to_vec(),
             cur_jobs,
-        );
+        )
     }
 
     #[allow(clippy::too_many_arguments)]
     fn receive_jobs<P, Q>(
         &self,
-        chunks: &mut Peekable<Fuse<Enumerate<ChunksMut<Job<P>>>>>,
+        chunks: &mut Chunks<P>,
         job_limit: usize,
         cur_jobs: &mut Vec<Job<P>>,
         queue: &Q,
         dir: &str,
         slurm_jobs: &mut HashMap<String, usize>,
+        arrays: &mut HashMap<String, (String, usize)>,
         time: &mut timer::Timer,
-        qstat: &mut HashSet<String>,
+        qstat: &mut HashMap<String, String>,
+        pending: &mut Pending,
+        failures: &mut Option<ChunkFailures>,
         last_chunk: &mut Option<usize>,
-    ) where
+    ) -> Result<(), Error>
+    where
         Self: Sync,
         P: Program + Clone + Send + Sync + Serialize + for<'a> Deserialize<'a>,
         Q: Queue<P> + ?Sized + Sync,
         <Self as Drain>::Item: Clone + Serialize,
     {
         use rayon::prelude::*;
-        let works: Vec<_> = chunks
-            .borrow_mut()
-            .take((job_limit - cur_jobs.len()) / queue.chunk_size())
-            // NOTE par_bridge does NOT preserve order
-            .par_bridge()
-            .map(|(chunk_num, jobs)| {
-                let now = std::time::Instant::now();
-                let (slurm_jobs, wi, ws, ss) =
-                    queue.build_chunk(dir, jobs, chunk_num, self.procedure());
-                let job_id = jobs[0].job_id.clone();
-                let elapsed = now.elapsed();
-                if DEBUG {
-                    eprintln!(
+        let take = (job_limit - cur_jobs.len()) / chunks.size;
+        let works: Vec<_> = if queue.array_jobs() && take > 1 {
+            let chunks: Vec<_> = chunks.borrow_mut().take(take).collect();
+            let (works, array_file) =
+                build_array(queue, dir, chunks, self.procedure())?;
+            let njobs = works.iter().map(|w| w.0.len()).sum();
+            arrays.insert(works[0].2.job_id.clone(), (array_file, njobs));
+            works
+        } else {
+            chunks
+                .borrow_mut()
+                .take(take)
+                // NOTE par_bridge does NOT preserve order
+                .par_bridge()
+                .map(|(chunk_num, jobs)| {
+                    let now = std::time::Instant::now();
+                    let (sub, slurm_jobs, wi, ws, ss) = queue.build_chunk(
+                        dir,
+                        jobs,
+                        chunk_num,
+                        self.procedure(),
+                    )?;
+                    let elapsed = now.elapsed();
+                    log::debug!(
                         "submitted chunk {} after {:.1} s",
                         chunk_num,
                         elapsed.as_millis() as f64 / 1000.0
                     );
-                }
-                (jobs.to_vec(), slurm_jobs, job_id, wi, ws, ss, chunk_num)
-            })
-            .collect();
-        for (jobs, sj, job_id, wi, ws, ss, cn) in works {
+                    Ok((jobs.to_vec(), slurm_jobs, sub, wi, ws, ss, chunk_num))
+                })
+                .collect::<Result<_, Error>>()?
+        };
+        // the chunks of an array job share its id
+        let mut ids = HashSet::new();
+        for (jobs, sj, sub, wi, ws, ss, cn) in works {
             slurm_jobs.extend(sj);
             time.writing_input += wi;
             time.writing_script += ws;
             time.submitting_script += ss;
-            qstat.insert(job_id);
+            if let Some(f) = failures {
+                f.register(&jobs[0].pbs_file, jobs.len());
+            }
+            if ids.insert(sub.job_id.clone()) {
+                time.latency.record(ss);
+                pending.submit(&sub);
+                insert_job_id(qstat, sub);
+            }
             cur_jobs.extend(jobs);
             // necessary because par_bridge may swap order
             if let Some(n) = *last_chunk {
@@ -409,9 +657,127 @@ pub(crate) trait Drain {
                 *last_chunk = Some(cn);
             }
         }
+        Ok(())
     }
 }
 
+/// the jobs of a built chunk along with the job count of its submit script,
+/// the [Submission] that started it, the durations spent writing the input
+/// files, writing the submit script, and submitting it, and its chunk number
+type Built<P> = (
+    Vec<Job<P>>,
+    HashMap<String, usize>,
+    Submission,
+    time::Duration,
+    time::Duration,
+    time::Duration,
+    usize,
+);
+
+/// write the submit scripts for `chunks` and submit them as a single array job
+/// for a queue with [super::SubQueue::array_jobs] set, named after the first
+/// chunk. all of the jobs receive the id of the array, and the time spent
+/// writing and submitting the array script is attributed to the first chunk.
+/// also returns the name of the array script
+fn build_array<P, Q>(
+    queue: &Q,
+    dir: &str,
+    mut chunks: Vec<(usize, &mut [Job<P>])>,
+    proc: Procedure,
+) -> Result<(Vec<Built<P>>, String), Error>
+where
+    P: Program + Clone + Send + Sync + Serialize + for<'a> Deserialize<'a>,
+    Q: Queue<P> + ?Sized + Sync,
+{
+    use rayon::prelude::*;
+    let written: Vec<_> = chunks
+        .par_iter_mut()
+        .map(|(chunk_num, jobs)| {
+            queue.write_chunk(dir, "main", *chunk_num, jobs, proc)
+        })
+        .collect::<Result<_, _>>()?;
+    let scripts: Vec<_> = written.iter().map(|(s, _, _)| s.clone()).collect();
+    let array_file = format!(
+        "{dir}/array{}.{}",
+        chunks[0].0,
+        <Q as super::SubQueue<P>>::SCRIPT_EXT
+    );
+    time!(ws, {
+        queue.write_array_script(&scripts, &array_file)?;
+    });
+    let sub;
+    time!(ss, {
+        let _permit = super::SUBMIT_LIMITER.acquire(queue.submit_limit());
+        sub = queue.submit(&array_file)?;
+    });
+    log::debug!(
+        "submitted chunks {}-{} as array {}",
+        chunks[0].0,
+        chunks[chunks.len() - 1].0,
+        sub.job_id
+    );
+    let submitted = std::time::SystemTime::now();
+    let built = chunks
+        .into_iter()
+        .zip(written)
+        .enumerate()
+        .map(|(i, ((chunk_num, jobs), (script, wi, cs)))| {
+            for job in jobs.iter_mut() {
+                job.job_id = sub.job_id.clone();
+                job.submitted = Some(submitted);
+            }
+            let (ws, ss) = if i == 0 {
+                (cs + ws, ss)
+            } else {
+                (cs, time::Duration::default())
+            };
+            let slurm_jobs = HashMap::from([(script, jobs.len())]);
+            (
+                jobs.to_vec(),
+                slurm_jobs,
+                sub.clone(),
+                wi,
+                ws,
+                ss,
+                chunk_num,
+            )
+        })
+        .collect();
+    Ok((built, array_file))
+}
+
+/// return the ids of the jobs in `status` that may still finish on their own,
+/// along with their submit scripts from the previous `qstat`, or empty strings
+/// for jobs not submitted by this run. jobs in a terminal state, like held PBS
+/// jobs, are reported, removed from the queue with the `delete` command if
+/// there is one, and left out so that they are treated as having left the
+/// queue and resubmitted
+fn active_jobs(
+    status: HashMap<String, JobState>,
+    qstat: &HashMap<String, String>,
+    delete: Option<&str>,
+) -> HashMap<String, String> {
+    status
+        .into_iter()
+        .filter_map(|(id, state)| {
+            let script = qstat.get(&id).cloned().unwrap_or_default();
+            if !state.is_terminal() {
+                return Some((id, script));
+            }
+            log::warn!(
+                "job {id} ({script}) is in state {state:?}, treating it as \
+                 failed"
+            );
+            if let Some(delete) = delete {
+                if let Err(e) = super::status_output(delete, &[&id]) {
+                    log::warn!("failed to run {delete} {id} with {e}");
+                }
+            }
+            None
+        })
+        .collect()
+}
+
 fn to_secs(time: timeval) -> f64 {
     time.tv_sec as f64 + time.tv_usec as f64 / 1e6
 }
@@ -429,17 +795,41 @@ fn get_cpu_time() -> f64 {
     }
 }
 
-fn wait<P, Q>(queue: &Q, time: &mut timer::Timer, iter: usize, remaining: usize)
-where
+fn wait<P, Q>(
+    queue: &Q,
+    time: &mut timer::Timer,
+    iter: usize,
+    remaining: usize,
+    eta: Option<time::Duration>,
+) where
     P: Program + Clone + Send + Sync + Serialize + for<'a> Deserialize<'a>,
     Q: Queue<P> + ?Sized + Sync,
 {
     let date = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-    eprintln!(
-        "[iter {iter} {date} {:.1} CPU s] {remaining} jobs remaining",
+    let eta = match eta {
+        Some(eta) => {
+            let secs = eta.as_secs();
+            format!(
+                ", ETA {}:{:02}:{:02}",
+                secs / 3600,
+                secs % 3600 / 60,
+                secs % 60
+            )
+        }
+        None => String::new(),
+    };
+    let factor = time.latency.factor(queue.throttle());
+    let throttle = if factor > 1 {
+        format!(", slowed {factor}x for scheduler latency")
+    } else {
+        String::new()
+    };
+    log::info!(
+        "[iter {iter} {date} {:.1} CPU s] {remaining} jobs remaining{eta}\
+         {throttle}",
         get_cpu_time()
     );
-    let d = time::Duration::from_secs(queue.sleep_int() as u64);
+    let d = time::Duration::from_secs(queue.sleep_int() as u64) * factor;
     time.sleeping += d;
     thread::sleep(d);
 }
@@ -453,6 +843,13 @@ impl Drain for Opt {
         Procedure::Opt
     }
 
+    fn parse(&self) -> ParseSelectors {
+        ParseSelectors {
+            geometry: true,
+            ..Default::default()
+        }
+    }
+
     fn set_result<P: Program>(
         &self,
         dst: &mut [Self::Item],
@@ -500,6 +897,11 @@ impl Drain for Both {
         Procedure::Opt
     }
 
+    /// the whole result is returned, so read everything
+    fn parse(&self) -> ParseSelectors {
+        ParseSelectors::all()
+    }
+
     fn set_result<P: Program>(
         &self,
         dst: &mut [Self::Item],
@@ -0,0 +1,104 @@
+use crate::{
+    program::{Job, Program},
+    queue::AdaptiveChunks,
+};
+
+/// An iterator over mutable, numbered chunks of a slice of jobs, like
+/// `slice::chunks_mut(..).enumerate()`, except that the size of the next chunk
+/// can be changed between calls to `next`
+pub(crate) struct Chunks<'a, P: Program> {
+    rest: &'a mut [Job<P>],
+
+    /// the size of the next chunk to yield
+    pub(crate) size: usize,
+
+    /// the offset into the original slice of the end of each chunk yielded so
+    /// far, indexed by chunk number
+    ends: Vec<usize>,
+}
+
+impl<'a, P: Program> Chunks<'a, P> {
+    pub(crate) fn new(jobs: &'a mut [Job<P>], size: usize) -> Self {
+        Self {
+            rest: jobs,
+            size: size.max(1),
+            ends: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    /// the offset of the first job after chunk `chunk_num`, or 0 if no chunks
+    /// have been consumed
+    pub(crate) fn end(&self, chunk_num: Option<usize>) -> usize {
+        chunk_num.map_or(0, |n| self.ends[n])
+    }
+}
+
+impl<'a, P: Program> Iterator for Chunks<'a, P> {
+    type Item = (usize, &'a mut [Job<P>]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let rest = std::mem::take(&mut self.rest);
+        let (chunk, rest) = rest.split_at_mut(self.size.min(rest.len()));
+        self.rest = rest;
+        let offset = self.ends.last().copied().unwrap_or(0);
+        self.ends.push(offset + chunk.len());
+        Some((self.ends.len() - 1, chunk))
+    }
+}
+
+/// Adjusts the chunk size between the bounds in an [AdaptiveChunks] so that
+/// each chunk takes about `target_time` to run, based on the average of the
+/// job times observed so far. Chunks are roughly halved when a job times out
+pub(crate) struct ChunkSizer {
+    config: AdaptiveChunks,
+    size: usize,
+    total_time: f64,
+    count: usize,
+}
+
+impl ChunkSizer {
+    pub(crate) fn new(config: AdaptiveChunks, size: usize) -> Self {
+        Self {
+            config,
+            size: size.clamp(config.min.max(1), config.max.max(1)),
+            total_time: 0.0,
+            count: 0,
+        }
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// record a job that finished in `time` seconds
+    pub(crate) fn observe(&mut self, time: f64) {
+        if time <= 0.0 {
+            return;
+        }
+        self.total_time += time;
+        self.count += 1;
+        let mean = self.total_time / self.count as f64;
+        let ideal = (self.config.target_time as f64 / mean) as usize;
+        self.size = self.clamp(ideal);
+    }
+
+    /// record a job that ran out of time or otherwise had to be resubmitted
+    pub(crate) fn timed_out(&mut self) {
+        self.size = self.clamp(self.size / 2);
+        // forget the old timings so they don't immediately grow the chunks
+        // again
+        self.total_time = 0.0;
+        self.count = 0;
+    }
+
+    fn clamp(&self, size: usize) -> usize {
+        size.clamp(self.config.min.max(1), self.config.max.max(1))
+    }
+}
@@ -1,48 +1,209 @@
 use std::{
+    path::PathBuf,
     sync::{
-        mpsc::{self, Sender, SyncSender},
-        LazyLock,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, SendError, Sender, SyncSender},
+        Arc, LazyLock,
     },
     thread::{self, JoinHandle},
 };
 
+use crate::fs::{self, FileSystem};
+
 static DUMP_DEBUG: LazyLock<bool> =
     LazyLock::new(|| std::env::var("DUMP_DEBUG").is_ok());
 
-/// a garbage heap that spawns another thread and sends filenames to be deleted.
-/// the `None` variant is used when no_del is enabled to turn every method into
-/// a no op
+/// the default maximum number of filenames taken from the channel at once by
+/// the deletion thread
+pub(crate) const DEFAULT_BATCH_SIZE: usize = 256;
+
+/// a garbage heap that spawns another thread and sends filenames to be deleted,
+/// or compressed if requested. the `None` variant is used when no_del is
+/// enabled without compression to turn every method into a no op
 pub(crate) enum Dump {
     Real {
         /// handle for spawned thread
         handle: JoinHandle<()>,
 
         /// channel for sending filenames to be deleted
-        sender: Sender<String>,
+        sender: DumpSender,
 
         /// a sync channel for signalling that the thread should exit
         /// immediately
         signal: SyncSender<()>,
+
+        /// the counts of files handled so far, shared with the thread
+        stats: Arc<Stats>,
     },
     None,
 }
 
+/// the number of files the [Dump] thread has deleted, compressed, or moved,
+/// and the number it failed to
+#[derive(Default)]
+pub(crate) struct Stats {
+    removed: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl Stats {
+    fn get(&self) -> (usize, usize) {
+        (
+            self.removed.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// the sending half of the channel of filenames, which blocks in
+/// [Dump::send] when it is bounded and full
+pub(crate) enum DumpSender {
+    Unbounded(Sender<String>),
+    Bounded(SyncSender<String>),
+}
+
+impl DumpSender {
+    fn send(&self, s: String) -> Result<(), SendError<String>> {
+        match self {
+            DumpSender::Unbounded(sender) => sender.send(s),
+            DumpSender::Bounded(sender) => sender.send(s),
+        }
+    }
+}
+
 #[inline]
 fn nil_handler(_file: &str, _e: std::io::Result<()>) {}
 
 #[inline]
 fn debug_handler(file: &str, e: std::io::Result<()>) {
     if let Err(e) = e {
-        eprintln!("failed to remove {file} with {e}");
+        log::error!("failed to remove {file} with {e}");
     }
 }
 
+#[inline]
+fn delete(fs: &dyn FileSystem, file: &str) -> std::io::Result<()> {
+    fs.remove(file)
+}
+
+/// move `file` into the directory `trash`, keeping its path relative to the
+/// working directory, or to the root for absolute paths, so that files with
+/// the same name in different directories don't collide. a file that can't be
+/// moved because `trash` is on another file system is deleted instead
+fn trash(
+    fs: &dyn FileSystem,
+    trash: &std::path::Path,
+    file: &str,
+) -> std::io::Result<()> {
+    use std::path::{Component, Path};
+    let rel: PathBuf = Path::new(file)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => Some(c),
+            _ => None,
+        })
+        .collect();
+    let dest = trash.join(rel);
+    if let Some(parent) = dest.parent() {
+        fs.create_dir_all(&parent.to_string_lossy())?;
+    }
+    match fs.rename(file, &dest.to_string_lossy()) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            fs.remove(file)
+        }
+        res => res,
+    }
+}
+
+/// what the deletion thread does with each file it receives
+enum Action {
+    Delete,
+    Gzip,
+    Trash(PathBuf),
+}
+
+impl Action {
+    fn apply(&self, fs: &dyn FileSystem, file: &str) -> std::io::Result<()> {
+        match self {
+            Action::Delete => delete(fs, file),
+            Action::Gzip => gzip(fs, file),
+            Action::Trash(dir) => trash(fs, dir, file),
+        }
+    }
+}
+
+/// gzip `file` to `file.gz` and remove the original. files already ending in
+/// `.gz` are left alone
+fn gzip(fs: &dyn FileSystem, file: &str) -> std::io::Result<()> {
+    if file.ends_with(".gz") {
+        return Ok(());
+    }
+    fs.gzip(file)
+}
+
 impl Dump {
-    pub(crate) fn new(no_del: bool) -> Self {
-        if no_del {
+    /// construct a new Dump. if `compress` is true, files are gzipped instead
+    /// of being deleted, regardless of `no_del`. files are removed through the
+    /// calling thread's [FileSystem]
+    #[allow(unused)]
+    pub(crate) fn new(no_del: bool, compress: bool) -> Self {
+        Self::with_capacity(no_del, compress, None)
+    }
+
+    /// like [Dump::new], but if `cap` is `Some`, hold at most that many
+    /// filenames waiting to be deleted, blocking [Dump::send] until the
+    /// deletion thread catches up
+    pub(crate) fn with_capacity(
+        no_del: bool,
+        compress: bool,
+        cap: Option<usize>,
+    ) -> Self {
+        Self::with_options(no_del, compress, cap, DEFAULT_BATCH_SIZE)
+    }
+
+    /// like [Dump::with_capacity], but have the deletion thread take up to
+    /// `batch_size` filenames from the channel at a time, once at least one is
+    /// available, and process them sorted by path so that the files in each
+    /// directory are handled together
+    pub(crate) fn with_options(
+        no_del: bool,
+        compress: bool,
+        cap: Option<usize>,
+        batch_size: usize,
+    ) -> Self {
+        if no_del && !compress {
             return Self::None;
         }
-        let (sender, receiver) = mpsc::channel::<String>();
+        let action = if compress {
+            Action::Gzip
+        } else {
+            Action::Delete
+        };
+        Self::spawn(action, cap, batch_size)
+    }
+
+    /// construct a Dump that moves files into `dir` instead of deleting them,
+    /// for inspecting them after a run or avoiding slow deletions on some file
+    /// systems, with the channel capacity `cap` described in
+    /// [Dump::with_capacity]. files that can't be moved to another file system
+    /// are deleted instead. `dir` must already exist
+    pub(crate) fn new_with_trash(dir: PathBuf, cap: Option<usize>) -> Self {
+        Self::spawn(Action::Trash(dir), cap, DEFAULT_BATCH_SIZE)
+    }
+
+    /// start the thread applying `action` to each file it receives, with the
+    /// channel capacity and batch size described in [Dump::with_options]
+    fn spawn(action: Action, cap: Option<usize>, batch_size: usize) -> Self {
+        let (sender, receiver) = match cap {
+            Some(cap) => {
+                let (sender, receiver) = mpsc::sync_channel::<String>(cap);
+                (DumpSender::Bounded(sender), receiver)
+            }
+            None => {
+                let (sender, receiver) = mpsc::channel::<String>();
+                (DumpSender::Unbounded(sender), receiver)
+            }
+        };
         let (signal, exit) = mpsc::sync_channel(0);
 
         // check this condition once before the loop
@@ -52,12 +213,30 @@ impl Dump {
             nil_handler
         };
 
+        let fs = fs::current();
+
+        let batch_size = batch_size.max(1);
+        let stats = Arc::new(Stats::default());
+        let counts = stats.clone();
         let handle = thread::spawn(move || {
-            for file in receiver {
-                if exit.try_recv().is_ok() {
-                    return;
+            let mut batch = Vec::with_capacity(batch_size);
+            while let Ok(file) = receiver.recv() {
+                batch.push(file);
+                batch.extend(receiver.try_iter().take(batch_size - 1));
+                batch.sort_unstable();
+                for file in batch.drain(..) {
+                    if exit.try_recv().is_ok() {
+                        return;
+                    }
+                    let res = action.apply(&*fs, &file);
+                    let count = if res.is_ok() {
+                        &counts.removed
+                    } else {
+                        &counts.failed
+                    };
+                    count.fetch_add(1, Ordering::Relaxed);
+                    err_handler(&file, res);
                 }
-                err_handler(&file, std::fs::remove_file(&file));
             }
         });
 
@@ -65,6 +244,16 @@ impl Dump {
             handle,
             sender,
             signal,
+            stats,
+        }
+    }
+
+    /// return the number of files handled successfully so far and the number
+    /// that could not be, which is always `(0, 0)` for [Dump::None]
+    pub(crate) fn stats(&self) -> (usize, usize) {
+        match self {
+            Dump::Real { stats, .. } => stats.get(),
+            Dump::None => (0, 0),
         }
     }
 
@@ -77,19 +266,20 @@ impl Dump {
         }
     }
 
-    pub(crate) fn shutdown(self) {
+    pub(crate) fn shutdown(mut self) {
         let Self::Real {
             handle,
             sender,
             signal,
-        } = self
+            stats,
+        } = std::mem::replace(&mut self, Self::None)
         else {
             return;
         };
         time!(e, {
             drop(sender);
         });
-        eprintln!(
+        log::debug!(
             "finished dropping after {:.1} s",
             e.as_millis() as f64 / 1000.0
         );
@@ -100,9 +290,36 @@ impl Dump {
         time!(e, {
             handle.join().unwrap();
         });
-        eprintln!(
+        log::debug!(
             "finished dropping after {:.1} s",
             e.as_millis() as f64 / 1000.0
         );
+        let (removed, failed) = stats.get();
+        let msg = format!("cleaned up {removed} files, failed on {failed}");
+        if failed > 0 {
+            log::warn!("{msg}");
+        } else {
+            log::info!("{msg}");
+        }
+    }
+}
+
+/// stop the thread when a [Dump] is dropped without [Dump::shutdown], such as
+/// when a panic unwinds through `drain`, so that it is not still removing or
+/// compressing files when their directory is removed, like a queue's
+/// `temp_dir`
+impl Drop for Dump {
+    fn drop(&mut self) {
+        if let Self::Real {
+            handle,
+            sender,
+            signal,
+            ..
+        } = std::mem::replace(self, Self::None)
+        {
+            drop(sender);
+            let _ = signal.send(());
+            let _ = handle.join();
+        }
     }
 }
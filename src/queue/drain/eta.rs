@@ -0,0 +1,58 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// A moving-average estimate of the time remaining in a run, based on the
+/// throughput over the last `window` completion samples
+#[derive(Debug)]
+pub struct Eta {
+    window: usize,
+
+    /// `(time, total jobs completed)` pairs, oldest first
+    samples: VecDeque<(Instant, usize)>,
+
+    remaining: usize,
+}
+
+impl Eta {
+    /// construct a new [Eta] averaging over the last `window` samples. at
+    /// least two samples are always kept so that there is an interval to
+    /// average over
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(2),
+            samples: VecDeque::new(),
+            remaining: 0,
+        }
+    }
+
+    /// record that `completed` jobs have finished in total so far and that
+    /// `remaining` jobs are left
+    pub fn record(&mut self, completed: usize, remaining: usize) {
+        self.record_at(Instant::now(), completed, remaining);
+    }
+
+    pub(crate) fn record_at(
+        &mut self,
+        time: Instant,
+        completed: usize,
+        remaining: usize,
+    ) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((time, completed));
+        self.remaining = remaining;
+    }
+
+    /// the estimated time until the remaining jobs finish, or `None` until at
+    /// least two samples with some completions between them have been recorded
+    pub fn eta(&self) -> Option<Duration> {
+        let (t0, c0) = self.samples.front()?;
+        let (t1, c1) = self.samples.back()?;
+        let done = c1.checked_sub(*c0).filter(|&d| d > 0)?;
+        let per_job = t1.duration_since(*t0).as_secs_f64() / done as f64;
+        Some(Duration::from_secs_f64(per_job * self.remaining as f64))
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{program::ProgramError, queue::ChunkFailure};
+
+#[derive(Default)]
+struct Chunk {
+    size: usize,
+
+    /// the names of the failed jobs. a set because failed jobs are re-read on
+    /// every iteration
+    failed: HashSet<String>,
+
+    /// the first error encountered in the chunk
+    error: Option<String>,
+
+    reported: bool,
+}
+
+/// Tracks the fraction of failed jobs in each chunk, identified by its submit
+/// script, to catch systematically broken chunks early
+pub(crate) struct ChunkFailures {
+    config: ChunkFailure,
+    chunks: HashMap<String, Chunk>,
+}
+
+impl ChunkFailures {
+    pub(crate) fn new(config: ChunkFailure) -> Self {
+        Self {
+            config,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn pause(&self) -> bool {
+        self.config.pause
+    }
+
+    /// record that `chunk` was submitted with `size` jobs
+    pub(crate) fn register(&mut self, chunk: &str, size: usize) {
+        self.chunks.entry(chunk.to_owned()).or_default().size = size;
+    }
+
+    /// the fraction of the jobs in `chunk` that have failed so far
+    pub(crate) fn rate(&self, chunk: &str) -> Option<f64> {
+        let c = self.chunks.get(chunk)?;
+        (c.size > 0).then(|| c.failed.len() as f64 / c.size as f64)
+    }
+
+    /// the failure rate of every registered chunk, sorted by submit script
+    pub(crate) fn rates(&self) -> Vec<(String, f64)> {
+        let mut ret: Vec<_> = self
+            .chunks
+            .keys()
+            .filter_map(|k| Some((k.clone(), self.rate(k)?)))
+            .collect();
+        ret.sort_by(|a, b| a.0.cmp(&b.0));
+        ret
+    }
+
+    /// record that `job` in `chunk` failed with `err`. the first time the
+    /// failure rate of `chunk` exceeds the threshold, return a report of it
+    pub(crate) fn fail(
+        &mut self,
+        chunk: &str,
+        job: &str,
+        err: &ProgramError,
+    ) -> Option<String> {
+        let c = self.chunks.entry(chunk.to_owned()).or_default();
+        c.failed.insert(job.to_owned());
+        c.error.get_or_insert_with(|| err.to_string());
+        let (failed, size) = (c.failed.len(), c.size.max(c.failed.len()));
+        if c.reported || (failed as f64) <= self.config.threshold * size as f64
+        {
+            return None;
+        }
+        c.reported = true;
+        Some(format!(
+            "{failed} of {size} jobs in chunk {chunk} failed, starting with \
+             `{}`",
+            c.error.as_deref().unwrap_or_default()
+        ))
+    }
+}
@@ -0,0 +1,55 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::queue::Throttle;
+
+/// Running statistics on the time taken by scheduler commands like `qstat` and
+/// `qsub`
+#[derive(Default)]
+pub(crate) struct Latency {
+    last: Duration,
+    max: Duration,
+    total: Duration,
+    count: u32,
+}
+
+impl Latency {
+    pub(crate) fn record(&mut self, d: Duration) {
+        self.last = d;
+        self.max = self.max.max(d);
+        self.total += d;
+        self.count += 1;
+    }
+
+    /// the factor by which to stretch the polling interval given the most
+    /// recent latency. this is the latency in multiples of
+    /// `throttle.threshold`, clamped to `[1, throttle.max_factor]`, or 1 if
+    /// `throttle` is `None`
+    pub(crate) fn factor(&self, throttle: Option<Throttle>) -> u32 {
+        let Some(Throttle {
+            threshold,
+            max_factor,
+        }) = throttle
+        else {
+            return 1;
+        };
+        let ratio = self.last.as_secs_f64() / threshold;
+        (ratio.ceil() as u32).clamp(1, max_factor.max(1))
+    }
+}
+
+impl Display for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mean = if self.count > 0 {
+            self.total.as_secs_f64() / self.count as f64
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "{:.1} s mean, {:.1} s max scheduler latency over {} calls",
+            mean,
+            self.max.as_secs_f64(),
+            self.count
+        )
+    }
+}
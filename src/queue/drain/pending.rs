@@ -0,0 +1,61 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::queue::Submission;
+
+/// Tracks submitted job ids that have not yet appeared in the queue status.
+/// Schedulers can take some time to report newly-submitted jobs, so these are
+/// treated as still queued for up to `grace`. After that, they are flagged as
+/// never-started and left out of the status so that they get resubmitted
+/// instead of waited on forever. With no grace period, missing jobs are
+/// treated as finished immediately.
+pub(crate) struct Pending {
+    grace: Option<Duration>,
+    /// the submit script and submission time of each pending job id
+    submitted: HashMap<String, (String, Instant)>,
+}
+
+impl Pending {
+    pub(crate) fn new(grace: Option<Duration>) -> Self {
+        Self {
+            grace,
+            submitted: HashMap::new(),
+        }
+    }
+
+    /// record that `sub` was just submitted
+    pub(crate) fn submit(&mut self, sub: &Submission) {
+        if self.grace.is_some() && !sub.job_id.is_empty() {
+            self.submitted.insert(
+                sub.job_id.clone(),
+                (sub.script.clone(), Instant::now()),
+            );
+        }
+    }
+
+    /// update the freshly-retrieved `qstat` by adding back any pending jobs
+    /// still within the grace period and warning about any that have exceeded
+    /// it. jobs that have appeared in `qstat` are no longer tracked
+    pub(crate) fn update(&mut self, qstat: &mut HashMap<String, String>) {
+        let Some(grace) = self.grace else {
+            return;
+        };
+        self.submitted.retain(|id, (script, time)| {
+            if qstat.contains_key(id) {
+                false
+            } else if time.elapsed() > grace {
+                log::warn!(
+                    "job {id} ({script}) never appeared in the queue \
+                     after {} s, treating it as never-started",
+                    grace.as_secs()
+                );
+                false
+            } else {
+                qstat.insert(id.clone(), script.clone());
+                true
+            }
+        });
+    }
+}
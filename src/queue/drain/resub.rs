@@ -1,10 +1,11 @@
-use std::{collections::HashMap, path::Path, time::Duration};
+use std::{collections::HashMap, time::Duration};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     program::{Job, Procedure, Program},
-    queue::Queue,
+    queue::{Queue, Submission},
+    Error,
 };
 
 pub(crate) struct Resub<
@@ -22,7 +23,7 @@ pub(crate) struct Resub<
 pub(crate) struct ResubOutput<P: Program + Clone + Send + Sync> {
     pub(crate) jobs: Vec<Job<P>>,
     pub(crate) slurm_jobs: HashMap<String, usize>,
-    pub(crate) job_id: String,
+    pub(crate) submission: Submission,
     pub(crate) writing_input: Duration,
     pub(crate) writing_script: Duration,
     pub(crate) submitting: Duration,
@@ -32,7 +33,7 @@ impl<P: Program + Clone + Send + Sync> ResubOutput<P> {
     fn new(
         jobs: Vec<Job<P>>,
         slurm_jobs: HashMap<String, usize>,
-        job_id: String,
+        submission: Submission,
         writing_input: Duration,
         writing_script: Duration,
         submitting: Duration,
@@ -40,7 +41,7 @@ impl<P: Program + Clone + Send + Sync> ResubOutput<P> {
         Self {
             jobs,
             slurm_jobs,
-            job_id,
+            submission,
             writing_input,
             writing_script,
             submitting,
@@ -68,48 +69,35 @@ impl<
         self.jobs.push(job)
     }
 
-    pub(crate) fn resubmit(&mut self) -> Vec<ResubOutput<P>> {
+    pub(crate) fn resubmit(&mut self) -> Result<Vec<ResubOutput<P>>, Error> {
         // this is inlined from Queue::resubmit minus actually submitting the
-        // job. copy all of the original jobs to job_redo.ext
+        // job. copy all of the original input files to job_redo.ext
         for job in &mut self.jobs {
-            let filename = format!(
-                "{}.{}",
-                job.program.filename(),
-                job.program.extension()
-            );
-            let path = Path::new(&filename);
-            let dir = path.parent().unwrap().to_str().unwrap();
-            let base = path.file_stem().unwrap().to_str().unwrap();
-            {
-                let ext = path.extension().unwrap().to_str().unwrap();
-                let inp_file = format!("{dir}/{base}_redo.{ext}");
-                match std::fs::copy(&filename, &inp_file) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        panic!(
-                            "failed to copy {} to {} with `{}`",
-                            &filename, inp_file, e
-                        )
-                    }
+            let filename = job.program.filename();
+            let inp_name = format!("{filename}_redo");
+            for inp_file in job.program.input_files() {
+                // files not named after the job don't need a copy
+                let Some(rest) = inp_file.strip_prefix(&filename) else {
+                    continue;
                 };
+                let redo_file = format!("{inp_name}{rest}");
+                std::fs::copy(&inp_file, &redo_file)
+                    .map_err(|e| Error::io(&inp_file, e))?;
             }
-            // nothing but the copy needs the name with extension
-            let inp_name = format!("{dir}/{base}_redo");
             job.program.set_filename(&inp_name);
         }
         let mut jobs = std::mem::take(&mut self.jobs);
         jobs.chunks_mut(self.queue.chunk_size())
             .map(|jobs| {
-                let (sj, wi, ws, ss) = self.queue.build_chunk_inner(
+                let (sub, sj, wi, ws, ss) = self.queue.build_chunk_inner(
                     self.dir,
                     "redo",
                     self.counter,
                     jobs,
                     self.proc,
-                );
+                )?;
                 self.counter += 1;
-                let job_id = jobs[0].job_id.clone();
-                ResubOutput::new(jobs.to_vec(), sj, job_id, wi, ws, ss)
+                Ok(ResubOutput::new(jobs.to_vec(), sj, sub, wi, ws, ss))
             })
             .collect()
     }
@@ -0,0 +1,341 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    program::ProgramError,
+    queue::{ChunkFailure, Submission, Throttle},
+};
+
+use super::{
+    dump::Dump, failures::ChunkFailures, latency::Latency, pending::Pending,
+};
+
+#[test]
+fn pending() {
+    // within the grace period, missing jobs are added back to the status
+    let sub =
+        |id: &str| Submission::new(id.to_owned(), &format!("main{id}.pbs"));
+    let mut pending = Pending::new(Some(Duration::from_secs(3600)));
+    pending.submit(&sub("1"));
+    pending.submit(&sub("2"));
+    let mut qstat = HashMap::from([("2".to_owned(), String::new())]);
+    pending.update(&mut qstat);
+    assert_eq!(
+        qstat,
+        HashMap::from([
+            ("1".to_owned(), "main1.pbs".to_owned()),
+            ("2".to_owned(), String::new())
+        ])
+    );
+
+    // once they've been seen, they're no longer tracked
+    let mut qstat = HashMap::new();
+    pending.update(&mut qstat);
+    assert_eq!(
+        qstat,
+        HashMap::from([("1".to_owned(), "main1.pbs".to_owned())])
+    );
+
+    // after the grace period, they're left out
+    let mut pending = Pending::new(Some(Duration::ZERO));
+    pending.submit(&sub("1"));
+    std::thread::sleep(Duration::from_millis(1));
+    let mut qstat = HashMap::new();
+    pending.update(&mut qstat);
+    assert!(qstat.is_empty());
+
+    // and with no grace period they're never added
+    let mut pending = Pending::new(None);
+    pending.submit(&sub("1"));
+    pending.update(&mut qstat);
+    assert!(qstat.is_empty());
+}
+
+#[test]
+fn chunks() {
+    use super::chunks::Chunks;
+    use crate::geom::Geom;
+    use crate::program::{mopac::Mopac, Job, Program, Template};
+    let mut jobs: Vec<_> = (0..10)
+        .map(|i| {
+            let mopac = Mopac::new(
+                format!("job{i}"),
+                Template::from(""),
+                0,
+                Geom::default(),
+            );
+            Job::new(mopac, i)
+        })
+        .collect();
+    let mut chunks = Chunks::new(&mut jobs, 4);
+    let (n, chunk) = chunks.next().unwrap();
+    assert_eq!((n, chunk.len(), chunk[0].index), (0, 4, 0));
+    chunks.size = 2;
+    let (n, chunk) = chunks.next().unwrap();
+    assert_eq!((n, chunk.len(), chunk[0].index), (1, 2, 4));
+    assert_eq!(chunks.end(None), 0);
+    assert_eq!(chunks.end(Some(0)), 4);
+    assert_eq!(chunks.end(Some(1)), 6);
+    chunks.size = 8;
+    let (n, chunk) = chunks.next().unwrap();
+    assert_eq!((n, chunk.len(), chunk[0].index), (2, 4, 6));
+    assert!(chunks.is_empty());
+    assert!(chunks.next().is_none());
+}
+
+#[test]
+fn chunk_sizer() {
+    use super::chunks::ChunkSizer;
+    use crate::queue::AdaptiveChunks;
+    let config = AdaptiveChunks {
+        min: 2,
+        max: 64,
+        target_time: 600,
+    };
+    let mut sizer = ChunkSizer::new(config, 128);
+    assert_eq!(sizer.size(), 64);
+    // 60 s jobs fit 10 to a chunk
+    sizer.observe(60.0);
+    assert_eq!(sizer.size(), 10);
+    // fast jobs grow the chunks up to the maximum
+    sizer.observe(0.1);
+    assert_eq!(sizer.size(), 19);
+    for _ in 0..100 {
+        sizer.observe(0.1);
+    }
+    assert_eq!(sizer.size(), 64);
+    sizer.timed_out();
+    assert_eq!(sizer.size(), 32);
+    for _ in 0..10 {
+        sizer.timed_out();
+    }
+    assert_eq!(sizer.size(), 2);
+}
+
+#[test]
+fn chunk_failures() {
+    let mut f = ChunkFailures::new(ChunkFailure {
+        threshold: 0.5,
+        pause: false,
+    });
+    f.register("main0.pbs", 4);
+    let err = ProgramError::ErrorInOutput("job0.out".to_owned());
+    assert!(f.fail("main0.pbs", "job0", &err).is_none());
+    // failed jobs are re-read on every iteration, so repeats don't count
+    assert!(f.fail("main0.pbs", "job0", &err).is_none());
+    assert!(f.fail("main0.pbs", "job1", &err).is_none());
+    assert_eq!(f.rate("main0.pbs"), Some(0.5));
+    let got = f
+        .fail(
+            "main0.pbs",
+            "job2",
+            &ProgramError::ErrorInOutput("job2.out".to_owned()),
+        )
+        .unwrap();
+    assert_eq!(
+        got,
+        "3 of 4 jobs in chunk main0.pbs failed, \
+         starting with `ErrorInOutput(\"job0.out\")`"
+    );
+    // only reported once
+    assert!(f.fail("main0.pbs", "job3", &err).is_none());
+    assert_eq!(f.rate("main1.pbs"), None);
+    f.register("main1.pbs", 2);
+    assert_eq!(
+        f.rates(),
+        vec![("main0.pbs".to_owned(), 1.0), ("main1.pbs".to_owned(), 0.0)]
+    );
+}
+
+#[test]
+fn latency() {
+    let throttle = Some(Throttle::default());
+    let mut latency = Latency::default();
+    assert_eq!(latency.factor(throttle), 1);
+    latency.record(Duration::from_secs(1));
+    assert_eq!(latency.factor(throttle), 1);
+    latency.record(Duration::from_secs(12));
+    assert_eq!(latency.factor(throttle), 3);
+    assert_eq!(latency.factor(None), 1);
+    latency.record(Duration::from_secs(100));
+    assert_eq!(latency.factor(throttle), 8);
+    assert_eq!(
+        latency.to_string(),
+        "37.7 s mean, 100.0 s max scheduler latency over 3 calls"
+    );
+}
+
+#[test]
+fn dump_capacity() {
+    use crate::fs::{self, FileSystem, MemFs};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let prev = fs::set(mem.clone());
+    let files: Vec<_> = (0..10).map(|i| format!("dump{i}.out")).collect();
+    for (cap, batch_size) in [(None, 256), (Some(0), 256), (Some(2), 3)] {
+        for f in &files {
+            mem.write(f, b"").unwrap();
+        }
+        let dump = match cap {
+            Some(cap) => {
+                Dump::with_options(false, false, Some(cap), batch_size)
+            }
+            None => Dump::new(false, false),
+        };
+        // a bounded channel blocks here until the deleter catches up
+        for f in &files {
+            dump.send(f.clone());
+        }
+        for _ in 0..100 {
+            if mem.paths().is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(mem.paths().is_empty(), "{cap:?}: {:?}", mem.paths());
+        assert_eq!(dump.stats(), (files.len(), 0));
+        dump.shutdown();
+    }
+    fs::set(prev);
+}
+
+#[test]
+fn dump_trash() {
+    use crate::fs::{self, FileSystem, MemFs};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let prev = fs::set(mem.clone());
+    // files with the same name in different directories are kept apart
+    mem.write("pts/job.00000000.out", b"output").unwrap();
+    mem.write("/tmp/redo/job.00000000.out", b"redo").unwrap();
+    let dump = Dump::new_with_trash("trash".into(), Some(1));
+    dump.send("pts/job.00000000.out".to_owned());
+    dump.send("/tmp/redo/job.00000000.out".to_owned());
+    for _ in 0..100 {
+        if mem.paths().iter().all(|p| p.starts_with("trash/")) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    dump.shutdown();
+    fs::set(prev);
+    assert_eq!(
+        mem.paths(),
+        vec![
+            "trash/pts/job.00000000.out",
+            "trash/tmp/redo/job.00000000.out"
+        ]
+    );
+    assert_eq!(mem.read("trash/pts/job.00000000.out").unwrap(), b"output");
+    assert_eq!(
+        mem.read("trash/tmp/redo/job.00000000.out").unwrap(),
+        b"redo"
+    );
+}
+
+#[test]
+fn dump_stats() {
+    use crate::fs::{self, FileSystem, MemFs};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let prev = fs::set(mem.clone());
+    mem.write("present.out", b"").unwrap();
+    let dump = Dump::new(false, false);
+    dump.send("present.out".to_owned());
+    dump.send("missing.out".to_owned());
+    for _ in 0..100 {
+        if dump.stats() == (1, 1) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(dump.stats(), (1, 1));
+    dump.shutdown();
+    fs::set(prev);
+    assert_eq!(Dump::new(true, false).stats(), (0, 0));
+}
+
+#[test]
+fn missing_checkpoint() {
+    use super::{Drain, Single};
+    use crate::{program::molpro::Molpro, Error};
+    let mut dst = [0.0];
+    let got = Single::load_checkpoint::<Molpro>("/not/a/chk.json", &mut dst);
+    assert!(matches!(got, Err(Error::Io(_))));
+    assert_eq!(
+        ProgramError::from(Error::Submit("qsub failed".to_owned())),
+        ProgramError::Queue("submit error: qsub failed".to_owned())
+    );
+}
+
+#[test]
+fn old_checkpoint() {
+    use super::{Both, Drain};
+    use crate::program::{molpro::Molpro, ProgramResult};
+    // a checkpoint written before any of the optional results were added
+    let chk = "/tmp/old_chk.json";
+    std::fs::write(
+        chk,
+        r#"{"dst": [{"energy": -1.5, "cart_geom": null, "time": 2.0}],
+            "jobs": []}"#,
+    )
+    .unwrap();
+    let mut dst = [ProgramResult::default()];
+    let jobs = Both::load_checkpoint::<Molpro>(chk, &mut dst).unwrap();
+    assert!(jobs.is_empty());
+    let want = ProgramResult {
+        energy: -1.5,
+        time: 2.0,
+        ..Default::default()
+    };
+    assert_eq!(dst[0], want);
+}
+
+#[test]
+fn gzip() {
+    use crate::fs::{FileSystem, MemFs, StdFs};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    let contents = "energy= -76.369839607972\n".repeat(1000);
+    let decode = |gz: Vec<u8>| {
+        let mut s = String::new();
+        GzDecoder::new(&gz[..]).read_to_string(&mut s).unwrap();
+        s
+    };
+
+    let file = "/tmp/gzip_test.out";
+    StdFs.write(file, contents.as_bytes()).unwrap();
+    StdFs.gzip(file).unwrap();
+    assert!(!std::path::Path::new(file).exists());
+    assert_eq!(decode(StdFs.read(&format!("{file}.gz")).unwrap()), contents);
+
+    let mem = MemFs::new();
+    mem.write("job.out", contents.as_bytes()).unwrap();
+    mem.gzip("job.out").unwrap();
+    assert_eq!(mem.paths(), vec!["job.out.gz"]);
+    assert_eq!(decode(mem.read("job.out.gz").unwrap()), contents);
+}
+
+#[test]
+fn active_jobs() {
+    use crate::queue::JobState;
+    let status = HashMap::from([
+        ("1".to_owned(), JobState::Running),
+        ("2".to_owned(), JobState::Held),
+        ("3".to_owned(), JobState::Exiting),
+    ]);
+    // the scripts of jobs from this run are kept
+    let qstat = HashMap::from([("1".to_owned(), "main1.pbs".to_owned())]);
+    let want = HashMap::from([
+        ("1".to_owned(), "main1.pbs".to_owned()),
+        ("3".to_owned(), String::new()),
+    ]);
+    assert_eq!(
+        super::active_jobs(status.clone(), &qstat, Some("true")),
+        want
+    );
+    // a failed delete is only reported
+    assert_eq!(
+        super::active_jobs(status, &qstat, Some("/no/such/qdel")),
+        want
+    );
+}
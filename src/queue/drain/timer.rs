@@ -4,6 +4,8 @@ use std::fmt::Display;
 
 use std::time::Duration;
 
+use super::latency::Latency;
+
 #[derive(Default)]
 pub(crate) struct Timer {
     pub(crate) writing_input: Duration,
@@ -12,6 +14,7 @@ pub(crate) struct Timer {
     pub(crate) reading: Duration,
     pub(crate) sleeping: Duration,
     pub(crate) removing: Duration,
+    pub(crate) latency: Latency,
 }
 
 impl Display for Timer {
@@ -19,13 +22,14 @@ impl Display for Timer {
         write!(
             f,
             "{:.1} s reading ok, {:.1} s writing input, {:.1} s writing script, \
-	     {:.1} s submitting, {:.1} s sleeping, {:.1} s removing",
+	     {:.1} s submitting, {:.1} s sleeping, {:.1} s removing, {}",
             self.reading.as_millis() as f64 / 1000.0,
             self.writing_input.as_millis() as f64 / 1000.0,
             self.writing_script.as_millis() as f64 / 1000.0,
             self.submitting_script.as_millis() as f64 / 1000.0,
             self.sleeping.as_millis() as f64 / 1000.0,
             self.removing.as_millis() as f64 / 1000.0,
+            self.latency,
         )
     }
 }
@@ -1,13 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
+use std::process::{Child, Command};
+use std::sync::Mutex;
 
 use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
 
 use crate::program::Program;
 use crate::queue::Queue;
+use crate::Error;
 
-use super::{SubQueue, Submit};
+use super::{
+    batches, mop_files, push_exports, temp_dir, write_script, AdaptiveChunks,
+    ChunkFailure, QueueOptions, SubQueue, Submission, Submit, SubmitError,
+};
+
+#[cfg(test)]
+mod tests;
 
 /// Minimal implementation for testing MOPAC locally
 #[derive(Debug)]
@@ -15,6 +25,41 @@ pub struct Local {
     pub dir: String,
     pub chunk_size: usize,
     pub mopac: String,
+
+    /// the options shared with the other queue types. defaults to
+    /// [QueueOptions::default]
+    pub options: QueueOptions,
+
+    /// a command to prefix each run line in the submit script with, such as
+    /// `singularity exec image.sif`, for running a containerized binary.
+    /// defaults to `None`, running `mopac` directly
+    pub prefix: Option<String>,
+
+    /// run `mopac` directly on each input file instead of generating and
+    /// running a shell script. the submit script then only lists the input
+    /// files, one line per run, and the output of each run is appended to the
+    /// script's `.out` file along with its exit status. defaults to false
+    pub direct: bool,
+
+    /// environment variables to export in each submit script before running
+    /// the jobs, such as `OMP_NUM_THREADS`. defaults to empty
+    pub env: Vec<(String, String)>,
+
+    /// the maximum number of input files to pass to a single `mopac`
+    /// invocation, avoiding its startup cost for each job. MOPAC still writes
+    /// a separate output file for each input. defaults to `None`, running
+    /// `mopac` once per input file
+    pub batch_size: Option<usize>,
+
+    /// the temporary directory created by [Self::in_temp_dir], which is
+    /// removed along with all of its files when `self` is dropped. defaults to
+    /// `None`, leaving the files in `dir`
+    pub temp_dir: Option<TempDir>,
+
+    /// the submit scripts started in the background by [Submit::submit],
+    /// keyed by their process ids, which are used as the job ids. finished
+    /// ones are removed by [SubQueue::stat_cmd]. defaults to empty
+    pub running: Mutex<HashMap<String, Child>>,
 }
 
 impl Default for Local {
@@ -23,6 +68,13 @@ impl Default for Local {
             dir: ".".to_string(),
             chunk_size: 128,
             mopac: "/opt/mopac/mopac".to_owned(),
+            options: QueueOptions::default(),
+            prefix: None,
+            direct: false,
+            env: Vec::new(),
+            batch_size: None,
+            temp_dir: None,
+            running: Mutex::new(HashMap::new()),
         }
     }
 }
@@ -32,7 +84,7 @@ impl Local {
         chunk_size: usize,
         _job_limit: usize,
         _sleep_int: usize,
-        dir: &'static str,
+        dir: &str,
         _no_del: bool,
         _template: Option<String>,
     ) -> Self {
@@ -40,13 +92,95 @@ impl Local {
             dir: dir.to_string(),
             chunk_size,
             mopac: "/opt/mopac/mopac".to_string(),
+            options: QueueOptions::default(),
+            prefix: None,
+            direct: false,
+            env: Vec::new(),
+            batch_size: None,
+            temp_dir: None,
+            running: Mutex::new(HashMap::new()),
         }
     }
+
+    /// run the whole campaign in a new temporary directory instead of `dir`,
+    /// for transient runs like tests where none of the files are needed
+    /// afterward. the directory is removed when `self` is dropped, including
+    /// while unwinding from a panic, so the job filenames should be built from
+    /// [SubQueue::dir] rather than the original `dir`
+    pub fn in_temp_dir(mut self) -> std::io::Result<Self> {
+        let (dir, tmp) = temp_dir()?;
+        self.dir = dir;
+        self.temp_dir = Some(tmp);
+        Ok(self)
+    }
 }
 
-impl<P> Submit<P> for Local where
-    P: Program + Clone + Serialize + for<'a> Deserialize<'a>
+impl<P> Submit<P> for Local
+where
+    P: Program + Clone + Serialize + for<'a> Deserialize<'a>,
 {
+    fn submit(&self, filename: &str) -> Result<Submission, SubmitError> {
+        if !self.direct {
+            let child =
+                Command::new(<Self as SubQueue<P>>::submit_command(self))
+                    .arg(filename)
+                    .spawn()
+                    .map_err(|e| SubmitError::io(filename, e))?;
+            let job_id = child.id().to_string();
+            self.running.lock().unwrap().insert(job_id.clone(), child);
+            return Ok(Submission::new(job_id, filename));
+        }
+        let infiles = crate::fs::current()
+            .read_to_string(filename)
+            .map_err(|e| SubmitError::io(filename, e))?;
+        let outfile = format!("{filename}.out");
+        let mut out = File::create(&outfile).map_err(|e| {
+            SubmitError::io(
+                filename,
+                format!("failed to create {outfile}: {e}"),
+            )
+        })?;
+        let write_err = |e: std::io::Error| {
+            SubmitError::io(filename, format!("failed to write {outfile}: {e}"))
+        };
+        for f in infiles.lines() {
+            let mut words =
+                self.prefix.iter().flat_map(|p| p.split_whitespace());
+            let mut cmd = match words.next() {
+                Some(prog) => {
+                    let mut cmd = Command::new(prog);
+                    cmd.args(words).arg(&self.mopac);
+                    cmd
+                }
+                None => Command::new(&self.mopac),
+            };
+            let output = cmd
+                .args(f.split_whitespace())
+                .env("LD_LIBRARY_PATH", "/opt/mopac/")
+                .envs(self.env.iter().map(|(k, v)| (k, v)))
+                .output();
+            let status = match output {
+                Ok(o) => {
+                    out.write_all(&o.stdout)
+                        .and_then(|_| out.write_all(&o.stderr))
+                        .map_err(write_err)?;
+                    o.status
+                }
+                Err(e) => {
+                    return Err(SubmitError::io(
+                        filename,
+                        format!("failed to run {} on {f}: {e}", self.mopac),
+                    ));
+                }
+            };
+            if !status.success() {
+                log::warn!("{} exited with {status} on {f}", self.mopac);
+            }
+            writeln!(out, "{f}: {status}\n================")
+                .map_err(write_err)?;
+        }
+        Ok(Submission::new(String::new(), filename))
+    }
 }
 
 impl<P> Queue<P> for Local
@@ -58,20 +192,41 @@ where
         + Serialize
         + for<'a> Deserialize<'a>,
 {
-    fn write_submit_script(&self, infiles: &[String], filename: &str) {
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
         use std::fmt::Write;
-        let mut body = String::from("export LD_LIBRARY_PATH=/opt/mopac/\n");
-        for f in infiles {
-            writeln!(body, "{} {f}.mop &> {filename}.out", self.mopac).unwrap();
-            writeln!(body, "cat {f}.mop {f}.out >> {filename}.out").unwrap();
-            writeln!(body, "echo \"================\" >> {filename}.out")
-                .unwrap();
+        let mut body = String::new();
+        if self.direct {
+            // just list the input files for `submit` to run
+            for batch in batches(infiles, self.batch_size) {
+                writeln!(body, "{}", mop_files(batch)).unwrap();
+            }
+        } else {
+            body.push_str("export LD_LIBRARY_PATH=/opt/mopac/\n");
+            push_exports(&mut body, &self.env);
+            let cmd = match &self.prefix {
+                Some(prefix) => format!("{prefix} {}", self.mopac),
+                None => self.mopac.clone(),
+            };
+            for batch in batches(infiles, self.batch_size) {
+                writeln!(body, "{cmd} {} &> {filename}.out", mop_files(batch))
+                    .unwrap();
+                for f in batch {
+                    writeln!(body, "cat {f}.mop {f}.out >> {filename}.out")
+                        .unwrap();
+                    writeln!(
+                        body,
+                        "echo \"================\" >> {filename}.out"
+                    )
+                    .unwrap();
+                }
+            }
+            writeln!(body, "date +%s >> {filename}.out").unwrap();
         }
-        writeln!(body, "date +%s >> {filename}.out").unwrap();
-        let mut file = File::create(filename).unwrap_or_else(|_| {
-            panic!("failed to create submit script `{filename}`")
-        });
-        write!(file, "{body}").expect("failed to write submit script");
+        write_script(filename, &body, self.options.skip_unchanged_scripts)
     }
 
     fn default_submit_script(&self) -> String {
@@ -104,26 +259,62 @@ impl<P: Program + Clone + Serialize + for<'a> Deserialize<'a>> SubQueue<P>
         &self.dir
     }
 
-    fn stat_cmd(&self) -> String {
-        todo!()
+    /// return the ids of the submit scripts in `self.running` that are still
+    /// running, one per line, after removing the ones that have exited
+    fn stat_cmd(&self) -> Result<String, Error> {
+        let mut running = self.running.lock().unwrap();
+        running.retain(|id, child| match child.try_wait() {
+            Ok(status) => status.is_none(),
+            Err(e) => {
+                log::warn!("failed to check on job {id} with {e}");
+                false
+            }
+        });
+        let mut ids: Vec<_> = running.keys().cloned().collect();
+        ids.sort();
+        Ok(ids.join("\n"))
     }
 
-    fn status(&self) -> HashSet<String> {
-        for dir in ["opt", "pts", "freqs"] {
-            let d = std::fs::read_dir(dir).unwrap();
-            for f in d {
-                eprintln!("contents of {:?}", f.as_ref().unwrap());
-                eprintln!(
-                    "{}",
-                    std::fs::read_to_string(f.unwrap().path()).unwrap()
-                );
-                eprintln!("================");
-            }
-        }
-        panic!("no status available for Local queue");
+    fn status(&self) -> Result<HashSet<String>, Error> {
+        Ok(<Self as SubQueue<P>>::stat_cmd(self)?
+            .lines()
+            .map(str::to_owned)
+            .collect())
     }
 
     fn no_del(&self) -> bool {
         false
     }
+
+    fn retry_budget(&self) -> Option<usize> {
+        self.options.retry_budget
+    }
+
+    fn compress_on_success(&self) -> bool {
+        self.options.compress_on_success
+    }
+
+    fn submit_limit(&self) -> usize {
+        self.options.submit_limit
+    }
+
+    fn shuffle_seed(&self) -> Option<u64> {
+        self.options.shuffle_seed
+    }
+
+    fn start_grace(&self) -> Option<usize> {
+        self.options.start_grace
+    }
+
+    fn adaptive_chunks(&self) -> Option<AdaptiveChunks> {
+        self.options.adaptive_chunks
+    }
+
+    fn chunk_failure(&self) -> Option<ChunkFailure> {
+        self.options.chunk_failure
+    }
+
+    fn stale_tolerance(&self) -> Option<usize> {
+        self.options.stale_tolerance
+    }
 }
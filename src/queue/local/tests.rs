@@ -0,0 +1,240 @@
+use std::{fs::read_to_string, path::Path};
+
+use crate::program::mopac::Mopac;
+use crate::queue::{local::Local, Queue, SubQueue};
+use crate::string;
+
+#[test]
+fn prefix() {
+    let local = Local {
+        prefix: Some("singularity exec image.sif".to_owned()),
+        ..Local::default()
+    };
+    let filename = "/tmp/prefix.slurm";
+    <Local as Queue<Mopac>>::write_submit_script(
+        &local,
+        &string!["inp/job.00000000"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "export LD_LIBRARY_PATH=/opt/mopac/
+singularity exec image.sif /opt/mopac/mopac inp/job.00000000.mop \
+&> /tmp/prefix.slurm.out
+cat inp/job.00000000.mop inp/job.00000000.out >> /tmp/prefix.slurm.out
+echo \"================\" >> /tmp/prefix.slurm.out
+date +%s >> /tmp/prefix.slurm.out
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn direct() {
+    use crate::queue::Submit;
+    let local = Local {
+        mopac: "echo".to_owned(),
+        prefix: Some("env".to_owned()),
+        direct: true,
+        ..Local::default()
+    };
+    let filename = "/tmp/direct.slurm";
+    <Local as Queue<Mopac>>::write_submit_script(
+        &local,
+        &string!["/tmp/direct0", "/tmp/direct1"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert_eq!(got, "/tmp/direct0.mop\n/tmp/direct1.mop\n");
+
+    let id = <Local as Submit<Mopac>>::submit(&local, filename).unwrap();
+    assert!(id.job_id.is_empty());
+    let got = read_to_string("/tmp/direct.slurm.out").unwrap();
+    let want = "/tmp/direct0.mop
+/tmp/direct0.mop: exit status: 0
+================
+/tmp/direct1.mop
+/tmp/direct1.mop: exit status: 0
+================
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+    std::fs::remove_file("/tmp/direct.slurm.out").unwrap();
+}
+
+#[test]
+fn env() {
+    let local = Local {
+        env: vec![("OMP_NUM_THREADS".to_owned(), "1".to_owned())],
+        ..Local::default()
+    };
+    let filename = "/tmp/env.slurm";
+    <Local as Queue<Mopac>>::write_submit_script(
+        &local,
+        &string!["inp/job.00000000"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "export LD_LIBRARY_PATH=/opt/mopac/
+export OMP_NUM_THREADS=1
+/opt/mopac/mopac inp/job.00000000.mop &> /tmp/env.slurm.out
+cat inp/job.00000000.mop inp/job.00000000.out >> /tmp/env.slurm.out
+echo \"================\" >> /tmp/env.slurm.out
+date +%s >> /tmp/env.slurm.out
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn batch() {
+    let local = Local {
+        batch_size: Some(2),
+        ..Local::default()
+    };
+    let filename = "/tmp/batch.slurm";
+    <Local as Queue<Mopac>>::write_submit_script(
+        &local,
+        &string!["inp/job.00000000", "inp/job.00000001", "inp/job.00000002"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "export LD_LIBRARY_PATH=/opt/mopac/
+/opt/mopac/mopac inp/job.00000000.mop inp/job.00000001.mop \
+&> /tmp/batch.slurm.out
+cat inp/job.00000000.mop inp/job.00000000.out >> /tmp/batch.slurm.out
+echo \"================\" >> /tmp/batch.slurm.out
+cat inp/job.00000001.mop inp/job.00000001.out >> /tmp/batch.slurm.out
+echo \"================\" >> /tmp/batch.slurm.out
+/opt/mopac/mopac inp/job.00000002.mop &> /tmp/batch.slurm.out
+cat inp/job.00000002.mop inp/job.00000002.out >> /tmp/batch.slurm.out
+echo \"================\" >> /tmp/batch.slurm.out
+date +%s >> /tmp/batch.slurm.out
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn direct_batch() {
+    use crate::queue::Submit;
+    let local = Local {
+        mopac: "echo".to_owned(),
+        direct: true,
+        batch_size: Some(2),
+        ..Local::default()
+    };
+    let filename = "/tmp/direct_batch.slurm";
+    <Local as Queue<Mopac>>::write_submit_script(
+        &local,
+        &string!["/tmp/batch0", "/tmp/batch1", "/tmp/batch2"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert_eq!(got, "/tmp/batch0.mop /tmp/batch1.mop\n/tmp/batch2.mop\n");
+
+    <Local as Submit<Mopac>>::submit(&local, filename).unwrap();
+    let got = read_to_string("/tmp/direct_batch.slurm.out").unwrap();
+    let want = "/tmp/batch0.mop /tmp/batch1.mop
+/tmp/batch0.mop /tmp/batch1.mop: exit status: 0
+================
+/tmp/batch2.mop
+/tmp/batch2.mop: exit status: 0
+================
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+    std::fs::remove_file("/tmp/direct_batch.slurm.out").unwrap();
+}
+
+#[test]
+fn in_temp_dir() {
+    let local = Local::default().in_temp_dir().unwrap();
+    let dir = <Local as SubQueue<Mopac>>::dir(&local).to_owned();
+    assert!(Path::new(&dir).is_dir());
+    let filename = format!("{dir}/main0.slurm");
+    <Local as Queue<Mopac>>::write_submit_script(
+        &local,
+        &string![format!("{dir}/job.00000000")],
+        &filename,
+    )
+    .unwrap();
+    assert!(Path::new(&filename).exists());
+    drop(local);
+    assert!(!Path::new(&dir).exists());
+}
+
+#[test]
+fn skip_unchanged_scripts() {
+    use std::time::SystemTime;
+    let mut local = Local::default();
+    local.options.skip_unchanged_scripts = true;
+    let filename = "/tmp/skip_unchanged.slurm";
+    let write = |local: &Local| {
+        <Local as Queue<Mopac>>::write_submit_script(
+            local,
+            &string!["inp/job.00000000"],
+            filename,
+        )
+        .unwrap();
+        std::fs::metadata(filename).unwrap().modified().unwrap()
+    };
+    write(&local);
+    let reset = || {
+        std::fs::File::options()
+            .write(true)
+            .open(filename)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap()
+    };
+
+    // identical contents are not rewritten
+    reset();
+    assert_eq!(write(&local), SystemTime::UNIX_EPOCH);
+
+    // but changed ones are
+    local.mopac = "mopac".to_owned();
+    assert_ne!(write(&local), SystemTime::UNIX_EPOCH);
+
+    // and everything is rewritten by default
+    local.options.skip_unchanged_scripts = false;
+    reset();
+    assert_ne!(write(&local), SystemTime::UNIX_EPOCH);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn status() {
+    use crate::queue::Submit;
+    let local = Local::default();
+    let filename = "/tmp/local_status.slurm";
+    let done = "/tmp/local_status.done";
+    let _ = std::fs::remove_file(done);
+    std::fs::write(
+        filename,
+        format!("while [ ! -e {done} ]; do sleep 0.05; done\n"),
+    )
+    .unwrap();
+    let id = <Local as Submit<Mopac>>::submit(&local, filename).unwrap();
+    assert!(!id.job_id.is_empty());
+    let status = <Local as SubQueue<Mopac>>::status(&local).unwrap();
+    assert!(status.contains(&id.job_id));
+
+    std::fs::write(done, "").unwrap();
+    let mut status = status;
+    for _ in 0..100 {
+        status = <Local as SubQueue<Mopac>>::status(&local).unwrap();
+        if status.is_empty() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(status.is_empty());
+    std::fs::remove_file(filename).unwrap();
+    std::fs::remove_file(done).unwrap();
+}
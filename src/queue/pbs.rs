@@ -1,17 +1,65 @@
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-use std::time::Duration;
-use std::{collections::HashSet, process::Command};
+use std::path::{Path, PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    process::Command,
+};
 
 use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
 
 use crate::program::molpro::Molpro;
 use crate::program::mopac::Mopac;
 use crate::program::Program;
 use crate::queue::Queue;
+use crate::Error;
 
-use super::{SubQueue, Submit};
+use super::{
+    batches, insert_directives, mop_files, push_exports, push_modules,
+    status_output, submit_inner, temp_dir, write_script, AdaptiveChunks,
+    ChunkFailure, JobState, KeepScratch, Module, QueueOptions, SubQueue,
+    Submission, Submit, SubmitError, Throttle,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The resources requested by the `#PBS -l` and `-q` lines of the default
+/// submit scripts, substituted for the `{{.ncpus}}`, `{{.mem}}`,
+/// `{{.walltime}}`, and `{{.queue}}` placeholders. These are also replaced in
+/// a user-supplied template wherever they appear
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resources {
+    pub ncpus: usize,
+
+    /// the memory per job, like `8gb`. `None` uses 8gb for Molpro and 1gb
+    /// for MOPAC
+    pub mem: Option<String>,
+
+    pub walltime: String,
+    pub queue: String,
+}
+
+impl Default for Resources {
+    fn default() -> Self {
+        Self {
+            ncpus: 1,
+            mem: None,
+            walltime: "1000:00:00".to_owned(),
+            queue: "workq".to_owned(),
+        }
+    }
+}
+
+impl Resources {
+    /// replace the resource placeholders in `body`, using `mem` when
+    /// `self.mem` is `None`
+    fn substitute(&self, body: &str, mem: &str) -> String {
+        body.replace("{{.ncpus}}", &self.ncpus.to_string())
+            .replace("{{.mem}}", self.mem.as_deref().unwrap_or(mem))
+            .replace("{{.walltime}}", &self.walltime)
+            .replace("{{.queue}}", &self.queue)
+    }
+}
 
 /// Pbs is a type for holding the information for submitting a pbs job.
 /// `filename` is the name of the Pbs submission script
@@ -20,9 +68,88 @@ pub struct Pbs {
     chunk_size: usize,
     job_limit: usize,
     sleep_int: usize,
-    dir: &'static str,
+    dir: String,
     no_del: bool,
     template: Option<String>,
+    resources: Resources,
+
+    /// the options shared with the other queue types. defaults to
+    /// [QueueOptions::default]
+    pub options: QueueOptions,
+
+    /// slow down polling when the scheduler is slow to respond. defaults to
+    /// `None`, polling at the usual interval
+    pub throttle: Option<Throttle>,
+
+    /// the maximum number of finished files waiting to be deleted or
+    /// compressed at once. defaults to `None`, meaning no limit
+    pub dump_capacity: Option<usize>,
+
+    /// an existing directory to move the files of successfully-parsed jobs
+    /// into instead of deleting them. defaults to `None`, deleting them
+    pub trash_dir: Option<PathBuf>,
+
+    /// additional `#PBS` directives to emit verbatim after the ones already in
+    /// the submit script. defaults to empty
+    pub extra_directives: Vec<String>,
+
+    /// files in `$TMPDIR` to copy back to the job directory after each Molpro
+    /// run, before the scratch directory is removed. shell globs are allowed.
+    /// defaults to empty
+    pub copy_back: Vec<String>,
+
+    /// when to skip the `rm -rf $TMPDIR` line at the end of Molpro submit
+    /// scripts, leaving their scratch files for restarting or debugging.
+    /// defaults to [KeepScratch::Never], always removing them
+    pub keep_scratch: KeepScratch,
+
+    /// environment variables to export in each submit script before running
+    /// the jobs, such as `OMP_NUM_THREADS`. defaults to empty
+    pub env: Vec<(String, String)>,
+
+    /// the maximum number of input files to pass to a single `mopac`
+    /// invocation in MOPAC submit scripts, avoiding its startup cost for each
+    /// job. MOPAC still writes a separate output file for each input. defaults
+    /// to `None`, running `mopac` once per input file. Molpro scripts are
+    /// unaffected
+    pub batch_size: Option<usize>,
+
+    /// environment modules to load in each submit script, in order, after any
+    /// loaded by the template. defaults to empty
+    pub modules: Vec<Module>,
+
+    /// emit `module purge` before loading `modules`, for a clean environment.
+    /// defaults to false
+    pub purge_modules: bool,
+
+    /// the temporary directory created by [Self::in_temp_dir], which is
+    /// removed along with all of its files when `self` is dropped. defaults to
+    /// `None`, leaving the files in `dir`
+    pub temp_dir: Option<TempDir>,
+
+    /// the number of times to retry a failed submission before giving up with
+    /// a [SubmitError]. defaults to 5
+    pub max_retries: usize,
+
+    /// the number of seconds to wait between submission attempts. defaults
+    /// to `None`, using `sleep_int`
+    pub retry_interval: Option<usize>,
+
+    /// double the wait after each failed submission attempt, starting from
+    /// `retry_interval`. defaults to false, waiting the same time between
+    /// every attempt
+    pub retry_backoff: bool,
+
+    /// the `mopac` binary to run in MOPAC submit scripts. defaults to the
+    /// value of the `MOPAC` environment variable if it is set, and otherwise
+    /// to `mopac`, to be found on the `PATH` of the job
+    pub mopac: String,
+
+    /// submit each batch of chunks as a single array job with `#PBS -J`,
+    /// dispatching the chunk scripts by `$PBS_ARRAY_INDEX`, to reduce the
+    /// load on the scheduler. defaults to false, submitting each chunk
+    /// separately
+    pub array_jobs: bool,
 }
 
 impl Pbs {
@@ -30,18 +157,92 @@ impl Pbs {
         chunk_size: usize,
         job_limit: usize,
         sleep_int: usize,
-        dir: &'static str,
+        dir: &str,
         no_del: bool,
         template: Option<String>,
+        resources: Resources,
     ) -> Self {
         Self {
             chunk_size,
             job_limit,
             sleep_int,
-            dir,
+            dir: dir.to_owned(),
             no_del,
             template,
+            resources,
+            options: QueueOptions::default(),
+            throttle: None,
+            dump_capacity: None,
+            trash_dir: None,
+            extra_directives: Vec::new(),
+            copy_back: Vec::new(),
+            keep_scratch: KeepScratch::Never,
+            env: Vec::new(),
+            batch_size: None,
+            modules: Vec::new(),
+            purge_modules: false,
+            temp_dir: None,
+            max_retries: 5,
+            retry_interval: None,
+            retry_backoff: false,
+            mopac: std::env::var("MOPAC")
+                .unwrap_or_else(|_| "mopac".to_owned()),
+            array_jobs: false,
+        }
+    }
+
+    /// write the array job script `filename` running each of `scripts` as
+    /// one element. the `#PBS` directives are taken from `template`, with
+    /// `mem` as the default [Resources::mem], followed by `extra_directives`
+    /// and the `-J` range. each element changes to `$PBS_O_WORKDIR` before
+    /// running its script, since the paths in `scripts` are relative to the
+    /// submission directory
+    fn write_array(
+        &self,
+        template: &str,
+        mem: &str,
+        scripts: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
+        use std::fmt::Write;
+        if scripts.is_empty() {
+            return Err(Error::Submit(format!(
+                "no chunk scripts to run in array job {filename}"
+            )));
+        }
+        let basename = Path::new(filename).file_name().unwrap();
+        let header: String = template
+            .lines()
+            .filter(|l| l.starts_with("#!") || l.starts_with("#PBS"))
+            .map(|l| format!("{l}\n"))
+            .collect();
+        let header = self
+            .resources
+            .substitute(&header, mem)
+            .replace("{{.basename}}", basename.to_str().unwrap())
+            .replace("{{.filename}}", filename);
+        let mut directives = self.extra_directives.clone();
+        directives.push(format!("#PBS -J 0-{}", scripts.len() - 1));
+        let mut body = insert_directives(&header, "#PBS", &directives)?;
+        body.push_str("\nscripts=(\n");
+        for script in scripts {
+            writeln!(body, "{script:?}").unwrap();
         }
+        body.push_str(")\ncd $PBS_O_WORKDIR\n");
+        body.push_str("bash \"${scripts[$PBS_ARRAY_INDEX]}\"\n");
+        write_script(filename, &body, self.options.skip_unchanged_scripts)
+    }
+
+    /// run the whole campaign in a new temporary directory instead of `dir`,
+    /// for transient runs like tests where none of the files are needed
+    /// afterward. the directory is removed when `self` is dropped, including
+    /// while unwinding from a panic, so the job filenames should be built from
+    /// [SubQueue::dir] rather than the original `dir`
+    pub fn in_temp_dir(mut self) -> std::io::Result<Self> {
+        let (dir, tmp) = temp_dir()?;
+        self.dir = dir;
+        self.temp_dir = Some(tmp);
+        Ok(self)
     }
 }
 
@@ -50,11 +251,18 @@ where
     Mopac: Serialize + for<'a> Deserialize<'a>,
 {
     /// submit `filename` to the queue and return the jobid
-    fn submit(&self, filename: &str) -> String {
+    fn submit(&self, filename: &str) -> Result<Submission, SubmitError> {
         let mut cmd =
             Command::new(<Self as SubQueue<Mopac>>::submit_command(self));
         let cmd = cmd.arg("-f").arg(filename);
-        submit_inner(cmd, self.sleep_int).unwrap()
+        let job_id = submit_inner(
+            cmd,
+            filename,
+            self.max_retries,
+            self.retry_interval.unwrap_or(self.sleep_int),
+            self.retry_backoff,
+        )?;
+        Ok(Submission::new(job_id, filename))
     }
 }
 
@@ -64,52 +272,81 @@ impl Submit<Molpro> for Pbs
 where
     Molpro: Serialize + for<'a> Deserialize<'a>,
 {
-    fn submit(&self, filename: &str) -> String {
+    fn submit(&self, filename: &str) -> Result<Submission, SubmitError> {
         let path = Path::new(filename);
         let dir = path.parent().unwrap();
         let base = path.file_name().unwrap();
         let mut cmd =
             Command::new(<Self as SubQueue<Molpro>>::submit_command(self));
         let cmd = cmd.arg(base).current_dir(dir);
-        submit_inner(cmd, self.sleep_int).unwrap()
+        let job_id = submit_inner(
+            cmd,
+            filename,
+            self.max_retries,
+            self.retry_interval.unwrap_or(self.sleep_int),
+            self.retry_backoff,
+        )?;
+        Ok(Submission::new(job_id, filename))
     }
 }
 
-/// helper function to consolidate error handling between the two submit
-/// implementations
-fn submit_inner(
-    cmd: &mut Command,
-    sleep_int: usize,
-) -> std::io::Result<String> {
-    let mut retries = 5;
-    loop {
-        match cmd.output() {
-            Ok(s) => {
-                if !s.status.success() {
-                    if retries > 0 {
-                        eprintln!(
-                            "qsub failed with output: {s:#?}, \
-				   retrying {retries} more times"
-                        );
-                        retries -= 1;
-                        std::thread::sleep(Duration::from_secs(
-                            sleep_int as u64,
-                        ));
-                        continue;
-                    }
-                    panic!("qsub failed with output: {s:#?}");
+/// parse the job ids, and their states if the state column can be found, from
+/// the output of `qstat`. the columns are located from the dashed line under
+/// the header, so a line with an empty or wrapped field can still be read by
+/// position. lines without a job id, starting with a digit, are skipped with a
+/// warning rather than aborting the run
+fn parse_qstat(output: &str) -> Vec<(String, Option<String>)> {
+    let lines: Vec<_> = output.lines().collect();
+    let sep = lines.iter().position(|l| {
+        l.contains("---") && l.chars().all(|c| c == '-' || c == ' ')
+    });
+    // the byte ranges of each column, from the runs of dashes
+    let mut spans = Vec::new();
+    if let Some(sep) = sep {
+        let mut start = None;
+        for (i, c) in lines[sep].char_indices().chain([(lines[sep].len(), ' ')])
+        {
+            match (c, start) {
+                ('-', None) => start = Some(i),
+                (' ', Some(s)) => {
+                    spans.push(s..i);
+                    start = None;
                 }
-                let raw =
-                    std::str::from_utf8(&s.stdout).unwrap().trim().to_string();
-                return Ok(raw
-                    .split_whitespace()
-                    .last()
-                    .unwrap_or("no jobid")
-                    .to_string());
+                _ => {}
+            }
+        }
+    }
+    let state_col = sep.and_then(|sep| {
+        let header = lines[..sep].last()?;
+        spans
+            .iter()
+            .position(|s| header.get(s.clone()).map(str::trim) == Some("S"))
+    });
+    let mut ret = Vec::new();
+    for line in &lines[sep.map_or(0, |s| s + 1)..] {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let Some(id) = fields.first() else {
+            continue;
+        };
+        if !id.starts_with(|c: char| c.is_ascii_digit()) {
+            if sep.is_some() {
+                log::warn!("skipping malformed qstat line `{line}`");
             }
-            Err(e) => return Err(e),
+            continue;
         }
+        // prefer the fields of a complete line, but fall back on the column
+        // position when one is empty or wrapped
+        let state = state_col.and_then(|col| {
+            let s = if fields.len() == spans.len() {
+                fields[col]
+            } else {
+                line.get(spans[col].clone())?.trim()
+            };
+            (!s.is_empty()).then(|| s.to_owned())
+        });
+        ret.push((id.to_string(), state));
     }
+    ret
 }
 
 impl Queue<Molpro> for Pbs
@@ -118,7 +355,11 @@ where
 {
     /// An example of `self.template` should look like
     ///
-    fn write_submit_script(&self, infiles: &[String], filename: &str) {
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
         let path = Path::new(filename);
         let basename = path.file_name().unwrap();
         let mut body = self
@@ -128,27 +369,65 @@ where
                 <Self as Queue<Molpro>>::default_submit_script(self)
             })
             .replace("{{.basename}}", basename.to_str().unwrap());
+        body = self.resources.substitute(&body, "8gb");
+        body = insert_directives(&body, "#PBS", &self.extra_directives)?;
+        push_modules(&mut body, &self.modules, self.purge_modules)?;
+        push_exports(&mut body, &self.env);
         {
             use std::fmt::Write;
+            // record failures for the final cleanup, since $? only holds
+            // the status of the last job
+            let on_failure = match self.keep_scratch {
+                KeepScratch::OnFailure => " || failed=1",
+                _ => "",
+            };
             for f in infiles {
                 let basename = Path::new(f).file_name().unwrap();
                 writeln!(
                     body,
-                    "molpro -t $NCPUS --no-xml-output {basename:?}.inp"
+                    "molpro -t $NCPUS --no-xml-output {basename:?}.inp\
+                     {on_failure}"
                 )
                 .unwrap();
             }
-            writeln!(body, "rm -rf $TMPDIR").unwrap();
-        }
-        let mut file = match File::create(filename) {
-            Ok(f) => f,
-            Err(_) => {
-                panic!("write_submit_script: failed to create {filename}");
+            for f in &self.copy_back {
+                writeln!(body, "cp -r $TMPDIR/{f} .").unwrap();
             }
-        };
-        write!(file, "{body}").unwrap_or_else(|_| {
-            panic!("failed to write molpro input file: {filename}")
+            match self.keep_scratch {
+                KeepScratch::Never => {
+                    writeln!(body, "rm -rf $TMPDIR").unwrap();
+                }
+                KeepScratch::OnFailure => {
+                    writeln!(
+                        body,
+                        "if [ -z \"$failed\" ]; then rm -rf $TMPDIR; fi"
+                    )
+                    .unwrap();
+                }
+                KeepScratch::Always => {}
+            }
+        }
+        write_script(filename, &body, self.options.skip_unchanged_scripts)
+    }
+
+    /// the scripts are run by their basenames since Molpro jobs are submitted
+    /// from the directory containing them
+    fn write_array_script(
+        &self,
+        scripts: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
+        let template = self.template.clone().unwrap_or_else(|| {
+            <Self as Queue<Molpro>>::default_submit_script(self)
         });
+        let scripts: Vec<_> = scripts
+            .iter()
+            .map(|s| {
+                let base = Path::new(s).file_name().unwrap();
+                base.to_str().unwrap().to_owned()
+            })
+            .collect();
+        self.write_array(&template, "8gb", &scripts, filename)
     }
 
     fn default_submit_script(&self) -> String {
@@ -158,10 +437,10 @@ where
 #PBS -j oe
 #PBS -o {{.basename}}.out
 #PBS -W umask=022
-#PBS -l walltime=1000:00:00
-#PBS -l ncpus=1
-#PBS -l mem=8gb
-#PBS -q workq
+#PBS -l walltime={{.walltime}}
+#PBS -l ncpus={{.ncpus}}
+#PBS -l mem={{.mem}}
+#PBS -q {{.queue}}
 
 module load openpbs molpro
 
@@ -177,7 +456,11 @@ mkdir -p $TMPDIR
 impl Queue<Mopac> for Pbs {
     /// An example of `self.template` should look like
     ///
-    fn write_submit_script(&self, infiles: &[String], filename: &str) {
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
         let path = Path::new(filename);
         let basename = path.file_name().unwrap();
         let mut body = self
@@ -188,19 +471,25 @@ impl Queue<Mopac> for Pbs {
             })
             .replace("{{.basename}}", basename.to_str().unwrap())
             .replace("{{.filename}}", filename);
-        for f in infiles {
-            body.push_str(&format!(
-                "/ddn/home1/r2518/Packages/mopac/build/mopac {f}.mop\n"
-            ));
+        body = self.resources.substitute(&body, "1gb");
+        body = insert_directives(&body, "#PBS", &self.extra_directives)?;
+        push_modules(&mut body, &self.modules, self.purge_modules)?;
+        push_exports(&mut body, &self.env);
+        for batch in batches(infiles, self.batch_size) {
+            body.push_str(&format!("{} {}\n", self.mopac, mop_files(batch)));
         }
-        let mut file = match File::create(filename) {
-            Ok(f) => f,
-            Err(_) => {
-                eprintln!("write_submit_script: failed to create {filename}");
-                std::process::exit(1);
-            }
-        };
-        write!(file, "{body}").expect("failed to write params file");
+        write_script(filename, &body, self.options.skip_unchanged_scripts)
+    }
+
+    fn write_array_script(
+        &self,
+        scripts: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
+        let template = self.template.clone().unwrap_or_else(|| {
+            <Self as Queue<Mopac>>::default_submit_script(self)
+        });
+        self.write_array(&template, "1gb", scripts, filename)
     }
 
     fn default_submit_script(&self) -> String {
@@ -210,10 +499,10 @@ impl Queue<Mopac> for Pbs {
 #PBS -j oe
 #PBS -o {{.filename}}.out
 #PBS -W umask=022
-#PBS -l walltime=1000:00:00
-#PBS -l ncpus=1
-#PBS -l mem=1gb
-#PBS -q workq
+#PBS -l walltime={{.walltime}}
+#PBS -l ncpus={{.ncpus}}
+#PBS -l mem={{.mem}}
+#PBS -q {{.queue}}
 
 module load openpbs
 
@@ -248,7 +537,7 @@ where
     const SCRIPT_EXT: &'static str = "pbs";
 
     fn dir(&self) -> &str {
-        self.dir
+        &self.dir
     }
 
     /// run `qstat -u $USER`. form of the output is:
@@ -258,33 +547,92 @@ where
     /// Job ID          Username Queue    Jobname    SessID NDS TSK Memory Time  S Time
     /// --------------- -------- -------- ---------- ------ --- --- ------ ----- - -----
     /// 819446          user     queue    C6HNpts      5085   1   1    8gb 26784 R 00:00
-    fn stat_cmd(&self) -> String {
-        let user = std::env::vars()
-            .find(|x| x.0 == "USER")
-            .expect("couldn't find $USER env var");
-        let status = match Command::new("qstat").args(["-u", &user.1]).output()
-        {
-            Ok(status) => status,
-            Err(e) => panic!("failed to run squeue with {e}"),
-        };
-        String::from_utf8(status.stdout)
-            .expect("failed to convert squeue output to String")
-    }
-
-    fn status(&self) -> HashSet<String> {
-        let mut ret = HashSet::new();
-        let lines = <Pbs as SubQueue<P>>::stat_cmd(self);
-        // skip to end of header
-        let lines = lines.lines().skip_while(|l| !l.contains("-----------"));
-        for line in lines {
-            let fields: Vec<_> = line.split_whitespace().collect();
-            assert!(fields.len() == 11);
-            ret.insert(fields[0].to_string());
-        }
-        ret
+    fn stat_cmd(&self) -> Result<String, Error> {
+        let user = std::env::var("USER").map_err(|_| {
+            Error::Scheduler("couldn't find $USER env var".to_owned())
+        })?;
+        let status = status_output("qstat", &["-u", &user]).map_err(|e| {
+            Error::Scheduler(format!("failed to run qstat with {e}"))
+        })?;
+        String::from_utf8(status).map_err(|_| {
+            Error::Scheduler("failed to convert qstat output to String".into())
+        })
+    }
+
+    fn status(&self) -> Result<HashSet<String>, Error> {
+        let lines = <Pbs as SubQueue<P>>::stat_cmd(self)?;
+        Ok(parse_qstat(&lines).into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn status_detailed(&self) -> Result<HashMap<String, JobState>, Error> {
+        let lines = <Pbs as SubQueue<P>>::stat_cmd(self)?;
+        Ok(parse_qstat(&lines)
+            .into_iter()
+            .map(|(id, state)| {
+                let state =
+                    state.map_or(JobState::Unknown, |s| JobState::from_pbs(&s));
+                (id, state)
+            })
+            .collect())
+    }
+
+    fn delete_command(&self) -> Option<&str> {
+        Some("qdel")
     }
 
     fn no_del(&self) -> bool {
         self.no_del
     }
+
+    fn retry_budget(&self) -> Option<usize> {
+        self.options.retry_budget
+    }
+
+    fn compress_on_success(&self) -> bool {
+        self.options.compress_on_success
+    }
+
+    fn submit_limit(&self) -> usize {
+        self.options.submit_limit
+    }
+
+    fn shuffle_seed(&self) -> Option<u64> {
+        self.options.shuffle_seed
+    }
+
+    fn start_grace(&self) -> Option<usize> {
+        self.options.start_grace
+    }
+
+    fn adaptive_chunks(&self) -> Option<AdaptiveChunks> {
+        self.options.adaptive_chunks
+    }
+
+    fn chunk_failure(&self) -> Option<ChunkFailure> {
+        self.options.chunk_failure
+    }
+
+    fn stale_tolerance(&self) -> Option<usize> {
+        self.options.stale_tolerance
+    }
+
+    fn throttle(&self) -> Option<Throttle> {
+        self.throttle
+    }
+
+    fn ncpus(&self) -> usize {
+        self.resources.ncpus
+    }
+
+    fn dump_capacity(&self) -> Option<usize> {
+        self.dump_capacity
+    }
+
+    fn trash_dir(&self) -> Option<PathBuf> {
+        self.trash_dir.clone()
+    }
+
+    fn array_jobs(&self) -> bool {
+        self.array_jobs
+    }
 }
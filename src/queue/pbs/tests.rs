@@ -0,0 +1,378 @@
+use std::fs::read_to_string;
+
+use crate::program::molpro::Molpro;
+use crate::queue::{
+    pbs::{Pbs, Resources},
+    KeepScratch, Module, Queue, SubQueue,
+};
+use crate::{string, Error};
+
+fn test_pbs() -> Pbs {
+    Pbs::new(
+        128,
+        1600,
+        1,
+        "inp",
+        false,
+        Some(String::new()),
+        Resources::default(),
+    )
+}
+
+#[test]
+fn copy_back() {
+    let mut pbs = test_pbs();
+    pbs.copy_back = string!["*.wfu", "orbs.dat"];
+    let filename = "/tmp/copy_back.pbs";
+    <Pbs as Queue<Molpro>>::write_submit_script(
+        &pbs,
+        &string!["inp/job.00000000"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "molpro -t $NCPUS --no-xml-output \"job.00000000\".inp
+cp -r $TMPDIR/*.wfu .
+cp -r $TMPDIR/orbs.dat .
+rm -rf $TMPDIR
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn extra_directives() {
+    use crate::program::mopac::Mopac;
+    let mut pbs =
+        Pbs::new(128, 1600, 1, "inp", false, None, Resources::default());
+    pbs.extra_directives = string!["#PBS -l place=scatter"];
+    let filename = "/tmp/extra_directives.pbs";
+    <Pbs as Queue<Mopac>>::write_submit_script(&pbs, &[], filename).unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.contains("#PBS -q workq\n#PBS -l place=scatter\n\nmodule"));
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn bad_extra_directive() {
+    use crate::program::mopac::Mopac;
+    let mut pbs = test_pbs();
+    pbs.extra_directives = string!["--exclusive"];
+    let got = <Pbs as Queue<Mopac>>::write_submit_script(
+        &pbs,
+        &[],
+        "/tmp/bad_extra_directive.pbs",
+    );
+    assert!(matches!(got, Err(Error::Input(_))));
+}
+
+#[test]
+fn keep_scratch() {
+    let mut pbs = test_pbs();
+    pbs.keep_scratch = KeepScratch::Always;
+    let filename = "/tmp/keep_scratch.pbs";
+    <Pbs as Queue<Molpro>>::write_submit_script(
+        &pbs,
+        &string!["inp/job.00000000"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(!got.contains("rm -rf"));
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn keep_failed_scratch() {
+    let mut pbs = test_pbs();
+    pbs.keep_scratch = KeepScratch::OnFailure;
+    let filename = "/tmp/keep_failed_scratch.pbs";
+    <Pbs as Queue<Molpro>>::write_submit_script(
+        &pbs,
+        &string!["inp/job.00000000", "inp/job.00000001"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want =
+        "molpro -t $NCPUS --no-xml-output \"job.00000000\".inp || failed=1
+molpro -t $NCPUS --no-xml-output \"job.00000001\".inp || failed=1
+if [ -z \"$failed\" ]; then rm -rf $TMPDIR; fi
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn modules() {
+    let mut pbs = test_pbs();
+    pbs.modules = vec![
+        Module::new("openpbs"),
+        Module::with_version("molpro", "2022.3"),
+    ];
+    pbs.purge_modules = true;
+    pbs.env = vec![("OMP_NUM_THREADS".to_owned(), "1".to_owned())];
+    let filename = "/tmp/modules.pbs";
+    <Pbs as Queue<Molpro>>::write_submit_script(
+        &pbs,
+        &string!["inp/job.00000000"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "module purge
+module load openpbs molpro/2022.3
+export OMP_NUM_THREADS=1
+molpro -t $NCPUS --no-xml-output \"job.00000000\".inp
+rm -rf $TMPDIR
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn bad_module_version() {
+    let mut pbs = test_pbs();
+    pbs.modules = vec![Module::with_version("molpro", "2022.3; rm -rf ~")];
+    let got = <Pbs as Queue<Molpro>>::write_submit_script(
+        &pbs,
+        &[],
+        "/tmp/bad_module_version.pbs",
+    );
+    assert!(matches!(got, Err(Error::Input(_))));
+}
+
+#[test]
+fn env() {
+    let mut pbs = test_pbs();
+    pbs.env =
+        vec![("MOLPRO_OPTIONS".to_owned(), "--no-helper-server".to_owned())];
+    let filename = "/tmp/env.pbs";
+    <Pbs as Queue<Molpro>>::write_submit_script(
+        &pbs,
+        &string!["inp/job.00000000"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "export MOLPRO_OPTIONS=--no-helper-server
+molpro -t $NCPUS --no-xml-output \"job.00000000\".inp
+rm -rf $TMPDIR
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn batch() {
+    use crate::program::mopac::Mopac;
+    let mut pbs = test_pbs();
+    pbs.batch_size = Some(2);
+    pbs.mopac = "/opt/mopac/mopac".to_owned();
+    let filename = "/tmp/batch.pbs";
+    <Pbs as Queue<Mopac>>::write_submit_script(
+        &pbs,
+        &string!["inp/job.00000000", "inp/job.00000001", "inp/job.00000002"],
+        filename,
+    )
+    .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = "/opt/mopac/mopac inp/job.00000000.mop inp/job.00000001.mop
+/opt/mopac/mopac inp/job.00000002.mop
+";
+    assert_eq!(got, want);
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn submit_error() {
+    use crate::queue::SubmitError;
+    let mut cmd = std::process::Command::new("sh");
+    cmd.args(["-c", "echo out; echo 'queue is full' >&2; exit 3"]);
+    let got = crate::queue::submit_inner(&mut cmd, "main0.pbs", 5, 0, false)
+        .unwrap_err();
+    let want = SubmitError {
+        script: "main0.pbs".to_owned(),
+        command: "sh -c echo out; echo 'queue is full' >&2; exit 3".to_owned(),
+        status: Some(3),
+        stdout: "out\n".to_owned(),
+        stderr: "queue is full\n".to_owned(),
+    };
+    assert_eq!(got, want);
+    assert_eq!(
+        got.to_string(),
+        "failed to submit main0.pbs with exit status 3: queue is full"
+    );
+
+    let mut cmd = std::process::Command::new("/nonexistent/qsub");
+    let got = crate::queue::submit_inner(&mut cmd, "main0.pbs", 5, 0, false)
+        .unwrap_err();
+    assert_eq!(got.status, None);
+    assert!(matches!(crate::Error::from(got), crate::Error::Submit(_)));
+}
+
+#[test]
+fn max_retries() {
+    let log = "/tmp/psqs_max_retries.log";
+    let attempts = |retries| {
+        let _ = std::fs::remove_file(log);
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", &format!("echo x >> {log}; exit 1")]);
+        crate::queue::submit_inner(&mut cmd, "main0.pbs", retries, 0, true)
+            .unwrap_err();
+        read_to_string(log).unwrap().lines().count()
+    };
+    assert_eq!(attempts(0), 1);
+    assert_eq!(attempts(2), 3);
+    std::fs::remove_file(log).unwrap();
+
+    let pbs = test_pbs();
+    assert_eq!(pbs.max_retries, 5);
+    assert_eq!(pbs.retry_interval, None);
+    assert!(!pbs.retry_backoff);
+}
+
+#[test]
+fn parse_qstat() {
+    let output = "
+maple:
+                                                            Req'd  Req'd   Elap
+Job ID          Username Queue    Jobname    SessID NDS TSK Memory Time  S Time
+--------------- -------- -------- ---------- ------ --- --- ------ ----- - -----
+819446          user     queue    C6HNpts      5085   1   1    8gb 26784 R 00:00
+819447          user     queue                 5086   1   1    8gb 26784 Q   --
+819448.maple    user     queue    main0.pbs      --   1   1    8gb 26784 H   --
+garbage
+";
+    let got = super::parse_qstat(output);
+    let want = vec![
+        ("819446".to_owned(), Some("R".to_owned())),
+        ("819447".to_owned(), Some("Q".to_owned())),
+        ("819448.maple".to_owned(), Some("H".to_owned())),
+    ];
+    assert_eq!(got, want);
+
+    // no header to find the state column from, but the ids are still read
+    let got = super::parse_qstat("819446 user queue C6HNpts R\n");
+    assert_eq!(got, vec![("819446".to_owned(), None)]);
+    assert!(super::parse_qstat("").is_empty());
+}
+
+#[test]
+fn job_state() {
+    use crate::queue::JobState;
+    let states: Vec<_> = ["R", "Q", "H", "E", "W"]
+        .into_iter()
+        .map(JobState::from_pbs)
+        .collect();
+    assert_eq!(
+        states,
+        vec![
+            JobState::Running,
+            JobState::Queued,
+            JobState::Held,
+            JobState::Exiting,
+            JobState::Other("W".to_owned()),
+        ]
+    );
+    let terminal: Vec<_> = states.iter().map(JobState::is_terminal).collect();
+    assert_eq!(terminal, vec![false, false, true, false, false]);
+    assert!(!JobState::Unknown.is_terminal());
+}
+
+#[test]
+fn resources() {
+    use crate::program::mopac::Mopac;
+    let filename = "/tmp/resources.pbs";
+    let pbs = Pbs::new(128, 1600, 1, "inp", false, None, Resources::default());
+    <Pbs as Queue<Molpro>>::write_submit_script(&pbs, &[], filename).unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.contains(
+        "#PBS -l walltime=1000:00:00
+#PBS -l ncpus=1
+#PBS -l mem=8gb
+#PBS -q workq
+"
+    ));
+    <Pbs as Queue<Mopac>>::write_submit_script(&pbs, &[], filename).unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.contains("#PBS -l ncpus=1\n#PBS -l mem=1gb\n"));
+
+    let resources = Resources {
+        ncpus: 8,
+        mem: Some("32gb".to_owned()),
+        walltime: "48:00:00".to_owned(),
+        queue: "long".to_owned(),
+    };
+    let pbs = Pbs::new(128, 1600, 1, "inp", false, None, resources.clone());
+    assert_eq!(<Pbs as SubQueue<Mopac>>::ncpus(&pbs), 8);
+    <Pbs as Queue<Mopac>>::write_submit_script(&pbs, &[], filename).unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.contains(
+        "#PBS -l walltime=48:00:00
+#PBS -l ncpus=8
+#PBS -l mem=32gb
+#PBS -q long
+"
+    ));
+
+    let template = "#PBS -l select=1:ncpus={{.ncpus}}:mem={{.mem}}\n";
+    let pbs = Pbs::new(
+        128,
+        1600,
+        1,
+        "inp",
+        false,
+        Some(template.to_owned()),
+        resources,
+    );
+    <Pbs as Queue<Mopac>>::write_submit_script(&pbs, &[], filename).unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.starts_with("#PBS -l select=1:ncpus=8:mem=32gb\n"));
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn array_script() {
+    use crate::program::mopac::Mopac;
+    let mut pbs =
+        Pbs::new(128, 1600, 1, "inp", false, None, Resources::default());
+    pbs.extra_directives = string!["#PBS -l place=scatter"];
+    let filename = "/tmp/array_script.pbs";
+    let scripts = string!["inp/main0.pbs", "inp/main1.pbs"];
+    <Pbs as Queue<Mopac>>::write_array_script(&pbs, &scripts, filename)
+        .unwrap();
+    let got = read_to_string(filename).unwrap();
+    let want = r#"#!/bin/sh
+#PBS -N array_script.pbs
+#PBS -S /bin/bash
+#PBS -j oe
+#PBS -o /tmp/array_script.pbs.out
+#PBS -W umask=022
+#PBS -l walltime=1000:00:00
+#PBS -l ncpus=1
+#PBS -l mem=1gb
+#PBS -q workq
+#PBS -l place=scatter
+#PBS -J 0-1
+
+scripts=(
+"inp/main0.pbs"
+"inp/main1.pbs"
+)
+cd $PBS_O_WORKDIR
+bash "${scripts[$PBS_ARRAY_INDEX]}"
+"#;
+    assert_eq!(got, want);
+
+    // Molpro scripts are submitted from their own directory
+    <Pbs as Queue<Molpro>>::write_array_script(&pbs, &scripts, filename)
+        .unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.contains("#PBS -l mem=8gb\n"));
+    assert!(got.contains("scripts=(\n\"main0.pbs\"\n\"main1.pbs\"\n)\n"));
+    std::fs::remove_file(filename).unwrap();
+
+    let got = <Pbs as Queue<Mopac>>::write_array_script(&pbs, &[], filename);
+    assert!(got.is_err());
+}
@@ -1,15 +1,26 @@
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::Write;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
 
 use crate::program::molpro::Molpro;
 use crate::program::mopac::Mopac;
 use crate::program::Program;
 use crate::queue::Queue;
+use crate::Error;
 
-use super::{SubQueue, Submit};
+use super::{
+    insert_directives, push_modules, status_output, submit_inner, temp_dir,
+    write_script, AdaptiveChunks, ChunkFailure, Module, QueueOptions, SubQueue,
+    Submission, Submit, SubmitError, Throttle,
+};
+
+#[cfg(test)]
+mod tests;
 
 /// Slurm is a type for holding the information for submitting a slurm job.
 /// `filename` is the name of the Slurm submission script
@@ -18,9 +29,71 @@ pub struct Slurm {
     chunk_size: usize,
     job_limit: usize,
     sleep_int: usize,
-    dir: &'static str,
+    dir: String,
     no_del: bool,
     template: Option<String>,
+
+    /// the options shared with the other queue types. defaults to
+    /// [QueueOptions::default]
+    pub options: QueueOptions,
+
+    /// slow down polling when the scheduler is slow to respond. defaults to
+    /// `None`, polling at the usual interval
+    pub throttle: Option<Throttle>,
+
+    /// the maximum number of finished files waiting to be deleted or
+    /// compressed at once. defaults to `None`, meaning no limit
+    pub dump_capacity: Option<usize>,
+
+    /// an existing directory to move the files of successfully-parsed jobs
+    /// into instead of deleting them. defaults to `None`, deleting them
+    pub trash_dir: Option<PathBuf>,
+
+    /// additional `#SBATCH` directives to emit verbatim after the ones already
+    /// in the submit script. defaults to empty
+    pub extra_directives: Vec<String>,
+
+    /// environment modules to load in each submit script, in order, after any
+    /// loaded by the template. defaults to empty
+    pub modules: Vec<Module>,
+
+    /// emit `module purge` before loading `modules`, for a clean environment.
+    /// defaults to false
+    pub purge_modules: bool,
+
+    /// the temporary directory created by [Self::in_temp_dir], which is
+    /// removed along with all of its files when `self` is dropped. defaults to
+    /// `None`, leaving the files in `dir`
+    pub temp_dir: Option<TempDir>,
+
+    /// the number of times to retry a failed submission before giving up with
+    /// a [SubmitError]. defaults to 5
+    pub max_retries: usize,
+
+    /// the number of seconds to wait between submission attempts. defaults
+    /// to `None`, using `sleep_int`
+    pub retry_interval: Option<usize>,
+
+    /// double the wait after each failed submission attempt, starting from
+    /// `retry_interval`. defaults to false, waiting the same time between
+    /// every attempt
+    pub retry_backoff: bool,
+
+    /// the partition to submit to, emitted as `#SBATCH --partition` in the
+    /// default submit scripts. defaults to `None`, using the cluster's
+    /// default partition
+    pub partition: Option<String>,
+
+    /// the walltime limit, in any format accepted by `sbatch --time`, like
+    /// `2-00:00:00`, emitted in the default submit scripts. defaults to
+    /// `None`, using the partition's limit
+    pub time: Option<String>,
+
+    /// the number of CPUs to request for each job, substituted for
+    /// `{{.ncpus}}` in the submit script, as in the `--cpus-per-task`
+    /// directive of the default ones, and used for reporting parallel
+    /// efficiency. defaults to 1
+    pub cpus_per_task: usize,
 }
 
 impl Slurm {
@@ -28,7 +101,7 @@ impl Slurm {
         chunk_size: usize,
         job_limit: usize,
         sleep_int: usize,
-        dir: &'static str,
+        dir: &str,
         no_del: bool,
         template: Option<String>,
     ) -> Self {
@@ -36,92 +109,186 @@ impl Slurm {
             chunk_size,
             job_limit,
             sleep_int,
-            dir,
+            dir: dir.to_owned(),
             no_del,
             template,
+            options: QueueOptions::default(),
+            throttle: None,
+            dump_capacity: None,
+            trash_dir: None,
+            extra_directives: Vec::new(),
+            modules: Vec::new(),
+            purge_modules: false,
+            temp_dir: None,
+            max_retries: 5,
+            retry_interval: None,
+            retry_backoff: false,
+            partition: None,
+            time: None,
+            cpus_per_task: 1,
         }
     }
+
+    /// append the `--partition` and `--time` directives for `self.partition`
+    /// and `self.time` to the directives in `header`
+    fn optional_directives(&self, header: &str) -> String {
+        let mut ret = header.to_owned();
+        if let Some(partition) = &self.partition {
+            ret.push_str(&format!("#SBATCH --partition={partition}\n"));
+        }
+        if let Some(time) = &self.time {
+            ret.push_str(&format!("#SBATCH --time={time}\n"));
+        }
+        ret
+    }
+
+    /// run the whole campaign in a new temporary directory instead of `dir`,
+    /// for transient runs like tests where none of the files are needed
+    /// afterward. the directory is removed when `self` is dropped, including
+    /// while unwinding from a panic, so the job filenames should be built from
+    /// [SubQueue::dir] rather than the original `dir`
+    pub fn in_temp_dir(mut self) -> std::io::Result<Self> {
+        let (dir, tmp) = temp_dir()?;
+        self.dir = dir;
+        self.temp_dir = Some(tmp);
+        Ok(self)
+    }
+}
+
+/// the final component of the path `filename`, for the `{{.basename}}`
+/// substitution in submit scripts
+fn basename(filename: &str) -> &str {
+    Path::new(filename)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(filename)
+}
+
+/// parse the job IDs out of the output of `squeue`, using the `JOBID` and `ST`
+/// columns of its header line and skipping completing jobs, which can get
+/// stuck in the queue after their output has been written. lines too short to
+/// contain both columns are skipped with a warning
+fn parse_squeue(output: &str) -> Result<HashSet<String>, Error> {
+    let mut lines = output.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Ok(HashSet::new());
+    };
+    let header: Vec<_> = header.split_whitespace().collect();
+    let column = |name: &str| {
+        header.iter().position(|&h| h == name).ok_or_else(|| {
+            Error::Scheduler(format!("no {name} column in squeue header"))
+        })
+    };
+    let (id, state) = (column("JOBID")?, column("ST")?);
+    let mut ret = HashSet::new();
+    for line in lines {
+        let fields: Vec<_> = line.split_whitespace().collect();
+        let (Some(job), Some(st)) = (fields.get(id), fields.get(state)) else {
+            log::warn!("skipping malformed squeue line `{line}`");
+            continue;
+        };
+        if *st != "CG" {
+            ret.insert(job.to_string());
+        }
+    }
+    Ok(ret)
 }
 
 impl<P: Program + Clone + Serialize + for<'a> Deserialize<'a>> Submit<P>
     for Slurm
 {
+    /// submit `filename` with `sbatch`, retrying transient failures like
+    /// [super::pbs::Pbs] does with `qsub`
+    fn submit(&self, filename: &str) -> Result<Submission, SubmitError> {
+        let mut cmd = Command::new(<Self as SubQueue<P>>::submit_command(self));
+        let cmd = cmd.arg(filename);
+        let job_id = submit_inner(
+            cmd,
+            filename,
+            self.max_retries,
+            self.retry_interval.unwrap_or(self.sleep_int),
+            self.retry_backoff,
+        )?;
+        Ok(Submission::new(job_id, filename))
+    }
 }
 
 impl Queue<Molpro> for Slurm {
-    fn write_submit_script(&self, infiles: &[String], filename: &str) {
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
         let mut body = self
             .template
             .clone()
             .unwrap_or_else(|| {
                 <Self as Queue<Molpro>>::default_submit_script(self)
             })
-            .replace("{{.filename}}", filename);
+            .replace("{{.basename}}", basename(filename))
+            .replace("{{.filename}}", filename)
+            .replace("{{.ncpus}}", &self.cpus_per_task.to_string());
+        body = insert_directives(&body, "#SBATCH", &self.extra_directives)?;
+        push_modules(&mut body, &self.modules, self.purge_modules)?;
         for f in infiles {
             body.push_str(&format!("/home/qc/bin/molpro2020.sh 1 1 {f}.inp\n"));
         }
-        let mut file = match File::create(filename) {
-            Ok(f) => f,
-            Err(_) => {
-                eprintln!("write_submit_script: failed to create {filename}");
-                std::process::exit(1);
-            }
-        };
-        write!(file, "{body}").unwrap_or_else(|_| {
-            panic!("failed to write molpro input file: {filename}")
-        });
+        write_script(filename, &body, self.options.skip_unchanged_scripts)
     }
 
     fn default_submit_script(&self) -> String {
-        "#!/bin/bash
+        self.optional_directives(
+            "#!/bin/bash
 #SBATCH --job-name={{.filename}}
 #SBATCH --ntasks=1
-#SBATCH --cpus-per-task=1
+#SBATCH --cpus-per-task={{.ncpus}}
 #SBATCH -o {{.filename}}.out
 #SBATCH --no-requeue
 #SBATCH --mem=8gb
-"
-        .to_owned()
+",
+        )
     }
 }
 
 impl Queue<Mopac> for Slurm {
-    fn write_submit_script(&self, infiles: &[String], filename: &str) {
+    fn write_submit_script(
+        &self,
+        infiles: &[String],
+        filename: &str,
+    ) -> Result<(), Error> {
         let mut body = self
             .template
             .clone()
             .unwrap_or_else(|| {
                 <Self as Queue<Mopac>>::default_submit_script(self)
             })
-            .replace("{{.filename}}", filename);
+            .replace("{{.basename}}", basename(filename))
+            .replace("{{.filename}}", filename)
+            .replace("{{.ncpus}}", &self.cpus_per_task.to_string());
+        body = insert_directives(&body, "#SBATCH", &self.extra_directives)?;
+        push_modules(&mut body, &self.modules, self.purge_modules)?;
         for f in infiles {
             body.push_str(&format!(
                 "/home/qc/mopac2016/MOPAC2016.exe {f}.mop\n"
             ));
         }
-        let mut file = match File::create(filename) {
-            Ok(f) => f,
-            Err(_) => {
-                eprintln!("write_submit_script: failed to create {filename}");
-                std::process::exit(1);
-            }
-        };
-        write!(file, "{body}").expect("failed to write params file");
+        write_script(filename, &body, self.options.skip_unchanged_scripts)
     }
 
     fn default_submit_script(&self) -> String {
-        "#!/bin/bash
+        self.optional_directives(
+            "#!/bin/bash
 #SBATCH --job-name=semp
 #SBATCH --ntasks=1
-#SBATCH --cpus-per-task=1
+#SBATCH --cpus-per-task={{.ncpus}}
 #SBATCH -o {{.filename}}.out
 #SBATCH --no-requeue
 #SBATCH --mem=1gb
-export LD_LIBRARY_PATH=/home/qc/mopac2016/
+",
+        ) + "export LD_LIBRARY_PATH=/home/qc/mopac2016/
 echo $SLURM_JOB_ID
 date
 hostname\n"
-            .to_owned()
     }
 }
 
@@ -148,47 +315,78 @@ where
     const SCRIPT_EXT: &'static str = "slurm";
 
     fn dir(&self) -> &str {
-        self.dir
+        &self.dir
     }
 
     /// run `squeue -u $USER`. form of the output is:
     ///
     ///    JOBID PARTITION   NAME     USER ST        TIME  NODES NODELIST(REASON)
     /// 30627992   compute  c3oh-   mdavis  R 46-17:12:23      1 node2
-    fn stat_cmd(&self) -> String {
-        let user = std::env::vars()
-            .find(|x| x.0 == "USER")
-            .expect("couldn't find $USER env var");
-        let status = match std::process::Command::new("squeue")
-            .args(["-u", &user.1])
-            .output()
-        {
-            Ok(status) => status,
-            Err(e) => panic!("failed to run squeue with {e}"),
-        };
-        String::from_utf8(status.stdout)
-            .expect("failed to convert squeue output to String")
-    }
-
-    fn status(&self) -> HashSet<String> {
-        let mut ret = HashSet::new();
-        // wut?
-        let lines = <Slurm as SubQueue<P>>::stat_cmd(self);
-        let lines = lines.lines();
-        for line in lines {
-            if !line.contains("JOBID") {
-                let fields: Vec<_> = line.split_whitespace().collect();
-                assert!(fields.len() == 8);
-                // exclude completing jobs to combat stuck completing bug
-                if fields[4] != "CG" {
-                    ret.insert(fields[0].to_string());
-                }
-            }
-        }
-        ret
+    fn stat_cmd(&self) -> Result<String, Error> {
+        let user = std::env::var("USER").map_err(|_| {
+            Error::Scheduler("couldn't find $USER env var".to_owned())
+        })?;
+        let status = status_output("squeue", &["-u", &user]).map_err(|e| {
+            Error::Scheduler(format!("failed to run squeue with {e}"))
+        })?;
+        String::from_utf8(status).map_err(|_| {
+            Error::Scheduler("failed to convert squeue output to String".into())
+        })
+    }
+
+    fn status(&self) -> Result<HashSet<String>, Error> {
+        parse_squeue(&<Slurm as SubQueue<P>>::stat_cmd(self)?)
     }
 
     fn no_del(&self) -> bool {
         self.no_del
     }
+
+    fn retry_budget(&self) -> Option<usize> {
+        self.options.retry_budget
+    }
+
+    fn compress_on_success(&self) -> bool {
+        self.options.compress_on_success
+    }
+
+    fn submit_limit(&self) -> usize {
+        self.options.submit_limit
+    }
+
+    fn shuffle_seed(&self) -> Option<u64> {
+        self.options.shuffle_seed
+    }
+
+    fn start_grace(&self) -> Option<usize> {
+        self.options.start_grace
+    }
+
+    fn adaptive_chunks(&self) -> Option<AdaptiveChunks> {
+        self.options.adaptive_chunks
+    }
+
+    fn chunk_failure(&self) -> Option<ChunkFailure> {
+        self.options.chunk_failure
+    }
+
+    fn stale_tolerance(&self) -> Option<usize> {
+        self.options.stale_tolerance
+    }
+
+    fn throttle(&self) -> Option<Throttle> {
+        self.throttle
+    }
+
+    fn dump_capacity(&self) -> Option<usize> {
+        self.dump_capacity
+    }
+
+    fn trash_dir(&self) -> Option<PathBuf> {
+        self.trash_dir.clone()
+    }
+
+    fn ncpus(&self) -> usize {
+        self.cpus_per_task
+    }
 }
@@ -0,0 +1,86 @@
+use std::{collections::HashSet, fs::read_to_string};
+
+use crate::program::{molpro::Molpro, mopac::Mopac};
+use crate::queue::{slurm::Slurm, Queue, SubQueue};
+
+use super::parse_squeue;
+
+#[test]
+fn basename() {
+    let slurm = Slurm::new(
+        128,
+        1600,
+        1,
+        "inp",
+        false,
+        Some(
+            "#SBATCH --job-name={{.basename}}\n#SBATCH -o {{.filename}}.out\n"
+                .to_owned(),
+        ),
+    );
+    let filename = "/tmp/slurm_basename.slurm";
+    <Slurm as Queue<Mopac>>::write_submit_script(&slurm, &[], filename)
+        .unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert_eq!(
+        got,
+        "#SBATCH --job-name=slurm_basename.slurm
+#SBATCH -o /tmp/slurm_basename.slurm.out
+"
+    );
+    std::fs::remove_file(filename).unwrap();
+}
+
+#[test]
+fn squeue() {
+    let output = "\
+    JOBID PARTITION     NAME     USER ST       TIME  NODES NODELIST(REASON)
+ 30627992   compute    c3oh-   mdavis  R 46-17:12:23      1 node2
+ 30627993   compute    c3oh-   mdavis CG       0:01      1 node3
+ 30627994   compute    c3oh-   mdavis PD       0:00      1 (Resources)
+";
+    let got = parse_squeue(output).unwrap();
+    let mut got: Vec<_> = got.into_iter().collect();
+    got.sort();
+    assert_eq!(got, vec!["30627992", "30627994"]);
+
+    assert!(parse_squeue("").unwrap().is_empty());
+    assert!(parse_squeue("JOBID NAME\n1 a\n").is_err());
+
+    // a short line is skipped without losing the rest of the jobs
+    let got = parse_squeue("JOBID ST\n1\n2 R\n").unwrap();
+    assert_eq!(got, HashSet::from(["2".to_owned()]));
+}
+
+#[test]
+fn partition_and_time() {
+    let mut slurm = Slurm::new(128, 1600, 1, "inp", false, None);
+    let got = <Slurm as Queue<Mopac>>::default_submit_script(&slurm);
+    assert!(!got.contains("--partition") && !got.contains("--time"));
+
+    slurm.partition = Some("gpu".to_owned());
+    slurm.time = Some("2-00:00:00".to_owned());
+    let got = <Slurm as Queue<Mopac>>::default_submit_script(&slurm);
+    assert!(got.contains(
+        "#SBATCH --mem=1gb\n#SBATCH --partition=gpu\n\
+         #SBATCH --time=2-00:00:00\nexport LD_LIBRARY_PATH"
+    ));
+    let got = <Slurm as Queue<Molpro>>::default_submit_script(&slurm);
+    assert!(
+        got.ends_with("#SBATCH --partition=gpu\n#SBATCH --time=2-00:00:00\n")
+    );
+}
+
+#[test]
+fn cpus_per_task() {
+    let filename = "/tmp/cpus_per_task.slurm";
+    let mut slurm = Slurm::new(128, 1600, 1, "inp", false, None);
+    assert_eq!(<Slurm as SubQueue<Molpro>>::ncpus(&slurm), 1);
+    slurm.cpus_per_task = 4;
+    assert_eq!(<Slurm as SubQueue<Molpro>>::ncpus(&slurm), 4);
+    <Slurm as Queue<Molpro>>::write_submit_script(&slurm, &[], filename)
+        .unwrap();
+    let got = read_to_string(filename).unwrap();
+    assert!(got.contains("#SBATCH --cpus-per-task=4\n"));
+    std::fs::remove_file(filename).unwrap();
+}
@@ -0,0 +1,187 @@
+//! Recording and replaying the scheduler commands run by the queues, like
+//! `qsub` and `qstat`, for reproducing a run's interactions with a particular
+//! cluster offline. A [Recorder] in record mode runs each command and appends
+//! it, along with its output, to a file as one JSON object per line. One in
+//! replay mode reads such a file and returns the recorded outputs of each
+//! command, in the order they were recorded, without running anything. Outputs
+//! are matched by command rather than by position in the file because chunks
+//! are submitted in parallel, so different commands may not run in the same
+//! order on replay
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, Error, ErrorKind, Result, Write},
+    os::unix::process::ExitStatusExt,
+    process::{ExitStatus, Output},
+    sync::{Arc, LazyLock, Mutex, RwLock},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+/// A single scheduler command and its output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// the time the command finished, in seconds since the Unix epoch
+    pub time: f64,
+
+    /// the program and its arguments, separated by spaces
+    pub command: String,
+
+    /// the exit code of the command
+    pub status: i32,
+
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Entry {
+    fn output(&self) -> Output {
+        Output {
+            status: ExitStatus::from_raw(self.status << 8),
+            stdout: self.stdout.clone().into_bytes(),
+            stderr: self.stderr.clone().into_bytes(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Record(File),
+    /// the recorded entries for each command, in the order they ran
+    Replay(HashMap<String, VecDeque<Entry>>),
+}
+
+/// A recording of scheduler commands in progress or being replayed, installed
+/// with [set_global] or [set]
+#[derive(Debug)]
+pub struct Recorder {
+    mode: Mutex<Mode>,
+}
+
+impl Recorder {
+    /// start recording to `path`, replacing any existing file
+    pub fn record(path: &str) -> Result<Self> {
+        Ok(Self {
+            mode: Mutex::new(Mode::Record(File::create(path)?)),
+        })
+    }
+
+    /// load the recording in `path` for replaying
+    pub fn replay(path: &str) -> Result<Self> {
+        let mut entries: HashMap<_, VecDeque<_>> = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            entries
+                .entry(entry.command.clone())
+                .or_default()
+                .push_back(entry);
+        }
+        Ok(Self {
+            mode: Mutex::new(Mode::Replay(entries)),
+        })
+    }
+
+    /// in record mode, call `run` and record its result as the output of
+    /// `command`. in replay mode, return the next recorded output of `command`
+    /// instead. commands are run one at a time while recording so that the
+    /// order in the file is the order they ran
+    fn intercept(
+        &self,
+        command: String,
+        run: impl FnOnce() -> Result<Output>,
+    ) -> Result<Output> {
+        let mut mode = self.mode.lock().unwrap();
+        match &mut *mode {
+            Mode::Record(file) => {
+                let output = run()?;
+                let entry = Entry {
+                    time: SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64(),
+                    command,
+                    status: output.status.code().unwrap_or(-1),
+                    stdout: String::from_utf8_lossy(&output.stdout).into(),
+                    stderr: String::from_utf8_lossy(&output.stderr).into(),
+                };
+                let line = serde_json::to_string(&entry)
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                writeln!(file, "{line}")?;
+                Ok(output)
+            }
+            Mode::Replay(entries) => match entries.get_mut(&command) {
+                Some(outputs) => match outputs.pop_front() {
+                    Some(entry) => Ok(entry.output()),
+                    None => Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("no recorded output left for `{command}`"),
+                    )),
+                },
+                None => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("`{command}` is not in the recording"),
+                )),
+            },
+        }
+    }
+}
+
+static GLOBAL: LazyLock<RwLock<Option<Arc<Recorder>>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+thread_local! {
+    static RECORDER: RefCell<Option<Arc<Recorder>>> =
+        const { RefCell::new(None) };
+}
+
+/// return the [Recorder] in use on the current thread, which is the one
+/// installed with [set] if there is one and otherwise the one installed with
+/// [set_global], if any
+pub fn current() -> Option<Arc<Recorder>> {
+    RECORDER
+        .with(|r| r.borrow().clone())
+        .or_else(|| GLOBAL.read().unwrap().clone())
+}
+
+/// install `recorder` for the current thread, or stop using one on this thread
+/// with `None`, and return the previous one for this thread
+pub fn set(recorder: Option<Arc<Recorder>>) -> Option<Arc<Recorder>> {
+    RECORDER.with(|r| r.replace(recorder))
+}
+
+/// install `recorder` for every thread without one installed by [set], or
+/// stop recording with `None`, and return the previous one. this is how a
+/// whole run should be recorded or replayed
+pub fn set_global(recorder: Option<Arc<Recorder>>) -> Option<Arc<Recorder>> {
+    std::mem::replace(&mut *GLOBAL.write().unwrap(), recorder)
+}
+
+/// run `program` with `args` by calling `run`, through the current [Recorder]
+/// if there is one
+pub(crate) fn intercept(
+    program: &str,
+    args: &[&str],
+    run: impl FnOnce() -> Result<Output>,
+) -> Result<Output> {
+    match current() {
+        Some(recorder) => recorder.intercept(
+            std::iter::once(program)
+                .chain(args.iter().copied())
+                .collect::<Vec<_>>()
+                .join(" "),
+            run,
+        ),
+        None => run(),
+    }
+}
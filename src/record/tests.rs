@@ -0,0 +1,37 @@
+use std::{process::Command, sync::Arc};
+
+use super::{set, Recorder};
+use crate::queue::{command_output, status_output};
+
+#[test]
+fn record_replay() {
+    let path = "/tmp/record_replay.jsonl";
+    let prev = set(Some(Arc::new(Recorder::record(path).unwrap())));
+    let submit =
+        command_output(Command::new("echo").arg("1234.cluster")).unwrap();
+    let status = status_output("printf", &["R 1234"]).unwrap();
+    let failed = command_output(&mut Command::new("false")).unwrap();
+    assert_eq!(submit.stdout, b"1234.cluster\n");
+    assert!(!failed.status.success());
+
+    // outputs are matched by command, so the order of different commands
+    // doesn't have to match the recording
+    set(Some(Arc::new(Recorder::replay(path).unwrap())));
+    let got = command_output(&mut Command::new("false")).unwrap();
+    assert_eq!(got.status.code(), Some(1));
+    assert_eq!(status_output("printf", &["R 1234"]).unwrap(), status);
+    let got = command_output(Command::new("echo").arg("1234.cluster")).unwrap();
+    assert_eq!(got, submit);
+
+    // the recording is exhausted
+    let err = status_output("printf", &["R 1234"]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    // and a command that was not recorded is an error
+    set(Some(Arc::new(Recorder::replay(path).unwrap())));
+    let err = status_output("qstat", &["-u", "user"]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    set(prev);
+    std::fs::remove_file(path).unwrap();
+}
@@ -11,6 +11,12 @@ OH = 1.0
 HOH = 109.5";
     let got = s.parse::<Geom>().unwrap();
     assert_eq!(got, Geom::Zmat(s.to_string()));
+
+    // the same checks as Geom::parse
+    assert!(matches!(
+        "H\nO 1 OH\n".parse::<Geom>(),
+        Err(crate::Error::Parse(_))
+    ));
 }
 
 #[test]
@@ -33,3 +39,599 @@ water geometry
         ])
     );
 }
+
+#[test]
+fn test_parse_xyz() {
+    let water = "3
+water geometry
+ H          0.0000000000        0.7574590974        0.5217905143
+ 8          0.0000000000        0.0000000000       -0.0657441568
+ h          0.0000000000       -0.7574590974        0.5217905143 extra
+";
+    let want = Geom::Xyz(vec![
+        Atom::new(1, 0.0000000000, 0.7574590974, 0.5217905143),
+        Atom::new(8, 0.0000000000, 0.0000000000, -0.0657441568),
+        Atom::new(1, 0.0000000000, -0.7574590974, 0.5217905143),
+    ]);
+    assert_eq!(Geom::parse_xyz(water).unwrap(), want);
+
+    let path = "/tmp/parse_xyz.xyz";
+    std::fs::write(path, water).unwrap();
+    assert_eq!(Geom::from_xyz_file(path).unwrap(), want);
+    std::fs::remove_file(path).unwrap();
+
+    let err = |s: &str| Geom::parse_xyz(s).unwrap_err().to_string();
+    assert!(err("").contains("empty"));
+    assert!(err("three\ncomment\n").contains("invalid atom count `three`"));
+    assert!(err("2\ncomment\nH 0 0 0\n").contains("expected 2 atoms"));
+    assert!(err("1\ncomment\nH 0 x 0\n").contains("coordinate `x` on line 3"));
+    assert!(err("1\ncomment\nZz 0 0 0\n").contains("element `Zz`"));
+    assert!(err("1\ncomment\nH 0 0\n").contains("3 coordinates on line 3"));
+    assert!(Geom::from_xyz_file("/tmp/missing.xyz")
+        .unwrap_err()
+        .to_string()
+        .contains("/tmp/missing.xyz"));
+}
+
+mod geom_string_for {
+    use crate::geom::{geom_string, geom_string_for, GeomFormat};
+
+    use super::*;
+
+    const ZMAT: &str = "H
+O,1,OH
+H 2 OH 1 HOH
+
+OH = 1.0
+HOH = 109.5;
+";
+
+    fn xyz() -> Geom {
+        Geom::Xyz(vec![
+            Atom::new(1, 0.0000000000, 0.7574590974, 0.5217905143),
+            Atom::new(8, 0.0000000000, 0.0000000000, -0.0657441568),
+            Atom::new(1, 0.0000000000, -0.7574590974, 0.5217905143),
+        ])
+    }
+
+    #[test]
+    fn cartesian() {
+        let geom = xyz();
+        let want = geom_string(&geom);
+        for format in
+            [GeomFormat::Molpro, GeomFormat::Mopac, GeomFormat::Gaussian]
+        {
+            assert_eq!(geom_string_for(&geom, format), want);
+        }
+    }
+
+    #[test]
+    fn fixed_width() {
+        let got = geom_string(&xyz());
+        let want = "\
+H    0.000000000000   0.757459097400   0.521790514300
+O    0.000000000000   0.000000000000  -0.065744156800
+H    0.000000000000  -0.757459097400   0.521790514300
+";
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn zmat_passthrough() {
+        let geom = Geom::Zmat(ZMAT.to_string());
+        for format in [GeomFormat::Molpro, GeomFormat::Mopac] {
+            assert_eq!(geom_string_for(&geom, format), ZMAT);
+        }
+    }
+
+    #[test]
+    fn zmat_gaussian() {
+        let geom = Geom::Zmat(ZMAT.to_string());
+        let got = geom_string_for(&geom, GeomFormat::Gaussian);
+        let want = "H
+O 1 OH
+H 2 OH 1 HOH
+
+OH 1.0
+HOH 109.5
+";
+        assert_eq!(got, want);
+    }
+}
+
+mod parse {
+    use crate::geom::geom_string;
+
+    use super::*;
+
+    fn xyz() -> Geom {
+        Geom::Xyz(vec![
+            Atom::new(1, 0.0, 0.757459097400, 0.521790514300),
+            Atom::new(8, 0.0, 0.0, -0.065744156800),
+            Atom::new(1, 0.0, -0.757459097400, 0.521790514300),
+        ])
+    }
+
+    #[test]
+    fn cartesian_round_trip() {
+        let geom = xyz();
+        assert_eq!(Geom::parse(&geom_string(&geom)).unwrap(), geom);
+    }
+
+    #[test]
+    fn xyz_file() {
+        let s = format!("3\nwater\n{}", geom_string(&xyz()));
+        assert_eq!(Geom::parse(&s).unwrap(), xyz());
+    }
+
+    #[test]
+    fn zmat_round_trip() {
+        let zmat = "O
+H 1 OH
+H 1 OH 2 HOH
+
+OH = 0.95
+HOH = 104.5
+";
+        let geom = Geom::Zmat(zmat.to_owned());
+        assert_eq!(Geom::parse(&geom_string(&geom)).unwrap(), geom);
+    }
+
+    #[test]
+    fn errors() {
+        let err = |s: &str| Geom::parse(s).unwrap_err().to_string();
+        assert!(err("\n  \n").contains("empty"));
+        assert!(err("2\nwater\nH 0 0 0\n").contains("expected 2 atoms"));
+        assert!(err("H\nO 1 OH\n").contains("variable `OH`"));
+    }
+}
+
+#[test]
+fn test_tail() {
+    use crate::program::{mopac::Mopac, Job, Program, Template};
+    let job = Job::new(
+        Mopac::new(
+            "testfiles/job".to_owned(),
+            Template::from(""),
+            0,
+            Geom::default(),
+        ),
+        0,
+    );
+    let got = job.tail(3);
+    let want = vec![
+        " TOTAL JOB TIME:             0.04 SECONDS",
+        "",
+        " == MOPAC DONE ==",
+    ];
+    assert_eq!(got, want);
+    assert_eq!(job.program.tail_output(1), &want[2..]);
+
+    let job = Job::new(
+        Mopac::new(
+            "testfiles/missing".to_owned(),
+            Template::from(""),
+            0,
+            Geom::default(),
+        ),
+        0,
+    );
+    assert!(job.tail(3).is_empty());
+    assert!(job.program.tail_output(3).is_empty());
+}
+
+#[test]
+fn test_is_stale() {
+    use crate::program::{mopac::Mopac, Job, Template};
+    use std::time::{Duration, SystemTime};
+    let mut job = Job::new(
+        Mopac::new(
+            "testfiles/job".to_owned(),
+            Template::from(""),
+            0,
+            Geom::default(),
+        ),
+        0,
+    );
+    // never submitted
+    assert!(!job.is_stale(Duration::ZERO));
+
+    job.submitted = Some(SystemTime::now());
+    assert!(job.is_stale(Duration::ZERO));
+    // allow a full year of clock skew
+    assert!(!job.is_stale(Duration::from_secs(365 * 24 * 3600)));
+
+    job.submitted = Some(SystemTime::UNIX_EPOCH);
+    assert!(!job.is_stale(Duration::ZERO));
+
+    // the modification time comes from the current FileSystem
+    use crate::fs::{self, FileSystem, MemFs};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let prev = fs::set(mem.clone());
+    job.submitted = Some(SystemTime::now() + Duration::from_secs(3600));
+    assert!(!job.is_stale(Duration::ZERO));
+    mem.write(&job.program.outfile(), b"").unwrap();
+    assert!(job.is_stale(Duration::ZERO));
+    fs::set(prev);
+}
+
+#[test]
+fn test_missing_outputs() {
+    use crate::fs::{FileSystem, MemFs};
+    use crate::program::{missing_outputs, mopac::Mopac, Job, Template};
+    use std::sync::Arc;
+    let mem = Arc::new(MemFs::new());
+    let prev = crate::fs::set(mem.clone());
+    let jobs: Vec<_> = (0..6)
+        .map(|i| {
+            let prog = Mopac::new(
+                format!("array/job.{i:08}"),
+                Template::from(""),
+                0,
+                Geom::default(),
+            );
+            let mut job = Job::new(prog, i / 2);
+            job.pbs_file = format!("array/main{}.pbs", i / 3);
+            job
+        })
+        .collect();
+    for i in [0, 1, 3] {
+        mem.write(&format!("array/job.{i:08}.out"), b"").unwrap();
+    }
+    mem.write("array/job.00000004.out.gz", b"").unwrap();
+    let got = missing_outputs(&jobs);
+    crate::fs::set(prev);
+    // job 2 is missing from index 1, and job 5 from index 2
+    assert_eq!(
+        got,
+        vec![
+            (1, "array/main0.pbs".to_owned()),
+            (2, "array/main1.pbs".to_owned())
+        ]
+    );
+}
+
+#[test]
+fn test_smoke_test() {
+    use crate::program::{mopac::Mopac, Template};
+    let tmpl = Template::from("scfcrt=1.D-21 aux(precision=14) PM6");
+    let got = tmpl.smoke_test::<Mopac>("false", "/tmp");
+    let Err(e) = got else {
+        panic!("expected an error, got {got:?}");
+    };
+    assert!(e.starts_with("false exited with"));
+    assert!(std::path::Path::new("/tmp/smoke.mop").exists());
+}
+
+#[test]
+fn test_relative_energies() {
+    use crate::program::{mopac::KCALHT, relative_energies, ProgramResult};
+    let results: Vec<_> = [-76.0, -76.5, -75.9]
+        .into_iter()
+        .map(|energy| ProgramResult {
+            energy,
+            ..Default::default()
+        })
+        .collect();
+    let got = relative_energies(&results, 1, false);
+    let want = [0.5, 0.0, 0.6];
+    for (g, w) in got.iter().zip(want) {
+        assert!((g - w).abs() < 1e-12);
+    }
+    let got = relative_energies(&results, 1, true);
+    for (g, w) in got.iter().zip(want) {
+        assert!((g - w * KCALHT).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn test_merge_results() {
+    use crate::program::{merge_results, ProgramError, ProgramResult};
+    let ok = |energy| {
+        Ok(ProgramResult {
+            energy,
+            ..Default::default()
+        })
+    };
+    let err = |f: &str| Err(ProgramError::ErrorInOutput(f.to_owned()));
+    let first = vec![
+        ("job0".to_owned(), ok(-76.0)),
+        ("job1".to_owned(), err("job1")),
+        ("job2".to_owned(), ok(-76.2)),
+    ];
+    let second = vec![
+        ("job1".to_owned(), ok(-76.1)),
+        ("job2".to_owned(), err("job2")),
+        ("job0".to_owned(), ok(-75.0)),
+        ("job3".to_owned(), err("job3")),
+    ];
+    let got = merge_results([first, second], 1e-8);
+    assert_eq!(got.results.len(), 4);
+    assert_eq!(got.results["job0"], ok(-76.0));
+    assert_eq!(got.results["job1"], ok(-76.1));
+    assert_eq!(got.results["job2"], ok(-76.2));
+    assert_eq!(got.results["job3"], err("job3"));
+    assert_eq!(got.conflicts, vec![("job0".to_owned(), -76.0, -75.0)]);
+}
+
+#[test]
+fn test_insert_job_id() {
+    use crate::queue::{insert_job_id, Submission};
+    use std::collections::HashMap;
+    let sub = |id: &str, script| Submission::new(id.to_owned(), script);
+    let mut qstat = HashMap::new();
+    assert!(insert_job_id(&mut qstat, sub("123", "main0.pbs")));
+    assert!(!insert_job_id(&mut qstat, sub("123", "main1.pbs")));
+    // the first script is kept for the colliding id
+    assert_eq!(qstat["123"], "main0.pbs");
+    assert!(insert_job_id(&mut qstat, sub("124", "main1.pbs")));
+    assert!(insert_job_id(&mut qstat, sub("", "main2.pbs")));
+    assert!(insert_job_id(&mut qstat, sub("", "main3.pbs")));
+    assert_eq!(qstat.len(), 3);
+}
+
+#[test]
+fn test_gradient_close() {
+    use crate::util::gradient_close;
+    let a = [0.1, -0.2, 0.3];
+    assert!(gradient_close(&a, &a, 0.0));
+    assert!(gradient_close(&a, &[0.1, -0.2, 0.3005], 1e-3));
+    assert!(!gradient_close(&a, &[0.1, -0.2, 0.302], 1e-3));
+    assert!(!gradient_close(&a, &a[..2], 1e-3));
+}
+
+#[test]
+fn test_shuffle() {
+    use crate::util::shuffle;
+    let orig: Vec<_> = (0..100).collect();
+    let mut a = orig.clone();
+    shuffle(&mut a, 42);
+    assert_ne!(a, orig);
+    let mut b = orig.clone();
+    shuffle(&mut b, 42);
+    assert_eq!(a, b);
+    let mut c = orig.clone();
+    shuffle(&mut c, 43);
+    assert_ne!(a, c);
+    a.sort();
+    assert_eq!(a, orig);
+}
+
+mod rotate {
+    use symm::Atom;
+
+    use crate::geom::Geom;
+
+    #[test]
+    fn xyz() {
+        let mut geom = Geom::Xyz(vec![
+            Atom::new(8, 1.0, 0.0, 0.0),
+            Atom::new(1, 0.0, 1.0, 2.0),
+        ]);
+        // 90 degrees about z
+        geom.rotate([[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        let want = [[0.0, 1.0, 0.0], [-1.0, 0.0, 2.0]];
+        for (atom, w) in geom.xyz().unwrap().iter().zip(want) {
+            assert!((atom.x - w[0]).abs() < 1e-12);
+            assert!((atom.y - w[1]).abs() < 1e-12);
+            assert!((atom.z - w[2]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn zmat() {
+        let mut geom = Geom::Zmat("H\nH 1 R\n\nR = 0.74\n".to_owned());
+        let want = geom.clone();
+        geom.rotate([[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(geom, want);
+    }
+
+    #[test]
+    #[should_panic]
+    fn not_orthonormal() {
+        let mut geom = Geom::Xyz(vec![Atom::new(1, 1.0, 0.0, 0.0)]);
+        geom.rotate([[2.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+    }
+}
+
+mod to_cartesian {
+    use symm::Atom;
+
+    use crate::geom::Geom;
+
+    fn check(got: &Geom, want: &[(usize, [f64; 3])]) {
+        let got = got.xyz().unwrap();
+        assert_eq!(got.len(), want.len());
+        for (atom, (number, w)) in got.iter().zip(want) {
+            let want = Atom::new(*number, w[0], w[1], w[2]);
+            assert_eq!(atom.label(), want.label());
+            assert!((atom.x - w[0]).abs() < 1e-8, "{atom:?} != {w:?}");
+            assert!((atom.y - w[1]).abs() < 1e-8, "{atom:?} != {w:?}");
+            assert!((atom.z - w[2]).abs() < 1e-8, "{atom:?} != {w:?}");
+        }
+    }
+
+    #[test]
+    fn water() {
+        let geom = Geom::Zmat(
+            "H
+O 1 OH
+H 2 OH 1 HOH
+
+OH = 1.0
+HOH = 109.5"
+                .to_owned(),
+        );
+        let want = [
+            (1, [0.0, 0.0, 0.0]),
+            (8, [0.0, 0.0, 1.0]),
+            (1, [0.9426414910921783, 0.0, 1.333806859233771]),
+        ];
+        check(&geom.to_cartesian().unwrap(), &want);
+    }
+
+    #[test]
+    fn dihedral() {
+        // commas, labels as references, and a literal value
+        let geom = Geom::Zmat(
+            "H1
+O2,H1,0.96
+O3,O2,1.45,H1,A
+H4,3,0.96,2,A,1,-D
+
+A = 105.0
+D = -120.0"
+                .to_owned(),
+        );
+        let want = [
+            (1, [0.0, 0.0, 0.0]),
+            (8, [0.0, 0.0, 0.96]),
+            (8, [1.4005924481, 0.0, 1.3352876154]),
+            (1, [1.5205924481, 0.8030556516, 1.8474415185]),
+        ];
+        check(&geom.to_cartesian().unwrap(), &want);
+    }
+
+    #[test]
+    fn linear() {
+        // the dihedral doesn't matter for a linear angle, and the dummy atom
+        // is dropped
+        let geom = Geom::Zmat(
+            "C
+C 1 1.2
+X 2 1.0 1 90.0
+H 2 1.1 1 180.0 3 0.0
+H 1 1.1 2 180.0 3 0.0"
+                .to_owned(),
+        );
+        let want = [
+            (6, [0.0, 0.0, 0.0]),
+            (6, [0.0, 0.0, 1.2]),
+            (1, [0.0, 0.0, 2.3]),
+            (1, [0.0, 0.0, -1.1]),
+        ];
+        check(&geom.to_cartesian().unwrap(), &want);
+
+        // but it is undefined for a bent one
+        let geom = Geom::Zmat(
+            "C\nC 1 1.2\nC 2 1.2 1 180.0\nH 3 1.1 2 90.0 1 0.0\n".to_owned(),
+        );
+        let err = geom.to_cartesian().unwrap_err().to_string();
+        assert!(err.contains("collinear"), "{err}");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |s: &str| {
+            Geom::Zmat(s.to_owned())
+                .to_cartesian()
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err("H\nH 1 R\n").contains("undefined Z-matrix variable `R`"));
+        assert!(err("H\nH 2 1.0\n").contains("invalid reference `2`"));
+        assert!(err("H\nH 1\n").contains("expected 3 fields"));
+        assert!(err("Zz\n").contains("invalid element `Zz`"));
+        assert!(err("H\nH 1 R\n\nR = x\n").contains("invalid value `x`"));
+    }
+
+    #[test]
+    fn xyz() {
+        let geom = Geom::Xyz(vec![Atom::new(1, 1.0, 2.0, 3.0)]);
+        assert_eq!(geom.to_cartesian().unwrap(), geom);
+    }
+}
+
+#[test]
+fn test_eta() {
+    use crate::queue::Eta;
+    use std::time::{Duration, Instant};
+    let mut eta = Eta::new(3);
+    assert!(eta.eta().is_none());
+    let t0 = Instant::now();
+    eta.record_at(t0, 0, 100);
+    assert!(eta.eta().is_none());
+    // 10 jobs per second
+    eta.record_at(t0 + Duration::from_secs(1), 10, 90);
+    let got = eta.eta().unwrap().as_secs_f64();
+    assert!((got - 9.0).abs() < 1e-6);
+    // the window drops the first sample, leaving 20 jobs in 3 seconds
+    eta.record_at(t0 + Duration::from_secs(2), 20, 80);
+    eta.record_at(t0 + Duration::from_secs(4), 30, 70);
+    let got = eta.eta().unwrap().as_secs_f64();
+    assert!((got - 10.5).abs() < 1e-6);
+}
+
+#[test]
+fn test_normalize_elements() {
+    let mut geom = Geom::Zmat(
+        "CL
+h1,cl,r
+x,CL,1.0,h1,90.0
+
+r=1.27
+"
+        .to_owned(),
+    );
+    geom.normalize_elements().unwrap();
+    assert_eq!(
+        geom,
+        Geom::Zmat(
+            "Cl
+H1,cl,r
+X,CL,1.0,h1,90.0
+
+r=1.27
+"
+            .to_owned()
+        )
+    );
+
+    let mut geom = Geom::Zmat("O\nEx,O,r\nr=1.0\n".to_owned());
+    assert_eq!(
+        geom.normalize_elements(),
+        Err("invalid element `Ex` for atom 1".to_owned())
+    );
+
+    let mut geom = Geom::Xyz(vec![Atom::new(8, 0.0, 0.0, 0.0)]);
+    assert!(geom.normalize_elements().is_ok());
+}
+
+#[test]
+fn test_stationary_point() {
+    use crate::program::{ProgramResult, StationaryPoint};
+    let mut res = ProgramResult::default();
+    assert_eq!(res.stationary_point(10.0), None);
+    res.frequencies = Some(vec![-3.2, 1600.0, 3700.0, 3800.0]);
+    assert_eq!(res.stationary_point(10.0), Some(StationaryPoint::Minimum));
+    res.frequencies = Some(vec![-512.3, -3.2, 1600.0, 3700.0]);
+    assert_eq!(
+        res.stationary_point(10.0),
+        Some(StationaryPoint::TransitionState)
+    );
+    assert_eq!(res.imaginary_frequencies(10.0), vec![-512.3]);
+    assert_eq!(res.stationary_point(1.0), Some(StationaryPoint::Saddle(2)));
+}
+
+#[test]
+fn test_rising_after_minimum() {
+    use crate::program::ProgramResult;
+    use crate::queue::rising_after_minimum;
+    let res = |energy| ProgramResult {
+        energy,
+        ..Default::default()
+    };
+    let mut stop = rising_after_minimum(1e-3);
+    assert!(!stop(0, &res(-1.0)));
+    // job 2 finishing early doesn't count until job 1 does
+    assert!(!stop(2, &res(-1.0)));
+    assert!(stop(1, &res(-1.5)));
+
+    let mut stop = rising_after_minimum(1e-3);
+    assert!(!stop(0, &res(-1.0)));
+    assert!(!stop(1, &res(-1.5)));
+    // rising by less than the threshold
+    assert!(!stop(2, &res(-1.4995)));
+    assert!(stop(3, &res(-1.4)));
+}
@@ -0,0 +1,24 @@
+/// report whether the gradients `a` and `b` agree to within `tol` in the
+/// infinity norm, meaning that no pair of corresponding elements differs by
+/// more than `tol`. gradients of different lengths are never close
+pub fn gradient_close(a: &[f64], b: &[f64], tol: f64) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() <= tol)
+}
+
+/// shuffle `v` in place with a Fisher-Yates shuffle driven by a SplitMix64
+/// generator seeded with `seed`. this is implemented here rather than using an
+/// external RNG so that the same seed gives the same order across versions
+pub fn shuffle<T>(v: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..v.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        v.swap(i, j);
+    }
+}